@@ -0,0 +1,128 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use axum::{
+    body::HttpBody,
+    extract::{ConnectInfo, Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::AppState;
+
+/// The `--access-log` file: one JSON line per HTTP request, apart from the tracing output.
+pub struct AccessLog {
+    file: Mutex<File>,
+}
+
+/// The JSON-RPC method a response answered, attached by the POST handler for the access log.
+#[derive(Clone)]
+pub struct RpcMethod(pub String);
+
+impl AccessLog {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open access log {}", path.display()))?;
+        info!("Writing the access log to {}", path.display());
+        Ok(AccessLog { file: Mutex::new(file) })
+    }
+
+    /// Appends one entry. Each line is a single unbuffered write, so it's on disk (and
+    /// visible to log shippers) as soon as the request completes.
+    fn write(&self, entry: &Value) {
+        let mut line = entry.to_string();
+        line.push('\n');
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            warn!("Failed to write the access log: {}", e);
+        }
+    }
+}
+
+/// Labels a POST body for the access log: its method, `batch` for batches, `-` otherwise.
+pub fn rpc_method_label(body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(Value::Array(_)) => "batch".to_string(),
+        Ok(request) => request.get("method").and_then(Value::as_str).unwrap_or("-").to_string(),
+        Err(_) => "-".to_string(),
+    }
+}
+
+pub async fn access_log_middleware(
+    State(state): State<AppState>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(access_log) = state.access_log.clone() else {
+        return next.run(request).await;
+    };
+
+    let started = Instant::now();
+    let time = format_timestamp(SystemTime::now());
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+
+    let response = next.run(request).await;
+
+    let bytes = response.body().size_hint().exact().or_else(|| {
+        response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse().ok())
+    });
+    let rpc_method = response.extensions().get::<RpcMethod>().map(|rpc_method| rpc_method.0.clone());
+
+    access_log.write(&json!({
+        "time": time,
+        "remote_addr": remote_addr.to_string(),
+        "method": method,
+        "path": path,
+        "rpc_method": rpc_method,
+        "status": response.status().as_u16(),
+        "bytes": bytes,
+        "duration_ms": started.elapsed().as_micros() as f64 / 1000.0,
+    }));
+
+    response
+}
+
+/// Formats a time as an RFC 3339 UTC timestamp with milliseconds.
+fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (hour, minute, second) = (seconds / 3600 % 24, seconds / 60 % 60, seconds % 60);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let days = (seconds / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        since_epoch.subsec_millis()
+    )
+}
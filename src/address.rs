@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde_json::{json, Map, Value};
+use tracing::{debug, info, warn};
+
+/// Maximum number of inputs remembered by `AddressCache` before it starts over
+const ADDRESS_CACHE_CAPACITY: usize = 4096;
+
+pub fn convert_eth_to_tron_address(eth_address: &str) -> Option<String> {
+    // Remove 0x prefix if present
+    let address_hex = eth_address.strip_prefix("0x").unwrap_or(eth_address);
+
+    // Ethereum addresses should be 40 hex characters (20 bytes)
+    if address_hex.len() != 40 {
+        warn!("Invalid Ethereum address length: {} (expected 40 hex chars, got {})", eth_address, address_hex.len());
+        return None;
+    }
+
+    // Validate that it's all hex characters
+    if !address_hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        warn!("Invalid Ethereum address format (non-hex characters): {}", eth_address);
+        return None;
+    }
+
+    // Convert to Tron format by adding 0x41 prefix
+    let tron_address = format!("0x41{}", address_hex);
+
+    debug!("Converted Ethereum address {} to Tron address {}", eth_address, tron_address);
+    Some(tron_address)
+}
+
+/// Reduces an address in EVM (`0x` + 40 hex) or TRON hex (`0x41` + 40 hex) form to
+/// lowercase `0x` + 40 hex, so both spellings compare equal.
+pub fn canonical_evm_address(address: &str) -> Option<String> {
+    let address_hex = address.strip_prefix("0x").unwrap_or(address);
+    let address_hex = if address_hex.len() == 42 && address_hex.starts_with("41") {
+        &address_hex[2..]
+    } else {
+        address_hex
+    };
+
+    if address_hex.len() != 40 || !address_hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Some(format!("0x{}", address_hex.to_ascii_lowercase()))
+}
+
+/// Reduces an address in any form clients use (EVM hex, TRON hex with or without `0x`, or
+/// TRON base58check such as `T...`) to lowercase `0x` + 40 hex.
+pub fn parse_address(address: &str) -> Option<String> {
+    if let Some(evm_address) = canonical_evm_address(address) {
+        return Some(evm_address);
+    }
+
+    // Base58check payloads are the 0x41 prefix followed by the 20 address bytes
+    let payload = bs58::decode(address).with_check(None).into_vec().ok()?;
+    match payload.as_slice() {
+        [0x41, address_bytes @ ..] if address_bytes.len() == 20 => Some(format!("0x{}", hex::encode(address_bytes))),
+        _ => None,
+    }
+}
+
+/// Encodes a lowercase `0x` + 40 hex EVM address as a TRON base58check address (`T...`).
+pub fn to_base58_address(evm_address: &str) -> Option<String> {
+    let address_bytes = hex::decode(evm_address.strip_prefix("0x")?).ok()?;
+    let mut payload = vec![0x41];
+    payload.extend_from_slice(&address_bytes);
+    Some(bs58::encode(payload).with_check().into_string())
+}
+
+/// Rewrites every string in `value` that is exactly an address, in any form `parse_address`
+/// accepts, to lowercase EVM hex, or to base58 with `base58`. Meant for log output only.
+pub fn normalize_address_strings(value: &mut Value, base58: bool) {
+    match value {
+        Value::String(text) => {
+            // Addresses are 34 (base58) to 44 (0x41-prefixed hex) characters long
+            if !(34..=44).contains(&text.len()) {
+                return;
+            }
+            let normalized = parse_address(text).and_then(|address| if base58 { to_base58_address(&address) } else { Some(address) });
+            if let Some(normalized) = normalized {
+                *text = normalized;
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(|value| normalize_address_strings(value, base58)),
+        Value::Object(members) => members.values_mut().for_each(|value| normalize_address_strings(value, base58)),
+        _ => {}
+    }
+}
+
+/// Remembers the result of `parse_address` for recently seen inputs, including inputs that
+/// aren't addresses, so scripts that send the same addresses over and over skip the
+/// base58 checksum work.
+#[derive(Default)]
+pub struct AddressCache {
+    entries: Mutex<HashMap<String, Option<String>>>,
+}
+
+/// Like `parse_address`, but consults and fills `cache`.
+pub fn normalize_address(cache: &AddressCache, address: &str) -> Option<String> {
+    if let Some(normalized) = cache.entries.lock().unwrap().get(address) {
+        return normalized.clone();
+    }
+
+    let normalized = parse_address(address);
+
+    let mut entries = cache.entries.lock().unwrap();
+    if entries.len() >= ADDRESS_CACHE_CAPACITY {
+        debug!("Address cache is full, clearing {} entries", entries.len());
+        entries.clear();
+    }
+    entries.insert(address.to_string(), normalized.clone());
+
+    normalized
+}
+
+/// Converts a TRON hex address (`0x41` + 40 hex) to a 20-byte EVM address.
+/// Anything else, including EVM addresses and arbitrary hex, yields `None`.
+pub fn tron_hex_to_evm_address(address: &str) -> Option<String> {
+    let address_hex = address.strip_prefix("0x")?;
+    if address_hex.len() != 42 || !address_hex.starts_with("41") {
+        return None;
+    }
+
+    canonical_evm_address(address)
+}
+
+/// Rewrites the named address fields of a response object from TRON hex to EVM form.
+/// Returns whether any field was changed.
+pub fn repair_address_fields(obj: &mut Map<String, Value>, fields: &[&str]) -> bool {
+    let mut modified = false;
+
+    for field in fields {
+        let Some(evm_address) = obj.get(*field).and_then(Value::as_str).and_then(tron_hex_to_evm_address) else {
+            continue;
+        };
+
+        info!("Converted TRON address in '{}' field to {}", field, evm_address);
+        obj.insert(field.to_string(), json!(evm_address));
+        modified = true;
+    }
+
+    modified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The USDT contract on mainnet, in each spelling
+    const BASE58: &str = "TR7NHqjeKQxGTCi8q8ZY4pL8otSzgjLj6t";
+    const TRON_HEX: &str = "0x41a614f803b6fd780986a42c78ec9c7f77e6ded13c";
+    const EVM: &str = "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c";
+
+    #[test]
+    fn every_spelling_parses_to_evm_hex() {
+        for address in [BASE58, TRON_HEX, &TRON_HEX[2..], EVM, &EVM[2..], "0xA614F803B6FD780986A42C78EC9C7F77E6DED13C"] {
+            assert_eq!(parse_address(address).as_deref(), Some(EVM), "{}", address);
+        }
+    }
+
+    #[test]
+    fn addresses_round_trip_between_spellings() {
+        assert_eq!(to_base58_address(EVM).as_deref(), Some(BASE58));
+        assert_eq!(parse_address(&to_base58_address(EVM).unwrap()).as_deref(), Some(EVM));
+        assert_eq!(convert_eth_to_tron_address(EVM).as_deref(), Some(TRON_HEX));
+        assert_eq!(tron_hex_to_evm_address(&convert_eth_to_tron_address(EVM).unwrap()).as_deref(), Some(EVM));
+    }
+
+    #[test]
+    fn non_addresses_are_rejected() {
+        // Bad checksum, wrong length, not hex, and a 21-byte value without the 0x41 prefix
+        for address in ["TR7NHqjeKQxGTCi8q8ZY4pL8otSzgjLj6u", "0xa614f803b6fd", "0xzz14f803b6fd780986a42c78ec9c7f77e6ded13c", "0x42a614f803b6fd780986a42c78ec9c7f77e6ded13c"] {
+            assert_eq!(parse_address(address), None, "{}", address);
+        }
+        assert_eq!(tron_hex_to_evm_address(EVM), None);
+    }
+
+    #[test]
+    fn receipt_contract_address_is_repaired() {
+        let mut receipt = json!({ "contractAddress": TRON_HEX, "to": null, "transactionHash": format!("0x41{}", "ab".repeat(31)) });
+        let receipt = receipt.as_object_mut().unwrap();
+
+        assert!(repair_address_fields(receipt, &["contractAddress", "from", "to"]));
+        assert_eq!(receipt["contractAddress"], EVM);
+        assert_eq!(receipt["to"], Value::Null);
+        assert_eq!(receipt["transactionHash"], format!("0x41{}", "ab".repeat(31)));
+    }
+
+    #[test]
+    fn transaction_from_is_repaired_and_other_fields_are_not() {
+        // `input` holds 21 bytes that look like a TRON address, but isn't an address field
+        let mut tx = json!({ "from": TRON_HEX, "to": EVM, "input": TRON_HEX });
+        let tx = tx.as_object_mut().unwrap();
+
+        assert!(repair_address_fields(tx, &["from", "to"]));
+        assert_eq!(tx["from"], EVM);
+        assert_eq!(tx["to"], EVM);
+        assert_eq!(tx["input"], TRON_HEX);
+        assert!(!repair_address_fields(tx, &["from", "to"]));
+    }
+
+    #[test]
+    fn cache_reuses_hits_and_misses() {
+        let cache = AddressCache::default();
+        for _ in 0..10_000 {
+            assert_eq!(normalize_address(&cache, BASE58).as_deref(), Some(EVM));
+            assert_eq!(normalize_address(&cache, "not-an-address"), None);
+        }
+        assert_eq!(cache.entries.lock().unwrap().len(), 2);
+
+        // Answers come from the cache, not from parsing the input again
+        cache.entries.lock().unwrap().insert(BASE58.to_string(), Some(TRON_HEX.to_string()));
+        cache.entries.lock().unwrap().insert("not-an-address".to_string(), Some(EVM.to_string()));
+        assert_eq!(normalize_address(&cache, BASE58).as_deref(), Some(TRON_HEX));
+        assert_eq!(normalize_address(&cache, "not-an-address").as_deref(), Some(EVM));
+    }
+
+    #[test]
+    fn cache_starts_over_when_full() {
+        let cache = AddressCache::default();
+        for n in 0..ADDRESS_CACHE_CAPACITY {
+            normalize_address(&cache, &format!("0x{:040x}", n));
+        }
+        assert_eq!(cache.entries.lock().unwrap().len(), ADDRESS_CACHE_CAPACITY);
+
+        assert_eq!(normalize_address(&cache, BASE58).as_deref(), Some(EVM));
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, Method},
+    response::Response,
+};
+use futures::future::{self, BoxFuture};
+use futures::FutureExt;
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+use tracing::{debug, info, warn};
+
+use crate::error::ProxyError;
+use crate::{
+    empty_response, handle_post_text, loggable_body, send_upstream, AppState, JsonRpcRequest, JsonRpcResponse,
+    UpstreamResponse, JSON_CONTENT_TYPE,
+};
+
+tokio::task_local! {
+    /// The batch entry being handled, while its request is answered
+    static BATCH_ENTRY: Arc<BatchEntry>;
+}
+
+/// Parses a request body that is a top-level JSON array.
+pub fn parse_batch(body: &str) -> Option<Vec<Value>> {
+    if !body.trim_start().starts_with('[') {
+        return None;
+    }
+    serde_json::from_str(body).ok()
+}
+
+/// Answers a JSON-RPC batch. Each entry goes through the same handling as a lone request,
+/// so every rewrite, local answer and enhancement applies, and the responses are returned
+/// in request order. The translated requests of the entries that are forwarded go to the
+/// upstream as one batch, and each gets its response back by id. Boxed, since the entries
+/// are dispatched back through `handle_post_text`.
+///
+/// Entries are routed one by one, so a batch mixing methods that `--route` sends to
+/// different upstreams is split between them rather than rejected; the client still gets a
+/// single response array.
+pub fn handle_batch(
+    state: AppState,
+    remote_addr: SocketAddr,
+    headers: HeaderMap,
+    entries: Vec<Value>,
+) -> BoxFuture<'static, Result<Response<String>, ProxyError>> {
+    async move {
+        if entries.is_empty() {
+            return JsonRpcResponse::failure(Some(Value::Null), -32600, "empty batch").into_http();
+        }
+
+        if entries.len() > state.max_batch_size {
+            warn!("Rejected batch of {} requests (limit {})", entries.len(), state.max_batch_size);
+            let message = format!("batch of {} requests exceeds the limit of {}", entries.len(), state.max_batch_size);
+            return JsonRpcResponse::failure(Some(Value::Null), -32600, message).into_http();
+        }
+
+        info!("Received batch of {} requests", entries.len());
+
+        // Every entry runs at once, so each has either been answered or has its upstream
+        // request queued by the time the upstream batch goes out
+        let upstream_batch = Arc::new(UpstreamBatch::new(entries.len()));
+        let responses: Vec<Value> = future::join_all(
+            entries
+                .into_iter()
+                .map(|entry| handle_entry(&state, remote_addr, &headers, &upstream_batch, entry)),
+        )
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+
+        // A batch of only notifications gets no response at all
+        if responses.is_empty() {
+            return Ok(empty_response());
+        }
+
+        let response_body = serde_json::to_string(&responses).map_err(ProxyError::translation)?;
+        debug!("Batch response body: {}", loggable_body(&state, &response_body));
+
+        Response::builder()
+            .status(200)
+            .header(header::CONTENT_TYPE, JSON_CONTENT_TYPE)
+            .body(response_body)
+            .map_err(ProxyError::translation)
+    }
+    .boxed()
+}
+
+/// Answers one batch entry. Notifications are handled but leave no entry in the response.
+async fn handle_entry(
+    state: &AppState,
+    remote_addr: SocketAddr,
+    headers: &HeaderMap,
+    upstream_batch: &Arc<UpstreamBatch>,
+    entry: Value,
+) -> Option<Value> {
+    let id = entry.get("id").cloned().unwrap_or(Value::Null);
+    let batch_entry = Arc::new(BatchEntry { batch: upstream_batch.clone(), settled: AtomicBool::new(false) });
+
+    // Entries that aren't requests are answered here instead of being forwarded as-is
+    if serde_json::from_value::<JsonRpcRequest>(entry.clone()).is_err() {
+        batch_entry.settle().await;
+        return Some(json!({
+            "jsonrpc": "2.0",
+            "error": { "code": -32600, "message": "invalid request" },
+            "id": id,
+        }));
+    }
+
+    let notification = entry.get("id").is_none();
+    let response = BATCH_ENTRY
+        .scope(
+            batch_entry.clone(),
+            handle_post_text(State(state.clone()), ConnectInfo(remote_addr), headers.clone(), entry.to_string()),
+        )
+        .await;
+    // Answered without the upstream batch, which may have been waiting on this entry
+    batch_entry.settle().await;
+    if notification {
+        return None;
+    }
+
+    let response = match response {
+        Ok(response) => match serde_json::from_str::<Value>(response.body()) {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("Batch entry got a response that isn't JSON: {}", e);
+                json!({
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32603, "message": format!("invalid upstream response: {}", e) },
+                    "id": id,
+                })
+            }
+        },
+        Err(e) => {
+            let mut response = e.to_json();
+            response["id"] = id;
+            response
+        }
+    };
+    Some(response)
+}
+
+/// The batch entry whose request is being answered, if any.
+pub fn current_entry() -> Option<Arc<BatchEntry>> {
+    BATCH_ENTRY.try_with(Arc::clone).ok()
+}
+
+/// Takes the batch entry being handled, if any, out of the upstream batch, for a request
+/// that goes upstream on its own.
+pub async fn leave_upstream_batch() {
+    if let Some(entry) = current_entry() {
+        entry.settle().await;
+    }
+}
+
+/// The upstream requests of a client batch's entries, gathered until every entry has either
+/// queued its request or been answered without one, then sent as one batch per destination.
+struct UpstreamBatch {
+    gathering: Mutex<Gathering>,
+}
+
+struct Gathering {
+    /// Entries that have neither queued a request nor finished
+    unsettled: usize,
+    queued: Vec<QueuedRequest>,
+}
+
+/// A translated request waiting for the upstream batch, with its id replaced by its
+/// position in the queue.
+struct QueuedRequest {
+    state: AppState,
+    headers: HeaderMap,
+    request: Value,
+    id: Value,
+    timeout: Duration,
+    reply: oneshot::Sender<Result<UpstreamResponse, ProxyError>>,
+}
+
+/// A client batch entry's place in the upstream batch.
+pub struct BatchEntry {
+    batch: Arc<UpstreamBatch>,
+    /// Whether the entry has queued its request, or has been answered without one
+    settled: AtomicBool,
+}
+
+impl BatchEntry {
+    /// Sends the entry's request upstream as part of the batch and waits for its response.
+    /// Requests made after the first, or that aren't JSON-RPC requests, go on their own.
+    pub async fn forward(
+        &self,
+        state: &AppState,
+        headers: &HeaderMap,
+        body: &str,
+        timeout: Duration,
+    ) -> Result<UpstreamResponse, ProxyError> {
+        let request = serde_json::from_str::<Value>(body).ok().filter(Value::is_object);
+        let Some(mut request) = request.filter(|_| !self.settled.swap(true, Ordering::SeqCst)) else {
+            self.settle().await;
+            return send_upstream(state, Method::POST, headers, body, timeout).await;
+        };
+
+        let (reply, response) = oneshot::channel();
+        let ready = {
+            let mut gathering = self.batch.gathering.lock().unwrap();
+            let id = std::mem::replace(&mut request["id"], json!(gathering.queued.len()));
+            gathering.queued.push(QueuedRequest { state: state.clone(), headers: headers.clone(), request, id, timeout, reply });
+            gathering.unsettled -= 1;
+            gathering.take_ready()
+        };
+        if let Some(queued) = ready {
+            send_batches(queued).await;
+        }
+
+        response
+            .await
+            .unwrap_or_else(|_| Err(ProxyError::translation("the upstream batch was abandoned")))
+    }
+
+    /// Marks the entry as not waiting on the upstream batch, sending the batch if it was the
+    /// last one it waited for.
+    async fn settle(&self) {
+        if self.settled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let ready = {
+            let mut gathering = self.batch.gathering.lock().unwrap();
+            gathering.unsettled -= 1;
+            gathering.take_ready()
+        };
+        if let Some(queued) = ready {
+            send_batches(queued).await;
+        }
+    }
+}
+
+impl UpstreamBatch {
+    fn new(entries: usize) -> Self {
+        UpstreamBatch { gathering: Mutex::new(Gathering { unsettled: entries, queued: Vec::new() }) }
+    }
+}
+
+impl Gathering {
+    /// The queued requests, once no entry can add to them.
+    fn take_ready(&mut self) -> Option<Vec<QueuedRequest>> {
+        (self.unsettled == 0 && !self.queued.is_empty()).then(|| std::mem::take(&mut self.queued))
+    }
+}
+
+/// Sends the queued requests, one batch per destination, and hands each its response.
+async fn send_batches(queued: Vec<QueuedRequest>) {
+    let mut by_destination: HashMap<String, Vec<QueuedRequest>> = HashMap::new();
+    for request in queued {
+        by_destination.entry(request.state.destination.to_string()).or_default().push(request);
+    }
+    future::join_all(by_destination.into_values().map(send_batch)).await;
+}
+
+async fn send_batch(mut queued: Vec<QueuedRequest>) {
+    if queued.len() == 1 {
+        let QueuedRequest { state, headers, mut request, id, timeout, reply } = queued.remove(0);
+        request["id"] = id;
+        let _ = reply.send(send_upstream(&state, Method::POST, &headers, &request.to_string(), timeout).await);
+        return;
+    }
+
+    let (state, headers) = (queued[0].state.clone(), queued[0].headers.clone());
+    let timeout = queued.iter().map(|request| request.timeout).max().unwrap_or_default();
+    let body = Value::Array(queued.iter().map(|request| request.request.clone()).collect()).to_string();
+    info!("Sending {} batch entries upstream as one batch", queued.len());
+
+    let upstream_response = match send_upstream(&state, Method::POST, &headers, &body, timeout).await {
+        Ok(upstream_response) => upstream_response,
+        Err(e) => {
+            for request in queued {
+                let _ = request.reply.send(Err(e.clone()));
+            }
+            return;
+        }
+    };
+
+    let Ok(Value::Array(responses)) = serde_json::from_str::<Value>(&upstream_response.body) else {
+        warn!("Upstream answered a batch with something other than an array, sending the entries one by one");
+        future::join_all(queued.into_iter().map(|request| send_batch(vec![request]))).await;
+        return;
+    };
+
+    let mut responses: HashMap<u64, Value> = responses
+        .into_iter()
+        .filter_map(|response| Some((response.get("id")?.as_u64()?, response)))
+        .collect();
+    // The headers describe the whole batch, not an entry's part of it
+    let mut response_headers = upstream_response.headers;
+    response_headers.remove(reqwest::header::CONTENT_LENGTH);
+
+    for request in queued {
+        let response = match request.request["id"].as_u64().and_then(|id| responses.remove(&id)) {
+            Some(mut response) => {
+                response["id"] = request.id;
+                response
+            }
+            None => {
+                warn!("Upstream batch response has no entry for {}", request.request["method"]);
+                json!(JsonRpcResponse::failure(Some(request.id), -32603, "upstream batch response has no entry for this request"))
+            }
+        };
+        let _ = request.reply.send(Ok(UpstreamResponse {
+            status: upstream_response.status,
+            headers: response_headers.clone(),
+            body: response.to_string(),
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::test_support::{MockReply, MockUpstream, TestProxy};
+
+    /// A node answering batches in reverse order, each entry with its method and params.
+    async fn reversing_upstream() -> MockUpstream {
+        MockUpstream::start(|hit| {
+            let answer = |request: &Value| json!({ "jsonrpc": "2.0", "id": request["id"], "result": [request["method"], request["params"]] });
+            match hit.json() {
+                Value::Array(requests) => MockReply::json(requests.iter().rev().map(answer).collect()),
+                request => MockReply::json(answer(&request)),
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn forwarded_entries_go_upstream_as_one_batch() {
+        let upstream = reversing_upstream().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": "a", "method": "eth_getCode", "params": ["0x0000000000000000000000000000000000000001", "latest"] },
+            { "jsonrpc": "2.0", "id": 7, "method": "eth_blockNumber", "params": [] },
+            { "jsonrpc": "2.0", "id": "a", "method": "eth_getStorageAt", "params": ["0x0000000000000000000000000000000000000001", "0x0", "latest"] },
+        ]);
+        let responses: Value = proxy.post(&batch).await.json().await.unwrap();
+
+        let hits = upstream.hits();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].json().as_array().unwrap().len(), 3);
+        assert_eq!(upstream.methods(), ["eth_getCode", "eth_blockNumber", "eth_getStorageAt"]);
+
+        assert_eq!(responses[0]["id"], "a");
+        assert_eq!(responses[0]["result"][0], "eth_getCode");
+        assert_eq!(responses[1]["id"], 7);
+        assert_eq!(responses[1]["result"][0], "eth_blockNumber");
+        assert_eq!(responses[2]["id"], "a");
+        assert_eq!(responses[2]["result"][0], "eth_getStorageAt");
+    }
+
+    #[tokio::test]
+    async fn local_answers_stay_out_of_the_upstream_batch() {
+        let upstream = reversing_upstream().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": [] },
+            { "jsonrpc": "2.0", "id": 2, "method": "web3_sha3", "params": ["0x"] },
+            { "jsonrpc": "2.0", "id": 3, "method": "eth_getTransactionCount", "params": ["0x0000000000000000000000000000000000000001", "latest"] },
+            { "jsonrpc": "2.0", "id": 4, "method": "eth_getBalance", "params": ["0x0000000000000000000000000000000000000001", "latest"] },
+        ]);
+        let responses: Value = proxy.post(&batch).await.json().await.unwrap();
+
+        assert_eq!(upstream.hits().len(), 1);
+        assert_eq!(upstream.methods(), ["eth_blockNumber", "eth_getBalance"]);
+        assert_eq!(responses[0]["result"][0], "eth_blockNumber");
+        assert_eq!(responses[1]["result"], "0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470");
+        assert_eq!(responses[2]["result"], "0x0");
+        assert_eq!(responses[3]["result"][0], "eth_getBalance");
+    }
+
+    #[tokio::test]
+    async fn entries_are_resent_one_by_one_when_the_upstream_rejects_batches() {
+        let upstream = MockUpstream::start(|hit| match hit.json() {
+            Value::Array(_) => MockReply::json(json!({ "jsonrpc": "2.0", "id": null, "error": { "code": -32600, "message": "batches are not supported" } })),
+            request => MockReply::json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": request["method"] })),
+        })
+        .await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": [] },
+            { "jsonrpc": "2.0", "id": 2, "method": "eth_gasPrice", "params": [] },
+        ]);
+        let responses: Value = proxy.post(&batch).await.json().await.unwrap();
+
+        assert_eq!(upstream.hits().len(), 3);
+        assert_eq!(responses, json!([
+            { "jsonrpc": "2.0", "id": 1, "result": "eth_blockNumber" },
+            { "jsonrpc": "2.0", "id": 2, "result": "eth_gasPrice" },
+        ]));
+    }
+
+    #[tokio::test]
+    async fn routed_entries_are_batched_per_upstream() {
+        let default = reversing_upstream().await;
+        let other = reversing_upstream().await;
+        let route = format!("other={}/jsonrpc", other.url);
+        let proxy = TestProxy::start(&default, &["--upstream", &route, "--route", "eth_gasPrice=other"]).await;
+
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": [] },
+            { "jsonrpc": "2.0", "id": 2, "method": "eth_gasPrice", "params": [] },
+            { "jsonrpc": "2.0", "id": 3, "method": "eth_getBalance", "params": ["0x0000000000000000000000000000000000000001", "latest"] },
+        ]);
+        let responses: Value = proxy.post(&batch).await.json().await.unwrap();
+
+        assert_eq!(default.hits().len(), 1);
+        assert_eq!(default.methods(), ["eth_blockNumber", "eth_getBalance"]);
+        assert_eq!(other.methods(), ["eth_gasPrice"]);
+        assert_eq!(other.hits()[0].json()["id"], 2);
+        let methods: Vec<_> = responses.as_array().unwrap().iter().map(|response| response["result"][0].clone()).collect();
+        assert_eq!(methods, ["eth_blockNumber", "eth_gasPrice", "eth_getBalance"]);
+    }
+
+    #[tokio::test]
+    async fn lone_notifications_get_an_empty_response() {
+        let upstream = reversing_upstream().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let forwarded = proxy.post(&json!({ "jsonrpc": "2.0", "method": "eth_blockNumber", "params": [] })).await;
+        assert_eq!(forwarded.status(), 200);
+        assert_eq!(forwarded.text().await.unwrap(), "");
+        assert_eq!(upstream.methods(), ["eth_blockNumber"]);
+
+        // Answered locally, still without a response
+        let local = proxy.post(&json!({ "jsonrpc": "2.0", "method": "web3_sha3", "params": ["0x"] })).await;
+        assert_eq!(local.status(), 200);
+        assert_eq!(local.text().await.unwrap(), "");
+        assert_eq!(upstream.hits().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn notifications_are_left_out_of_batch_responses() {
+        let upstream = reversing_upstream().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let batch = json!([
+            { "jsonrpc": "2.0", "method": "eth_gasPrice", "params": [] },
+            { "jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": [] },
+            { "jsonrpc": "2.0", "method": "web3_sha3", "params": ["0x"] },
+            { "jsonrpc": "2.0", "id": 2, "method": "web3_sha3", "params": ["0x"] },
+        ]);
+        let responses: Value = proxy.post(&batch).await.json().await.unwrap();
+
+        assert_eq!(upstream.methods(), ["eth_gasPrice", "eth_blockNumber"]);
+        let ids: Vec<_> = responses.as_array().unwrap().iter().map(|response| response["id"].clone()).collect();
+        assert_eq!(ids, [1, 2]);
+        assert_eq!(responses[0]["result"][0], "eth_blockNumber");
+
+        let only_notifications = json!([{ "jsonrpc": "2.0", "method": "eth_gasPrice", "params": [] }]);
+        let response = proxy.post(&only_notifications).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "");
+    }
+}
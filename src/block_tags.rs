@@ -0,0 +1,261 @@
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::{upstream_call, wallet_post, AppState};
+
+/// Position of the block parameter for methods that take one
+const BLOCK_PARAM_POSITIONS: &[(&str, usize)] = &[
+    ("eth_call", 1),
+    ("eth_getBalance", 1),
+    ("eth_getCode", 1),
+    ("eth_getStorageAt", 2),
+    ("eth_getProof", 2),
+    ("eth_getTransactionCount", 1),
+    ("eth_getBlockByNumber", 0),
+    ("eth_getBlockReceipts", 0),
+    ("eth_getBlockTransactionCountByNumber", 0),
+    ("eth_getTransactionByBlockNumberAndIndex", 0),
+];
+
+/// Block tags TRON's jsonrpc doesn't understand
+const UNSUPPORTED_TAGS: &[&str] = &["earliest", "pending", "safe", "finalized"];
+
+/// Methods that look up a block by number, for which `earliest` must name a block the
+/// upstream actually has
+const BLOCK_LOOKUP_METHODS: &[&str] = &[
+    "eth_getBlockByNumber",
+    "eth_getBlockReceipts",
+    "eth_getBlockTransactionCountByNumber",
+    "eth_getTransactionByBlockNumberAndIndex",
+];
+
+/// Returns the block parameters of a request, including eth_getLogs' fromBlock/toBlock.
+fn block_params_mut<'a>(method: &str, params: &'a mut Value) -> Vec<&'a mut Value> {
+    if method == "eth_getLogs" {
+        return match params.get_mut(0).and_then(Value::as_object_mut) {
+            Some(filter) => filter
+                .iter_mut()
+                .filter(|(key, _)| *key == "fromBlock" || *key == "toBlock")
+                .map(|(_, value)| value)
+                .collect(),
+            None => Vec::new(),
+        };
+    }
+
+    BLOCK_PARAM_POSITIONS
+        .iter()
+        .find(|(name, _)| *name == method)
+        .and_then(|(_, position)| params.get_mut(*position))
+        .into_iter()
+        .collect()
+}
+
+/// Rewrites block tags the upstream rejects into ones it accepts.
+///
+/// `earliest` becomes the genesis block `0x0` (or, for block lookups, the lowest block the
+/// upstream has). `pending`, `safe` and `finalized` become `latest`, except that
+/// `finalized` maps to the latest solidified block when `--map-finalized-to-solidified` is
+/// set, and `finalized` and `safe` map to the latest block minus `--finalized-offset`.
+/// Numbers and `latest` pass through untouched.
+pub async fn rewrite_block_tags(state: &AppState, method: &str, params: &mut Value) {
+    let mut solidified_block = None;
+    let mut latest_block = None;
+
+    for param in block_params_mut(method, params) {
+        let Some(tag) = param.as_str().filter(|tag| UNSUPPORTED_TAGS.contains(tag)) else {
+            continue;
+        };
+
+        let replacement = match tag {
+            "earliest" if BLOCK_LOOKUP_METHODS.contains(&method) => {
+                format!("0x{:x}", earliest_available_block(state).await)
+            }
+            "earliest" => "0x0".to_string(),
+            "finalized" | "safe" if state.finalized_offset.is_some() => {
+                if latest_block.is_none() {
+                    latest_block = fetch_latest_block_number(state).await;
+                }
+                let offset = state.finalized_offset.unwrap_or_default();
+                latest_block.map_or_else(
+                    || "latest".to_string(),
+                    |number| format!("0x{:x}", number.saturating_sub(offset)),
+                )
+            }
+            "finalized" if state.map_finalized_to_solidified => {
+                if solidified_block.is_none() {
+                    solidified_block = fetch_solidified_block_number(state).await;
+                }
+                solidified_block.map_or_else(|| "latest".to_string(), |number| format!("0x{:x}", number))
+            }
+            _ => "latest".to_string(),
+        };
+
+        info!("Rewrote '{}' block tag to '{}' for {}", tag, replacement, method);
+        *param = json!(replacement);
+    }
+}
+
+/// Fetches the latest solidified block number from the node's solidity API.
+async fn fetch_solidified_block_number(state: &AppState) -> Option<u64> {
+    let block = match wallet_post(state, "/walletsolidity/getnowblock", &json!({})).await {
+        Ok(block) => Some(block),
+        Err(e) => {
+            warn!("Failed to fetch solidified block: {:#}", e);
+            None
+        }
+    };
+
+    let number = block
+        .as_ref()
+        .and_then(|block| block.pointer("/block_header/raw_data/number"))
+        .and_then(Value::as_u64);
+
+    if number.is_none() {
+        warn!("Could not determine solidified block number, falling back to 'latest'");
+    }
+
+    number
+}
+
+async fn fetch_latest_block_number(state: &AppState) -> Option<u64> {
+    match upstream_call(state, "eth_blockNumber", json!([])).await {
+        Ok(number) => {
+            let number = number.as_str().and_then(crate::parse_hex_u64);
+            if number.is_none() {
+                warn!("Invalid eth_blockNumber result, falling back to 'latest'");
+            }
+            number
+        }
+        Err(e) => {
+            warn!("Failed to fetch latest block number, falling back to 'latest': {}", e);
+            None
+        }
+    }
+}
+
+async fn block_exists(state: &AppState, number: u64) -> anyhow::Result<bool> {
+    let block = upstream_call(state, "eth_getBlockByNumber", json!([format!("0x{:x}", number), false])).await?;
+    Ok(!block.is_null())
+}
+
+/// Returns the lowest block number the upstream can serve, which is 0 unless the node
+/// indexes from 1 or has pruned history. A successful lookup is cached.
+async fn earliest_available_block(state: &AppState) -> u64 {
+    match state.earliest_block.get_or_try_init(|| find_earliest_block(state)).await {
+        Ok(number) => *number,
+        Err(e) => {
+            warn!("Failed to determine earliest available block, using 0x0: {}", e);
+            0
+        }
+    }
+}
+
+async fn find_earliest_block(state: &AppState) -> anyhow::Result<u64> {
+    if block_exists(state, 0).await? {
+        return Ok(0);
+    }
+
+    let latest = upstream_call(state, "eth_blockNumber", json!([])).await?;
+    let mut high = latest
+        .as_str()
+        .and_then(crate::parse_hex_u64)
+        .ok_or_else(|| anyhow::anyhow!("invalid eth_blockNumber result {}", latest))?;
+    let mut low = 0;
+
+    // Blocks are available from some number onward; binary search for the first one
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if block_exists(state, middle).await? {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+
+    warn!("Upstream has no genesis block, mapping 'earliest' to block {}", high);
+    Ok(high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{rpc_reply, MockReply, MockUpstream, TestProxy};
+
+    /// A node at block 100 that has pruned everything below block 5, with block 90 solidified.
+    async fn pruned_node() -> MockUpstream {
+        MockUpstream::start(|hit| match hit.path.as_str() {
+            "/walletsolidity/getnowblock" => MockReply::json(json!({ "block_header": { "raw_data": { "number": 90 } } })),
+            _ => rpc_reply(hit, |method, params| match method {
+                "eth_blockNumber" => Ok(json!("0x64")),
+                "eth_getBlockByNumber" => {
+                    let number = params[0].as_str().and_then(crate::parse_hex_u64).unwrap();
+                    Ok(if number >= 5 { json!({ "number": params[0] }) } else { Value::Null })
+                }
+                _ => Err(json!({ "code": -32601, "message": "method not found" })),
+            }),
+        })
+        .await
+    }
+
+    async fn rewritten(proxy: &TestProxy, method: &str, mut params: Value) -> Value {
+        rewrite_block_tags(&proxy.state, method, &mut params).await;
+        params
+    }
+
+    #[tokio::test]
+    async fn earliest_is_genesis_except_for_block_lookups() {
+        let upstream = pruned_node().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        assert_eq!(rewritten(&proxy, "eth_getBalance", json!(["0x01", "earliest"])).await, json!(["0x01", "0x0"]));
+        assert!(upstream.hits().is_empty());
+
+        assert_eq!(rewritten(&proxy, "eth_getBlockByNumber", json!(["earliest", false])).await, json!(["0x5", false]));
+        let searches = upstream.hits().len();
+        assert_eq!(rewritten(&proxy, "eth_getBlockReceipts", json!(["earliest"])).await, json!(["0x5"]));
+        assert_eq!(upstream.hits().len(), searches, "the earliest block is cached");
+    }
+
+    #[tokio::test]
+    async fn pending_safe_and_finalized_become_latest() {
+        let upstream = pruned_node().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        for tag in ["pending", "safe", "finalized"] {
+            assert_eq!(rewritten(&proxy, "eth_getCode", json!(["0x01", tag])).await, json!(["0x01", "latest"]), "{}", tag);
+        }
+        assert_eq!(rewritten(&proxy, "eth_getStorageAt", json!(["0x01", "0x0", "0x10"])).await, json!(["0x01", "0x0", "0x10"]));
+        assert_eq!(rewritten(&proxy, "eth_call", json!([{}, "latest"])).await, json!([{}, "latest"]));
+        assert!(upstream.hits().is_empty());
+    }
+
+    #[tokio::test]
+    async fn finalized_can_map_to_the_solidified_block() {
+        let upstream = pruned_node().await;
+        let proxy = TestProxy::start(&upstream, &["--map-finalized-to-solidified"]).await;
+
+        assert_eq!(rewritten(&proxy, "eth_getBalance", json!(["0x01", "finalized"])).await, json!(["0x01", "0x5a"]));
+        assert_eq!(rewritten(&proxy, "eth_getBalance", json!(["0x01", "safe"])).await, json!(["0x01", "latest"]));
+    }
+
+    #[tokio::test]
+    async fn finalized_and_safe_can_trail_latest() {
+        let upstream = pruned_node().await;
+        let proxy = TestProxy::start(&upstream, &["--finalized-offset", "20"]).await;
+
+        assert_eq!(rewritten(&proxy, "eth_getBalance", json!(["0x01", "finalized"])).await, json!(["0x01", "0x50"]));
+        assert_eq!(rewritten(&proxy, "eth_getBalance", json!(["0x01", "safe"])).await, json!(["0x01", "0x50"]));
+    }
+
+    #[tokio::test]
+    async fn log_ranges_are_rewritten_too() {
+        let upstream = pruned_node().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let params = json!([{ "fromBlock": "earliest", "toBlock": "pending", "address": "0x01" }]);
+        assert_eq!(
+            rewritten(&proxy, "eth_getLogs", params).await,
+            json!([{ "fromBlock": "0x0", "toBlock": "latest", "address": "0x01" }])
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::info;
+
+/// Fixed answers for matching requests, loaded from `--canned`, for testing clients against
+/// responses the upstream can't easily be made to produce.
+pub struct CannedResponses {
+    rules: Vec<CannedRule>,
+}
+
+/// One entry of the `--canned` file: `{"method", "params"?, "result" | "error"}`.
+#[derive(Deserialize)]
+struct CannedRule {
+    method: String,
+    /// Matches any params when absent
+    #[serde(default)]
+    params: Option<Value>,
+    #[serde(flatten)]
+    reply: CannedReply,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CannedReply {
+    Result(Value),
+    Error(Value),
+}
+
+impl CannedResponses {
+    /// Reads a JSON array of rules.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let rules: Vec<CannedRule> =
+            serde_json::from_str(&contents).with_context(|| format!("invalid canned responses in {}", path.display()))?;
+        info!("Loaded {} canned responses from {}", rules.len(), path.display());
+        Ok(CannedResponses { rules })
+    }
+
+    /// Returns the reply of the first rule matching the request, if any.
+    pub fn find(&self, method: &str, params: Option<&Value>) -> Option<&CannedReply> {
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.method == method
+                    && rule.params.as_ref().is_none_or(|expected| params.is_some_and(|params| matches(expected, params)))
+            })
+            .map(|rule| &rule.reply)
+    }
+}
+
+/// Whether `actual` matches the `expected` pattern: objects need the pattern's keys to
+/// match, arrays the pattern's leading elements, and `0x` strings compare case-insensitively.
+fn matches(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => expected
+            .iter()
+            .all(|(key, expected)| actual.get(key).is_some_and(|actual| matches(expected, actual))),
+        (Value::Array(expected), Value::Array(actual)) => {
+            expected.len() <= actual.len() && expected.iter().zip(actual).all(|(expected, actual)| matches(expected, actual))
+        }
+        (Value::String(expected), Value::String(actual)) if expected.starts_with("0x") => {
+            expected.eq_ignore_ascii_case(actual)
+        }
+        _ => expected == actual,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn canned(rules: Value) -> CannedResponses {
+        CannedResponses { rules: serde_json::from_value(rules).unwrap() }
+    }
+
+    fn result(reply: Option<&CannedReply>) -> Option<&Value> {
+        match reply {
+            Some(CannedReply::Result(result)) => Some(result),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn array_params_match_by_prefix() {
+        let responses = canned(json!([{ "method": "eth_getBalance", "params": ["0xd8da6bf26964af9d7eed9e03e53415d37aa96045"], "result": "0x1" }]));
+
+        let find = |params: Value| result(responses.find("eth_getBalance", Some(&params))).cloned();
+        assert_eq!(find(json!(["0xd8da6bf26964af9d7eed9e03e53415d37aa96045", "latest"])), Some(json!("0x1")));
+        assert_eq!(find(json!(["0xd8da6bf26964af9d7eed9e03e53415d37aa96045"])), Some(json!("0x1")));
+        assert_eq!(find(json!([])), None);
+    }
+
+    #[test]
+    fn object_params_match_on_the_pattern_keys() {
+        let responses = canned(json!([{ "method": "eth_call", "params": [{ "data": "0x70a08231" }], "result": "0x" }]));
+
+        let call = json!([{ "to": "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c", "data": "0x70a08231" }, "latest"]);
+        assert_eq!(result(responses.find("eth_call", Some(&call))), Some(&json!("0x")));
+        let call = json!([{ "to": "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c" }, "latest"]);
+        assert!(responses.find("eth_call", Some(&call)).is_none());
+    }
+
+    #[test]
+    fn hex_strings_match_case_insensitively() {
+        assert!(matches(&json!("0xABCDEF"), &json!("0xabcdef")));
+        assert!(matches(&json!(["0xd8DA6BF26964aF9D7eEd9e03E53415D37aA96045"]), &json!(["0xd8da6bf26964af9d7eed9e03e53415d37aa96045"])));
+        // Other strings compare exactly
+        assert!(!matches(&json!("Latest"), &json!("latest")));
+    }
+
+    #[test]
+    fn rules_reply_with_a_result_or_an_error() {
+        let responses = canned(json!([
+            { "method": "eth_chainId", "result": "0x2b6653dc" },
+            { "method": "eth_sendRawTransaction", "error": { "code": -32000, "message": "nonce too low" } },
+        ]));
+
+        assert_eq!(result(responses.find("eth_chainId", None)), Some(&json!("0x2b6653dc")));
+        match responses.find("eth_sendRawTransaction", Some(&json!(["0x02f8"]))) {
+            Some(CannedReply::Error(error)) => assert_eq!(*error, json!({ "code": -32000, "message": "nonce too low" })),
+            _ => panic!("expected the canned error"),
+        }
+    }
+
+    #[test]
+    fn rules_for_other_methods_or_params_do_not_match() {
+        let responses = canned(json!([{ "method": "eth_getBalance", "params": ["0xd8da6bf26964af9d7eed9e03e53415d37aa96045", "0x10"], "result": "0x1" }]));
+
+        assert!(responses.find("eth_getCode", Some(&json!(["0xd8da6bf26964af9d7eed9e03e53415d37aa96045", "0x10"]))).is_none());
+        assert!(responses.find("eth_getBalance", Some(&json!(["0xd8da6bf26964af9d7eed9e03e53415d37aa96045", "latest"]))).is_none());
+        assert!(responses.find("eth_getBalance", Some(&json!({ "address": "0xd8da6bf26964af9d7eed9e03e53415d37aa96045" }))).is_none());
+        // A rule with params needs the request to have some
+        assert!(responses.find("eth_getBalance", None).is_none());
+    }
+}
@@ -0,0 +1,159 @@
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+
+use crate::{wallet_post, AppState};
+
+/// The chain parameters the proxy's synthetic answers depend on, from
+/// `wallet/getchainparameters`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainParameters {
+    /// Price of one unit of energy, in SUN
+    pub energy_fee: u64,
+    /// Largest fee_limit a transaction may set, in SUN
+    pub max_fee_limit: Option<u64>,
+    /// Energy the whole network can stake for per day
+    pub total_energy_limit: Option<u64>,
+}
+
+/// The latest chain parameters fetched from the node, refreshed in the background.
+#[derive(Default)]
+pub struct ChainParameterCache {
+    current: RwLock<Option<ChainParameters>>,
+}
+
+impl ChainParameterCache {
+    pub fn get(&self) -> Option<ChainParameters> {
+        *self.current.read().unwrap()
+    }
+
+    fn set(&self, parameters: ChainParameters) -> bool {
+        self.current.write().unwrap().replace(parameters) != Some(parameters)
+    }
+}
+
+async fn fetch_chain_parameters(state: &AppState) -> anyhow::Result<ChainParameters> {
+    let response = wallet_post(state, "/wallet/getchainparameters", &json!({})).await?;
+
+    let parameter = |key: &str| {
+        response
+            .get("chainParameter")
+            .and_then(Value::as_array)
+            .and_then(|parameters| parameters.iter().find(|parameter| parameter.get("key").and_then(Value::as_str) == Some(key)))
+            .and_then(|parameter| parameter.get("value"))
+            .and_then(Value::as_u64)
+    };
+
+    Ok(ChainParameters {
+        energy_fee: parameter("getEnergyFee").context("wallet/getchainparameters has no getEnergyFee")?,
+        max_fee_limit: parameter("getMaxFeeLimit"),
+        total_energy_limit: parameter("getTotalEnergyLimit"),
+    })
+}
+
+/// Fetches the chain parameters into the cache, logging them when they change.
+pub async fn refresh_chain_parameters(state: &AppState) {
+    match fetch_chain_parameters(state).await {
+        Ok(parameters) => {
+            if state.chain_params.set(parameters) {
+                let show = |value: Option<u64>| value.map_or_else(|| "unknown".to_string(), |value| value.to_string());
+                info!(
+                    "Chain parameters for {}: energy fee {} SUN, max fee limit {} SUN, total energy limit {}",
+                    state.destination,
+                    parameters.energy_fee,
+                    show(parameters.max_fee_limit),
+                    show(parameters.total_energy_limit)
+                );
+            } else {
+                debug!("Chain parameters for {} unchanged", state.destination);
+            }
+        }
+        Err(e) => warn!("Failed to fetch chain parameters from {}: {:#}", state.destination, e),
+    }
+}
+
+/// Refreshes the chain parameters every `interval` in the background.
+pub fn spawn_refresh(state: AppState, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        // The first tick fires immediately; startup already fetched
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            refresh_chain_parameters(&state).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{rpc_reply, MockReply, MockUpstream, TestProxy};
+
+    /// A node with a 420 SUN energy fee and a 15000 TRX max fee limit, at block 0x64, whose
+    /// estimates are whatever the call's `data` says
+    async fn node() -> MockUpstream {
+        MockUpstream::start(|hit| match hit.path.as_str() {
+            "/wallet/getchainparameters" => MockReply::json(json!({
+                "chainParameter": [
+                    { "key": "getMaxFeeLimit", "value": 15_000_000_000u64 },
+                    { "key": "getEnergyFee", "value": 420 },
+                ],
+            })),
+            _ => rpc_reply(hit, |method, params| match method {
+                "eth_blockNumber" => Ok(json!("0x64")),
+                "eth_gasPrice" => Ok(json!("0x1")),
+                "eth_estimateGas" => Ok(params[0]["data"].clone()),
+                _ => Err(json!({ "code": -32601, "message": "method not found" })),
+            }),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn refresh_parses_the_energy_fee_and_limits() {
+        let upstream = node().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+        assert_eq!(proxy.state.chain_params.get(), None);
+
+        refresh_chain_parameters(&proxy.state).await;
+
+        let expected = ChainParameters { energy_fee: 420, max_fee_limit: Some(15_000_000_000), total_energy_limit: None };
+        assert_eq!(proxy.state.chain_params.get(), Some(expected));
+        assert_eq!(proxy.rpc("eth_gasPrice", json!([])).await["result"], "0x1a4");
+    }
+
+    #[tokio::test]
+    async fn fee_history_reports_the_energy_fee() {
+        let upstream = node().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+        refresh_chain_parameters(&proxy.state).await;
+
+        let history = proxy.rpc("eth_feeHistory", json!(["0x3", "latest", [25, 75]])).await["result"].clone();
+
+        assert_eq!(history["oldestBlock"], "0x62");
+        assert_eq!(history["baseFeePerGas"], json!(["0x1a4", "0x1a4", "0x1a4", "0x1a4"]));
+        assert_eq!(history["gasUsedRatio"].as_array().unwrap().len(), 3);
+        assert_eq!(history["reward"], json!([["0x0", "0x0"], ["0x0", "0x0"], ["0x0", "0x0"]]));
+        assert!(!upstream.methods().contains(&"eth_gasPrice".to_string()));
+    }
+
+    #[tokio::test]
+    async fn estimates_over_the_max_fee_limit_are_rejected() {
+        let upstream = node().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+        refresh_chain_parameters(&proxy.state).await;
+        let to = "0x95222290dd7278aa3ddd389cc1e1d165cc4bafe5";
+
+        // 15000 TRX buys 35714285 (0x220f4ed) energy at 420 SUN
+        let within = proxy.rpc("eth_estimateGas", json!([{ "to": to, "data": "0x0220f4ed" }])).await;
+        let over = proxy.rpc("eth_estimateGas", json!([{ "to": to, "data": "0x0220f4ee" }])).await;
+
+        assert_eq!(within["result"], "0x0220f4ed");
+        assert_eq!(over["error"]["code"], -32000);
+        assert_eq!(over["error"]["message"], "gas required exceeds allowance (35714285)");
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use axum::{
+    http::{header, HeaderMap, Method, StatusCode},
+    response::Response,
+};
+use futures::future::{BoxFuture, Shared, WeakShared};
+use futures::FutureExt;
+use serde_json::Value;
+use tracing::debug;
+
+use crate::error::ProxyError;
+use crate::{forward_request, readdress, AppState};
+
+/// An upstream response as shared between coalesced callers.
+#[derive(Clone)]
+struct ForwardedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: String,
+}
+
+type ForwardFuture = BoxFuture<'static, Result<ForwardedResponse, ProxyError>>;
+type SharedForward = Shared<ForwardFuture>;
+
+/// Upstream requests currently in flight, keyed by destination, credentials, method and
+/// params, so identical concurrent reads share one upstream round trip. Only the callers
+/// hold the requests; once every one of them is gone (their clients disconnected) the
+/// upstream request is dropped and the entry can no longer be joined.
+#[derive(Default)]
+pub struct InFlightRequests {
+    requests: Mutex<HashMap<String, WeakShared<ForwardFuture>>>,
+}
+
+/// Forwards a read request, or joins an identical one that's already in flight. The shared
+/// response is re-addressed to each caller's `id`.
+pub async fn forward_coalesced(
+    state: &AppState,
+    headers: &HeaderMap,
+    method: &str,
+    params: Option<&Value>,
+    id: Option<&Value>,
+    body: &str,
+) -> Result<Response<String>, ProxyError> {
+    // Clients with different credentials may be answered differently, so only requests
+    // that reach the upstream with the same Authorization share a response
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .filter(|_| !state.override_auth)
+        .map(|value| String::from_utf8_lossy(value.as_bytes()))
+        .unwrap_or_default();
+    let key = format!(
+        "{} {} {} {}",
+        state.destination,
+        authorization,
+        method,
+        params.map(Value::to_string).unwrap_or_default()
+    );
+
+    let (forward, joined) = {
+        let mut requests = state.in_flight.requests.lock().unwrap();
+        match requests.get(&key).and_then(WeakShared::upgrade) {
+            Some(forward) => (forward, true),
+            None => {
+                let forward = forward_shared(state.clone(), headers.clone(), method.to_string(), body.to_string(), key.clone());
+                // Entries of cancelled requests never remove themselves
+                requests.retain(|_, forward| forward.upgrade().is_some());
+                if let Some(weak) = forward.downgrade() {
+                    requests.insert(key, weak);
+                }
+                (forward, false)
+            }
+        }
+    };
+
+    if joined {
+        debug!("Joining an identical in-flight {} request", method);
+        state.stats.record_coalesced_request(method);
+    }
+
+    let response = forward.await?;
+    let body = if joined { readdress(response.body, id) } else { response.body };
+
+    let mut builder = Response::builder().status(response.status);
+    for (name, value) in &response.headers {
+        // The body may have been re-addressed, so its length is recomputed
+        if name != header::CONTENT_LENGTH {
+            builder = builder.header(name, value);
+        }
+    }
+    builder.body(body).map_err(ProxyError::translation)
+}
+
+/// Starts the upstream request as a shared future that removes itself from the in-flight
+/// map once answered.
+fn forward_shared(state: AppState, headers: HeaderMap, method: String, body: String, key: String) -> SharedForward {
+    async move {
+        let response = forward_request(&state, Method::POST, &headers, &body, &method).await;
+        state.in_flight.requests.lock().unwrap().remove(&key);
+
+        let (parts, body) = response?.into_parts();
+        Ok(ForwardedResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        })
+    }
+    .boxed()
+    .shared()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::test_support::{MockUpstream, TestProxy};
+
+    #[tokio::test]
+    async fn identical_concurrent_reads_share_one_upstream_request() {
+        let upstream = MockUpstream::rpc_with_delay(Duration::from_millis(200), |_, _| Ok(json!("0x10"))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let first = json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": [] });
+        let second = json!({ "jsonrpc": "2.0", "id": 2, "method": "eth_blockNumber", "params": [] });
+        let (first, second) = tokio::join!(proxy.post(&first), proxy.post(&second));
+        let first: serde_json::Value = first.json().await.unwrap();
+        let second: serde_json::Value = second.json().await.unwrap();
+
+        assert_eq!(upstream.methods(), ["eth_blockNumber"]);
+        assert_eq!(first, json!({ "jsonrpc": "2.0", "id": 1, "result": "0x10" }));
+        assert_eq!(second, json!({ "jsonrpc": "2.0", "id": 2, "result": "0x10" }));
+    }
+
+    #[tokio::test]
+    async fn reads_with_different_credentials_are_not_shared() {
+        let upstream = MockUpstream::rpc_with_delay(Duration::from_millis(200), |_, _| Ok(json!("0x10"))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": [] }).to_string();
+        let send = |token: &'static str| {
+            proxy
+                .client
+                .post(&proxy.url)
+                .header("content-type", "application/json")
+                .header("authorization", token)
+                .body(body.clone())
+                .send()
+        };
+        let (first, second) = tokio::join!(send("Bearer alice"), send("Bearer bob"));
+        first.unwrap();
+        second.unwrap();
+
+        let credentials: Vec<_> = upstream.hits().iter().map(|hit| hit.headers["authorization"].clone()).collect();
+        assert_eq!(credentials.len(), 2);
+        assert_ne!(credentials[0], credentials[1]);
+    }
+}
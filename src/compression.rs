@@ -0,0 +1,77 @@
+use std::io::Write;
+
+use axum::{
+    body::{self, Body},
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use tracing::{debug, warn};
+
+use crate::AppState;
+
+/// Bodies smaller than this aren't worth compressing
+const MIN_COMPRESSED_BYTES: usize = 1024;
+
+/// Whether an Accept-Encoding header allows gzip: listed (or `*`) without `q=0`.
+fn accepts_gzip(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|coding| {
+            let mut parts = coding.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            let refused = parts.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .is_some_and(|q| q == 0.0)
+            });
+            (name.eq_ignore_ascii_case("gzip") || name == "*") && !refused
+        })
+}
+
+/// Gzips responses for clients that accept it with `--compress-responses`. Runs on the final
+/// body, after any enhancement, and leaves small or already-encoded bodies alone.
+pub async fn compression_middleware(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if !state.compress_responses || !accepts_gzip(request.headers()) {
+        return next.run(request).await;
+    }
+
+    let response = next.run(request).await;
+    if response.headers().contains_key(header::CONTENT_ENCODING) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Failed to read response body for compression: {}", e);
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+    if bytes.len() < MIN_COMPRESSED_BYTES {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+
+    let mut encoder = GzEncoder::new(Vec::with_capacity(bytes.len() / 4), Compression::fast());
+    let compressed = match encoder.write_all(&bytes).and_then(|()| encoder.finish()) {
+        Ok(compressed) => compressed,
+        Err(e) => {
+            warn!("Failed to compress response body: {}", e);
+            return Response::from_parts(parts, Body::from(bytes));
+        }
+    };
+    debug!("Compressed response body from {} to {} bytes", bytes.len(), compressed.len());
+
+    parts.headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    parts.headers.insert(header::CONTENT_LENGTH, HeaderValue::from(compressed.len()));
+    parts.headers.append(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+    Response::from_parts(parts, Body::from(compressed))
+}
@@ -0,0 +1,175 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::time::{sleep, timeout_at, Instant};
+use tracing::{debug, info, warn};
+
+use crate::overrides::OverrideError;
+use crate::{enhance_receipt, parse_quantity, upstream_call, wallet_post, AppState};
+
+/// Answers `tron_waitForConfirmation(txid, timeout)`: polls the node's solidity API until the
+/// transaction is solidified, then returns its receipt, enhanced like an
+/// `eth_getTransactionReceipt` answer. `timeout` is in seconds, at most
+/// `--confirmation-max-wait`, which is also the default.
+pub async fn wait_for_confirmation(state: &AppState, params: Option<&Value>) -> Result<Value, OverrideError> {
+    let params = params.and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    let Some(tx_hash) = params.first().and_then(Value::as_str) else {
+        return Err((-32602, "tron_waitForConfirmation expects a transaction hash".to_string()));
+    };
+    let tx_id = tx_hash.strip_prefix("0x").unwrap_or(tx_hash).to_ascii_lowercase();
+    if tx_id.len() != 64 || !tx_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err((-32602, format!("invalid transaction hash '{}'", tx_hash)));
+    }
+
+    let timeout = match params.get(1).filter(|timeout| !timeout.is_null()) {
+        Some(timeout) => Duration::from_secs(
+            parse_quantity(timeout).ok_or_else(|| (-32602, format!("invalid timeout {}", timeout)))?,
+        )
+        .min(state.confirmation_max_wait),
+        None => state.confirmation_max_wait,
+    };
+
+    info!("Waiting up to {}s for {} to be solidified", timeout.as_secs(), tx_hash);
+    let deadline = Instant::now() + timeout;
+    let mut polls = 0u32;
+
+    loop {
+        polls += 1;
+        // A slow node can't hold a poll past the deadline
+        if let Ok(Some(receipt)) = timeout_at(deadline, solidified_receipt(state, &tx_id)).await {
+            info!("{} confirmed after {} polls", tx_hash, polls);
+            return Ok(receipt);
+        }
+
+        if Instant::now() + state.confirmation_poll_interval > deadline {
+            return Err((
+                -32000,
+                format!("transaction {} not confirmed within {}s", tx_hash, timeout.as_secs()),
+            ));
+        }
+        sleep(state.confirmation_poll_interval).await;
+    }
+}
+
+/// Returns the transaction's receipt once the solidity node knows it, `None` until then.
+/// Failed polls count as not yet confirmed.
+async fn solidified_receipt(state: &AppState, tx_id: &str) -> Option<Value> {
+    let info = match wallet_post(state, "/walletsolidity/gettransactioninfobyid", &json!({ "value": tx_id })).await {
+        Ok(info) => info,
+        Err(e) => {
+            warn!("Failed to poll the solidity node for 0x{}: {:#}", tx_id, e);
+            return None;
+        }
+    };
+
+    // Transactions that aren't solidified yet come back as an empty object
+    info.get("id")?;
+
+    // The jsonrpc side can trail the solidity node briefly
+    let mut receipt = match upstream_call(state, "eth_getTransactionReceipt", json!([format!("0x{}", tx_id)])).await {
+        Ok(receipt) => receipt,
+        Err(e) => {
+            warn!("Failed to fetch the receipt for solidified 0x{}: {}", tx_id, e);
+            return None;
+        }
+    };
+    let Some(receipt_obj) = receipt.as_object_mut() else {
+        debug!("0x{} is solidified but has no receipt yet", tx_id);
+        return None;
+    };
+    enhance_receipt(state, receipt_obj).await;
+    Some(receipt)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::test_support::{rpc_reply, MockReply, MockUpstream, TestProxy};
+
+    const TX_HASH: &str = "0x5f1c1e3e8f0c2b4a6d8e0f1a3b5c7d9e1f2a4b6c8d0e2f4a6b8c0d2e4f6a8b0c";
+    const SOLIDITY_PATH: &str = "/walletsolidity/gettransactioninfobyid";
+
+    /// A node whose solidity API knows the transaction from poll `confirmed_on` on (never, if
+    /// `None`), taking `delay` over each poll.
+    async fn solidifying_node(confirmed_on: Option<usize>, delay: Duration) -> MockUpstream {
+        let polls = Arc::new(AtomicUsize::new(0));
+        MockUpstream::start(move |hit| match hit.path.as_str() {
+            SOLIDITY_PATH => {
+                let poll = polls.fetch_add(1, Ordering::SeqCst) + 1;
+                let info = match confirmed_on {
+                    Some(confirmed_on) if poll >= confirmed_on => json!({ "id": &TX_HASH[2..], "blockNumber": 16 }),
+                    _ => json!({}),
+                };
+                MockReply::json(info).delay(delay)
+            }
+            "/jsonrpc" => rpc_reply(hit, |method, _| match method {
+                "eth_getTransactionReceipt" => Ok(json!({
+                    "transactionHash": TX_HASH,
+                    "blockNumber": "0x10",
+                    "status": "0x1",
+                    "gasUsed": "0x5208",
+                    "cumulativeGasUsed": "0x5208",
+                    "logs": [],
+                })),
+                _ => Ok(Value::Null),
+            }),
+            _ => MockReply::json(json!({})),
+        })
+        .await
+    }
+
+    fn solidity_polls(upstream: &MockUpstream) -> usize {
+        upstream.hits().iter().filter(|hit| hit.path == SOLIDITY_PATH).count()
+    }
+
+    #[tokio::test]
+    async fn receipt_is_returned_once_solidified() {
+        let upstream = solidifying_node(Some(3), Duration::ZERO).await;
+        let proxy = TestProxy::start(&upstream, &["--confirmation-poll-ms", "50"]).await;
+
+        let response = proxy.rpc("tron_waitForConfirmation", json!([TX_HASH, 5])).await;
+
+        assert_eq!(response["result"]["transactionHash"], TX_HASH);
+        assert_eq!(response["result"]["status"], "0x1");
+        assert_eq!(solidity_polls(&upstream), 3);
+    }
+
+    #[tokio::test]
+    async fn unconfirmed_transactions_time_out() {
+        let upstream = solidifying_node(None, Duration::ZERO).await;
+        let proxy = TestProxy::start(&upstream, &["--confirmation-poll-ms", "200"]).await;
+
+        let started = Instant::now();
+        let response = proxy.rpc("tron_waitForConfirmation", json!([TX_HASH, 1])).await;
+
+        assert_eq!(response["error"]["code"], -32000);
+        assert_eq!(response["error"]["message"], format!("transaction {} not confirmed within 1s", TX_HASH));
+        assert!(started.elapsed() <= Duration::from_millis(1500), "{:?}", started.elapsed());
+        assert!(solidity_polls(&upstream) >= 4);
+    }
+
+    #[tokio::test]
+    async fn slow_polls_stop_at_the_deadline() {
+        let upstream = solidifying_node(Some(1), Duration::from_secs(5)).await;
+        let proxy = TestProxy::start(&upstream, &["--confirmation-poll-ms", "100"]).await;
+
+        let started = Instant::now();
+        let response = proxy.rpc("tron_waitForConfirmation", json!([TX_HASH, 1])).await;
+
+        assert_eq!(response["error"]["code"], -32000);
+        assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn malformed_hashes_are_rejected() {
+        let upstream = solidifying_node(Some(1), Duration::ZERO).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let response = proxy.rpc("tron_waitForConfirmation", json!(["0x1234"])).await;
+        assert_eq!(response["error"]["code"], -32602);
+        assert_eq!(solidity_polls(&upstream), 0);
+    }
+}
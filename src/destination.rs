@@ -0,0 +1,148 @@
+use url::Url;
+
+/// Path TRON nodes serve JSON-RPC under
+const JSONRPC_PATH: &str = "/jsonrpc";
+
+/// A node as given to `--dest`, `--dest-replica` or `--upstream`, resolved to the two places
+/// the proxy talks to it: the JSON-RPC endpoint and the root of its HTTP API.
+#[derive(Clone, Debug)]
+pub struct Destination {
+    /// Where JSON-RPC requests are POSTed
+    pub jsonrpc: Url,
+    /// What `/wallet/...` and the node's other HTTP API paths are joined to
+    pub api_root: Url,
+}
+
+/// Parses a node URL given with or without the `/jsonrpc` suffix: `https://nile.trongrid.io`
+/// and `https://nile.trongrid.io/jsonrpc` both mean JSON-RPC at `/jsonrpc` and the HTTP API
+/// at the root. A URL with any other path is taken as the JSON-RPC endpoint of a gateway, and
+/// doubles as its HTTP API root.
+pub fn parse_destination(destination: &str) -> Result<Destination, String> {
+    let url = parse_base_url(destination)?;
+    let path = url.path().trim_end_matches('/').to_string();
+
+    let mut jsonrpc = url.clone();
+    let mut api_root = url;
+    api_root.set_query(None);
+    match path.strip_suffix(JSONRPC_PATH) {
+        Some(root) => {
+            jsonrpc.set_path(&path);
+            api_root.set_path(root);
+        }
+        None if path.is_empty() => jsonrpc.set_path(JSONRPC_PATH),
+        None => {
+            jsonrpc.set_path(&path);
+            api_root.set_path(&path);
+        }
+    }
+
+    Ok(Destination { jsonrpc, api_root })
+}
+
+/// Parses an http(s) URL, explaining the usual mistake of leaving out the scheme.
+pub fn parse_base_url(url: &str) -> Result<Url, String> {
+    let url = url.trim();
+    let missing_scheme = || format!("'{}' has no http:// or https:// scheme; did you mean https://{}?", url, url);
+
+    let parsed = Url::parse(url).map_err(|e| match e {
+        url::ParseError::RelativeUrlWithoutBase => missing_scheme(),
+        e => format!("invalid URL '{}': {}", url, e),
+    })?;
+    // `localhost:8545` parses, with `localhost` as its scheme and no `//`
+    if parsed.cannot_be_a_base() {
+        return Err(missing_scheme());
+    }
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("unsupported scheme {}:// in '{}': expected http:// or https://", parsed.scheme(), url));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err(format!("'{}' has no host", url));
+    }
+    Ok(parsed)
+}
+
+/// Joins a path (and query) below a base URL with `Url::join`, keeping the base's whole path:
+/// `https://gw/tron/v1` and `/wallet/getnowblock` give `https://gw/tron/v1/wallet/getnowblock`,
+/// whether or not either side has its slash. Runs of slashes in the path collapse to one, and
+/// an empty path leaves the base itself (with the query, if there is one).
+pub fn join_destination(base: &Url, path_and_query: &str) -> Result<Url, url::ParseError> {
+    let (path, query) = path_and_query.split_at(path_and_query.find('?').unwrap_or(path_and_query.len()));
+    let mut relative = String::with_capacity(path_and_query.len());
+    for c in path.trim_start_matches('/').chars() {
+        if !(c == '/' && relative.ends_with('/')) {
+            relative.push(c);
+        }
+    }
+
+    if relative.is_empty() {
+        return base.join(query);
+    }
+
+    let mut base = base.clone();
+    if !base.path().ends_with('/') {
+        let path = format!("{}/", base.path());
+        base.set_path(&path);
+    }
+    // `./` keeps a first segment with a colon from reading as a scheme
+    base.join(&format!("./{}{}", relative, query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(destination: &str) -> (String, String) {
+        let destination = parse_destination(destination).unwrap();
+        (destination.jsonrpc.to_string(), destination.api_root.to_string())
+    }
+
+    #[test]
+    fn jsonrpc_suffix_is_optional() {
+        let expected = ("https://nile.trongrid.io/jsonrpc".to_string(), "https://nile.trongrid.io/".to_string());
+        assert_eq!(parsed("https://nile.trongrid.io"), expected);
+        assert_eq!(parsed("https://nile.trongrid.io/jsonrpc"), expected);
+    }
+
+    #[test]
+    fn trailing_slashes_are_ignored() {
+        let expected = ("https://nile.trongrid.io/jsonrpc".to_string(), "https://nile.trongrid.io/".to_string());
+        assert_eq!(parsed("https://nile.trongrid.io/"), expected);
+        assert_eq!(parsed("https://nile.trongrid.io/jsonrpc/"), expected);
+        assert_eq!(
+            parsed("https://gw.example.com/tron/v1/"),
+            ("https://gw.example.com/tron/v1".to_string(), "https://gw.example.com/tron/v1".to_string())
+        );
+    }
+
+    #[test]
+    fn explicit_port_is_kept() {
+        assert_eq!(
+            parsed("http://127.0.0.1:8545"),
+            ("http://127.0.0.1:8545/jsonrpc".to_string(), "http://127.0.0.1:8545/".to_string())
+        );
+        assert_eq!(
+            parsed("http://localhost:50545/jsonrpc"),
+            ("http://localhost:50545/jsonrpc".to_string(), "http://localhost:50545/".to_string())
+        );
+    }
+
+    #[test]
+    fn missing_scheme_is_explained() {
+        for url in ["nile.trongrid.io", "localhost:8545", "127.0.0.1:8545/jsonrpc"] {
+            let error = parse_destination(url).unwrap_err();
+            assert!(error.contains("no http:// or https:// scheme"), "{}: {}", url, error);
+            assert!(error.contains(&format!("https://{}", url)), "{}: {}", url, error);
+        }
+        assert!(parse_destination("ws://localhost:8545").unwrap_err().contains("unsupported scheme ws://"));
+    }
+
+    #[test]
+    fn join_keeps_the_base_path() {
+        let base = Url::parse("https://gw.example.com/tron/v1").unwrap();
+        assert_eq!(
+            join_destination(&base, "//wallet//getnowblock?visible=true").unwrap().as_str(),
+            "https://gw.example.com/tron/v1/wallet/getnowblock?visible=true"
+        );
+        assert_eq!(join_destination(&base, "").unwrap().as_str(), "https://gw.example.com/tron/v1");
+    }
+}
@@ -0,0 +1,101 @@
+use std::fmt;
+use std::time::Duration;
+
+use axum::{
+    http::{header, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Value};
+
+use crate::JSON_CONTENT_TYPE;
+
+/// A failure the proxy reports to the client as a JSON-RPC error body.
+#[derive(Debug, Clone)]
+pub enum ProxyError {
+    /// The upstream couldn't be reached or dropped the connection
+    UpstreamUnreachable(String),
+    /// The upstream didn't answer within the request's timeout
+    UpstreamTimeout(Duration),
+    /// The upstream's response body couldn't be read
+    Decode(String),
+    /// The proxy couldn't build the request or response
+    Translation(String),
+    /// An HTTP method the proxy doesn't forward
+    MethodNotAllowed(Method),
+    /// An X-Tron-Network header naming no configured upstream
+    UnknownNetwork(String),
+    /// A request body over `--max-request-bytes`
+    RequestTooLarge(usize),
+}
+
+impl ProxyError {
+    /// Classifies a failed upstream request that was given `timeout` to answer.
+    pub fn upstream(e: reqwest::Error, timeout: Duration) -> Self {
+        if e.is_timeout() {
+            ProxyError::UpstreamTimeout(timeout)
+        } else {
+            ProxyError::UpstreamUnreachable(e.to_string())
+        }
+    }
+
+    /// Classifies a failure reading the upstream's response body, which counts against the
+    /// same timeout as the request.
+    pub fn decode(e: reqwest::Error, timeout: Duration) -> Self {
+        if e.is_timeout() {
+            ProxyError::UpstreamTimeout(timeout)
+        } else {
+            ProxyError::Decode(e.to_string())
+        }
+    }
+
+    pub fn translation(e: impl fmt::Display) -> Self {
+        ProxyError::Translation(e.to_string())
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            ProxyError::UpstreamUnreachable(_) | ProxyError::Decode(_) => StatusCode::BAD_GATEWAY,
+            ProxyError::UpstreamTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ProxyError::Translation(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ProxyError::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            ProxyError::UnknownNetwork(_) => StatusCode::NOT_FOUND,
+            ProxyError::RequestTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+
+    /// The JSON-RPC error body reported for this failure.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "error": { "code": self.code(), "message": self.to_string() },
+            "id": null,
+        })
+    }
+
+    pub fn code(&self) -> i64 {
+        match self {
+            ProxyError::MethodNotAllowed(_) | ProxyError::UnknownNetwork(_) | ProxyError::RequestTooLarge(_) => -32600,
+            _ => -32603,
+        }
+    }
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyError::UpstreamUnreachable(e) => write!(f, "upstream unreachable: {}", e),
+            ProxyError::UpstreamTimeout(timeout) => write!(f, "upstream timed out after {}ms", timeout.as_millis()),
+            ProxyError::Decode(e) => write!(f, "failed to read upstream response: {}", e),
+            ProxyError::Translation(e) => write!(f, "failed to translate request: {}", e),
+            ProxyError::MethodNotAllowed(method) => write!(f, "HTTP method {} is not supported", method),
+            ProxyError::UnknownNetwork(network) => write!(f, "unknown network '{}'", network),
+            ProxyError::RequestTooLarge(limit) => write!(f, "request body exceeds {} bytes", limit),
+        }
+    }
+}
+
+impl IntoResponse for ProxyError {
+    fn into_response(self) -> Response {
+        (self.status(), [(header::CONTENT_TYPE, JSON_CONTENT_TYPE)], self.to_json().to_string()).into_response()
+    }
+}
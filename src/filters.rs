@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde_json::{json, Map, Value};
+use tracing::{debug, info, warn};
+
+use crate::logs::{get_logs, normalize_filter_addresses};
+use crate::overrides::OverrideError;
+use crate::{parse_quantity, upstream_call, AppState};
+
+/// Most blocks a block filter reports per poll; a filter that falls further behind skips
+/// ahead to the newest blocks
+pub const MAX_BLOCK_FILTER_WINDOW: u64 = 1000;
+
+/// Concurrent block lookups while answering a block filter poll
+const BLOCK_FETCH_CONCURRENCY: usize = 8;
+
+/// What a filter watches for, and how far it has been polled.
+#[derive(Debug, Clone)]
+enum FilterKind {
+    /// Logs matching `criteria` (an eth_getLogs filter object) in blocks from `next_block` on
+    Logs { criteria: Map<String, Value>, next_block: u64 },
+    /// Hashes of blocks from `next_block` on
+    Blocks { next_block: u64 },
+    /// Hashes of transactions broadcast through the proxy since `since`
+    PendingTransactions { since: Instant },
+}
+
+#[derive(Debug)]
+struct Filter {
+    kind: FilterKind,
+    last_polled: Instant,
+}
+
+/// Filters installed with `eth_newFilter` and friends, keyed by filter id. TRON's jsonrpc has
+/// no filter API, so the proxy keeps the cursors and answers polls with `eth_getLogs` and
+/// block lookups.
+#[derive(Default)]
+pub struct FilterStore {
+    filters: Mutex<HashMap<String, Filter>>,
+    next_id: AtomicU64,
+}
+
+impl FilterStore {
+    fn install(&self, kind: FilterKind, idle_timeout: Duration) -> String {
+        let id = format!("0x{:x}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+
+        let mut filters = self.filters.lock().unwrap();
+        prune(&mut filters, idle_timeout);
+        filters.insert(
+            id.clone(),
+            Filter {
+                kind,
+                last_polled: Instant::now(),
+            },
+        );
+
+        id
+    }
+
+    /// Returns a filter's state and marks it as polled, or `None` if it doesn't exist.
+    fn poll(&self, id: &str, idle_timeout: Duration) -> Option<FilterKind> {
+        let mut filters = self.filters.lock().unwrap();
+        prune(&mut filters, idle_timeout);
+
+        let filter = filters.get_mut(id)?;
+        filter.last_polled = Instant::now();
+        Some(filter.kind.clone())
+    }
+
+    fn advance(&self, id: &str, next: u64) {
+        if let Some(filter) = self.filters.lock().unwrap().get_mut(id) {
+            match &mut filter.kind {
+                FilterKind::Logs { next_block, .. } | FilterKind::Blocks { next_block } => {
+                    *next_block = (*next_block).max(next)
+                }
+                FilterKind::PendingTransactions { .. } => {}
+            }
+        }
+    }
+
+    fn advance_pending(&self, id: &str, next: Instant) {
+        if let Some(filter) = self.filters.lock().unwrap().get_mut(id)
+            && let FilterKind::PendingTransactions { since } = &mut filter.kind
+        {
+            *since = (*since).max(next);
+        }
+    }
+
+    fn uninstall(&self, id: &str, idle_timeout: Duration) -> bool {
+        let mut filters = self.filters.lock().unwrap();
+        prune(&mut filters, idle_timeout);
+        filters.remove(id).is_some()
+    }
+}
+
+/// Drops filters nobody has polled within the idle timeout.
+fn prune(filters: &mut HashMap<String, Filter>, idle_timeout: Duration) {
+    filters.retain(|id, filter| {
+        let live = filter.last_polled.elapsed() < idle_timeout;
+        if !live {
+            debug!("Expiring idle filter {}", id);
+        }
+        live
+    });
+}
+
+fn filter_not_found() -> OverrideError {
+    (-32000, "filter not found".to_string())
+}
+
+pub async fn latest_block(state: &AppState) -> Result<u64, OverrideError> {
+    let latest = upstream_call(state, "eth_blockNumber", json!([]))
+        .await
+        .map_err(|e| (-32603, format!("failed to fetch latest block: {}", e)))?;
+    parse_quantity(&latest).ok_or_else(|| (-32603, format!("invalid eth_blockNumber result {}", latest)))
+}
+
+fn filter_id(params: Option<&Value>) -> Result<&str, OverrideError> {
+    params
+        .and_then(|params| params.get(0))
+        .and_then(Value::as_str)
+        .ok_or_else(|| (-32602, "expected a filter id".to_string()))
+}
+
+pub async fn new_filter(state: &AppState, params: Option<&Value>) -> Result<Value, OverrideError> {
+    let Some(mut criteria) = params.and_then(|params| params.get(0)).and_then(Value::as_object).cloned() else {
+        return Err((-32602, "eth_newFilter expects a filter object".to_string()));
+    };
+    normalize_filter_addresses(&state.address_cache, &mut criteria);
+
+    // Like geth, a filter reports logs from blocks after its creation unless it names an
+    // explicit starting block
+    let next_block = match criteria.get("fromBlock").and_then(parse_quantity) {
+        Some(from_block) => from_block,
+        None => latest_block(state).await? + 1,
+    };
+
+    let id = state.filters.install(
+        FilterKind::Logs {
+            criteria,
+            next_block,
+        },
+        state.filter_timeout,
+    );
+    info!("Installed log filter {} starting at block {}", id, next_block);
+
+    Ok(json!(id))
+}
+
+pub async fn new_block_filter(state: &AppState, _params: Option<&Value>) -> Result<Value, OverrideError> {
+    let next_block = latest_block(state).await? + 1;
+    let id = state.filters.install(FilterKind::Blocks { next_block }, state.filter_timeout);
+    info!("Installed block filter {} starting at block {}", id, next_block);
+
+    Ok(json!(id))
+}
+
+pub async fn new_pending_transaction_filter(state: &AppState, _params: Option<&Value>) -> Result<Value, OverrideError> {
+    let id = state.filters.install(
+        FilterKind::PendingTransactions { since: Instant::now() },
+        state.filter_timeout,
+    );
+    warn!(
+        "Installed pending transaction filter {}; it only reports transactions broadcast through this proxy",
+        id
+    );
+
+    Ok(json!(id))
+}
+
+async fn block_hash(state: &AppState, number: u64) -> anyhow::Result<Value> {
+    let block = upstream_call(state, "eth_getBlockByNumber", json!([format!("0x{:x}", number), false])).await?;
+    block
+        .get("hash")
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("block {} not found", number))
+}
+
+pub async fn get_filter_changes(state: &AppState, params: Option<&Value>) -> Result<Value, OverrideError> {
+    let id = filter_id(params)?;
+    let kind = state.filters.poll(id, state.filter_timeout).ok_or_else(filter_not_found)?;
+
+    match kind {
+        FilterKind::Logs { criteria, next_block } => {
+            let mut to_block = latest_block(state).await?;
+            if let Some(last_block) = criteria.get("toBlock").and_then(parse_quantity) {
+                to_block = to_block.min(last_block);
+            }
+            if next_block > to_block {
+                return Ok(json!([]));
+            }
+
+            let mut range = criteria;
+            range.insert("fromBlock".to_string(), json!(format!("0x{:x}", next_block)));
+            range.insert("toBlock".to_string(), json!(format!("0x{:x}", to_block)));
+            let logs = get_logs(state, range).await?;
+
+            debug!("Filter {} polled blocks {}..={}", id, next_block, to_block);
+            state.filters.advance(id, to_block + 1);
+
+            Ok(logs)
+        }
+        FilterKind::Blocks { mut next_block } => {
+            let latest = latest_block(state).await?;
+            if next_block > latest {
+                return Ok(json!([]));
+            }
+
+            if latest - next_block + 1 > MAX_BLOCK_FILTER_WINDOW {
+                let skipped_to = latest + 1 - MAX_BLOCK_FILTER_WINDOW;
+                warn!(
+                    "Block filter {} is {} blocks behind, skipping blocks {}..{}",
+                    id,
+                    latest - next_block + 1,
+                    next_block,
+                    skipped_to
+                );
+                next_block = skipped_to;
+            }
+
+            let hashes: Vec<Value> = stream::iter(next_block..=latest)
+                .map(|number| block_hash(state, number))
+                .buffered(BLOCK_FETCH_CONCURRENCY)
+                .try_collect()
+                .await
+                .map_err(|e| (-32603, format!("failed to fetch blocks: {}", e)))?;
+
+            debug!("Filter {} polled blocks {}..={}", id, next_block, latest);
+            state.filters.advance(id, latest + 1);
+
+            Ok(json!(hashes))
+        }
+        FilterKind::PendingTransactions { since } => {
+            let polled_at = Instant::now();
+            let hashes = state.pending_txs.broadcast_since(since);
+            // A transaction recorded while listing may be reported again next time, which is
+            // harmless for a hash filter; one is never skipped
+            state.filters.advance_pending(id, polled_at);
+
+            Ok(json!(hashes))
+        }
+    }
+}
+
+/// Returns every log matching a log filter's criteria, from its original `fromBlock`,
+/// regardless of how far it has been polled.
+pub async fn get_filter_logs(state: &AppState, params: Option<&Value>) -> Result<Value, OverrideError> {
+    let id = filter_id(params)?;
+    // Like geth, only log filters have logs; block and pending filters are "not found"
+    let Some(FilterKind::Logs { criteria, .. }) = state.filters.poll(id, state.filter_timeout) else {
+        return Err(filter_not_found());
+    };
+
+    debug!("Replaying log filter {}", id);
+    get_logs(state, criteria).await
+}
+
+pub async fn uninstall_filter(state: &AppState, params: Option<&Value>) -> Result<Value, OverrideError> {
+    let id = filter_id(params)?;
+    Ok(json!(state.filters.uninstall(id, state.filter_timeout)))
+}
@@ -0,0 +1,306 @@
+use serde_json::{json, Map, Value};
+use tracing::{debug, info};
+
+use crate::address::{normalize_address, AddressCache};
+use crate::filters::latest_block;
+use crate::overrides::OverrideError;
+use crate::{normalize_logs, parse_quantity, upstream_call, AppState};
+
+/// Rewrites the addresses in the filter object of an `eth_getLogs` or `eth_newFilter`
+/// request, or an `eth_subscribe("logs")` one. Returns whether anything changed.
+pub fn normalize_filter_params(cache: &AddressCache, method: &str, params: &mut Value) -> bool {
+    let criteria = match method {
+        "eth_subscribe" if params.get(0).and_then(Value::as_str) == Some("logs") => params.get_mut(1),
+        "eth_subscribe" => None,
+        _ => params.get_mut(0),
+    };
+    match criteria.and_then(Value::as_object_mut) {
+        Some(criteria) => normalize_filter_addresses(cache, criteria),
+        None => false,
+    }
+}
+
+/// Rewrites the addresses in a log filter object: the `address` criterion, one address or
+/// an array of them, to EVM hex, and address topics as `normalize_topic_addresses` does.
+/// Returns whether anything changed.
+pub fn normalize_filter_addresses(cache: &AddressCache, criteria: &mut Map<String, Value>) -> bool {
+    let mut changed = match criteria.get_mut("address") {
+        Some(Value::Array(addresses)) => addresses
+            .iter_mut()
+            .fold(false, |changed, address| normalize_filter_address(cache, address) | changed),
+        Some(address) => normalize_filter_address(cache, address),
+        None => false,
+    };
+    changed |= normalize_topic_addresses(cache, criteria);
+    changed
+}
+
+fn normalize_filter_address(cache: &AddressCache, address: &mut Value) -> bool {
+    let Some(text) = address.as_str() else {
+        return false;
+    };
+    let Some(normalized) = normalize_address(cache, text).filter(|normalized| normalized != text) else {
+        return false;
+    };
+
+    debug!("Rewrote filter address {} to {}", text, normalized);
+    *address = json!(normalized);
+    true
+}
+
+/// Rewrites topics given as addresses (TRON base58 `T...`, `41`-prefixed hex, or 20-byte EVM
+/// hex) into the 32-byte left-padded words indexed address parameters are logged as,
+/// including inside OR arrays. 32-byte topics and `null` wildcards are left alone. Returns
+/// whether any topic changed.
+fn normalize_topic_addresses(cache: &AddressCache, criteria: &mut Map<String, Value>) -> bool {
+    let Some(Value::Array(topics)) = criteria.get_mut("topics") else {
+        return false;
+    };
+
+    let mut changed = false;
+    for topic in topics.iter_mut() {
+        match topic {
+            Value::Array(alternatives) => {
+                for alternative in alternatives.iter_mut() {
+                    changed |= pad_address_topic(cache, alternative);
+                }
+            }
+            topic => changed |= pad_address_topic(cache, topic),
+        }
+    }
+    changed
+}
+
+fn pad_address_topic(cache: &AddressCache, topic: &mut Value) -> bool {
+    let Some(text) = topic.as_str() else {
+        return false;
+    };
+    // Already a full 32-byte word
+    if text.len() == 66 {
+        return false;
+    }
+    let Some(address) = normalize_address(cache, text) else {
+        return false;
+    };
+
+    let padded = format!("0x{:0>64}", &address[2..]);
+    debug!("Rewrote address topic {} to {}", text, padded);
+    *topic = json!(padded);
+    true
+}
+
+/// Returns the block range of an `eth_getLogs` filter object when it spans more than
+/// `--logs-block-window` blocks and has to be split, or `None` when it can go upstream as
+/// one query. An open-ended range is resolved against the latest block once, so every
+/// chunk covers the same range.
+pub async fn chunked_range(state: &AppState, criteria: &Map<String, Value>) -> Result<Option<(u64, u64)>, OverrideError> {
+    if criteria.contains_key("blockHash") {
+        return Ok(None);
+    }
+
+    // A query starting at `latest` covers a single block
+    let Some(from_block) = criteria.get("fromBlock").and_then(parse_quantity) else {
+        return Ok(None);
+    };
+    let to_block = match criteria.get("toBlock") {
+        None | Some(Value::Null) => latest_block(state).await?,
+        Some(Value::String(tag)) if tag == "latest" => latest_block(state).await?,
+        Some(to_block) => match parse_quantity(to_block) {
+            Some(to_block) => to_block,
+            None => return Ok(None),
+        },
+    };
+
+    if to_block < from_block || to_block - from_block < state.logs_block_window {
+        return Ok(None);
+    }
+    Ok(Some((from_block, to_block)))
+}
+
+/// Fetches the logs for blocks `from_block..=to_block` in sequential queries of at most
+/// `--logs-block-window` blocks, failing once more than `--logs-max-results` logs are found
+/// rather than returning a truncated list.
+pub async fn get_logs_chunked(
+    state: &AppState,
+    criteria: Map<String, Value>,
+    from_block: u64,
+    to_block: u64,
+) -> Result<Value, OverrideError> {
+    info!(
+        "Splitting eth_getLogs over blocks {}..={} into chunks of {} blocks",
+        from_block, to_block, state.logs_block_window
+    );
+
+    let mut logs = Vec::new();
+    let mut chunk_start = from_block;
+    loop {
+        let chunk_end = to_block.min(chunk_start.saturating_add(state.logs_block_window - 1));
+
+        let mut chunk = criteria.clone();
+        chunk.insert("fromBlock".to_string(), json!(format!("0x{:x}", chunk_start)));
+        chunk.insert("toBlock".to_string(), json!(format!("0x{:x}", chunk_end)));
+
+        let chunk_logs = upstream_call(state, "eth_getLogs", json!([chunk]))
+            .await
+            .map_err(|e| (-32603, format!("failed to fetch logs for blocks {}..={}: {}", chunk_start, chunk_end, e)))?;
+        let Value::Array(chunk_logs) = chunk_logs else {
+            return Err((-32603, format!("invalid eth_getLogs result {}", chunk_logs)));
+        };
+        logs.extend(chunk_logs);
+
+        if logs.len() > state.logs_max_results {
+            return Err((
+                -32005,
+                format!(
+                    "query returned more than {} results; narrow the block range or raise --logs-max-results",
+                    state.logs_max_results
+                ),
+            ));
+        }
+
+        if chunk_end == to_block {
+            break;
+        }
+        chunk_start = chunk_end + 1;
+    }
+
+    let mut logs = Value::Array(logs);
+    normalize_logs(&mut logs);
+    Ok(logs)
+}
+
+/// Fetches and normalizes the logs matching an `eth_getLogs` filter object, splitting large
+/// block ranges like client queries.
+pub async fn get_logs(state: &AppState, mut criteria: Map<String, Value>) -> Result<Value, OverrideError> {
+    normalize_filter_addresses(&state.address_cache, &mut criteria);
+
+    if let Some((from_block, to_block)) = chunked_range(state, &criteria).await? {
+        return get_logs_chunked(state, criteria, from_block, to_block).await;
+    }
+
+    let mut logs = match upstream_call(state, "eth_getLogs", json!([criteria])).await {
+        Ok(logs) => logs,
+        Err(e) if criteria.contains_key("blockHash") => {
+            debug!("Upstream rejected a blockHash eth_getLogs filter, retrying by number: {}", e);
+            return get_logs_by_block_hash(state, criteria).await;
+        }
+        Err(e) => return Err((-32603, format!("failed to fetch logs: {}", e))),
+    };
+    normalize_logs(&mut logs);
+    Ok(logs)
+}
+
+/// Answers an `eth_getLogs` filter naming a `blockHash`, which some TRON nodes reject, by
+/// looking the block's number up and querying `fromBlock == toBlock` with the same address
+/// and topics criteria.
+pub async fn get_logs_by_block_hash(state: &AppState, mut criteria: Map<String, Value>) -> Result<Value, OverrideError> {
+    let Some(block_hash) = criteria.remove("blockHash") else {
+        return Err((-32602, "expected a blockHash filter".to_string()));
+    };
+
+    let block = upstream_call(state, "eth_getBlockByHash", json!([block_hash, false]))
+        .await
+        .map_err(|e| (-32603, format!("failed to fetch block {}: {}", block_hash, e)))?;
+    if block.is_null() {
+        return Err((-32000, "unknown block".to_string()));
+    }
+    let Some(number) = block.get("number").and_then(parse_quantity) else {
+        return Err((-32603, format!("block {} has no valid number", block_hash)));
+    };
+
+    let number = json!(format!("0x{:x}", number));
+    criteria.insert("fromBlock".to_string(), number.clone());
+    criteria.insert("toBlock".to_string(), number);
+
+    let mut logs = upstream_call(state, "eth_getLogs", json!([criteria]))
+        .await
+        .map_err(|e| (-32603, format!("failed to fetch logs: {}", e)))?;
+    normalize_logs(&mut logs);
+    Ok(logs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::to_base58_address;
+    use crate::test_support::{MockUpstream, TestProxy};
+
+    const ADDRESS: &str = "0x95222290dd7278aa3ddd389cc1e1d165cc4bafe5";
+
+    #[test]
+    fn normalizes_a_single_filter_address() {
+        let cache = AddressCache::default();
+        let base58 = to_base58_address(ADDRESS).unwrap();
+        let mut params = json!([{ "address": base58, "topics": [] }]);
+
+        assert!(normalize_filter_params(&cache, "eth_getLogs", &mut params));
+        assert_eq!(params, json!([{ "address": ADDRESS, "topics": [] }]));
+    }
+
+    #[test]
+    fn normalizes_each_address_of_an_array() {
+        let cache = AddressCache::default();
+        let base58 = to_base58_address(ADDRESS).unwrap();
+        let tron_hex = format!("41{}", &ADDRESS[2..]);
+        let mut params = json!([{ "address": [base58, tron_hex, ADDRESS] }]);
+
+        assert!(normalize_filter_params(&cache, "eth_newFilter", &mut params));
+        assert_eq!(params, json!([{ "address": [ADDRESS, ADDRESS, ADDRESS] }]));
+    }
+
+    #[test]
+    fn leaves_evm_addresses_and_word_topics_alone() {
+        let cache = AddressCache::default();
+        let topic = format!("0x{:0>64}", &ADDRESS[2..]);
+        let mut params = json!([{ "address": ADDRESS, "topics": [topic, null] }]);
+        let original = params.clone();
+
+        assert!(!normalize_filter_params(&cache, "eth_getLogs", &mut params));
+        assert_eq!(params, original);
+    }
+
+    #[test]
+    fn normalizes_the_criteria_of_a_logs_subscription() {
+        let cache = AddressCache::default();
+        let base58 = to_base58_address(ADDRESS).unwrap();
+        let mut params = json!(["logs", { "address": base58 }]);
+
+        assert!(normalize_filter_params(&cache, "eth_subscribe", &mut params));
+        assert_eq!(params, json!(["logs", { "address": ADDRESS }]));
+
+        let mut params = json!(["newHeads"]);
+        assert!(!normalize_filter_params(&cache, "eth_subscribe", &mut params));
+    }
+
+    #[tokio::test]
+    async fn get_logs_sends_the_address_upstream_as_evm_hex() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!([]))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+        let base58 = to_base58_address(ADDRESS).unwrap();
+
+        let response = proxy
+            .rpc("eth_getLogs", json!([{ "fromBlock": "0x1", "toBlock": "0x2", "address": base58 }]))
+            .await;
+
+        assert_eq!(response["result"], json!([]));
+        let sent = upstream.hits().iter().map(|hit| hit.json()).find(|request| request["method"] == "eth_getLogs").unwrap();
+        assert_eq!(sent["params"][0]["address"], ADDRESS);
+    }
+
+    #[test]
+    fn pads_address_topics_given_singly_in_or_arrays_and_around_wildcards() {
+        let cache = AddressCache::default();
+        let base58 = to_base58_address(ADDRESS).unwrap();
+        let tron_hex = format!("0x41{}", &ADDRESS[2..]);
+        let word = format!("0x{:0>64}", &ADDRESS[2..]);
+        let transfer = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+        let mut params = json!([{ "topics": [transfer, null, base58.clone()] }]);
+
+        assert!(normalize_filter_params(&cache, "eth_getLogs", &mut params));
+        assert_eq!(params, json!([{ "topics": [transfer, null, word] }]));
+
+        let mut params = json!([{ "topics": [transfer, [base58, tron_hex, ADDRESS, word.clone()], null] }]);
+        assert!(normalize_filter_params(&cache, "eth_getLogs", &mut params));
+        assert_eq!(params, json!([{ "topics": [transfer, [word, word, word, word], null] }]));
+    }
+}
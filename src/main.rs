@@ -1,15 +1,74 @@
+use anyhow::Context;
 use axum::{
-    extract::{Query, State},
-    http::{HeaderMap, Method, StatusCode},
-    response::Response,
+    body::{Body, Bytes},
+    extract::{rejection::BytesRejection, ConnectInfo, DefaultBodyLimit, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
     routing::{get, post},
-    Router,
+    Json, Router,
 };
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::de::IgnoredAny;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use base64::Engine;
+use sha3::{Digest, Keccak256};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::OnceCell;
 use tracing::{debug, error, info, warn};
+use url::Url;
+
+mod access_log;
+mod address;
+mod batch;
+mod block_tags;
+mod canned;
+mod chain_params;
+mod coalesce;
+mod compression;
+mod confirmation;
+mod destination;
+mod error;
+mod filters;
+mod logs;
+mod native;
+mod overrides;
+mod path_rewrite;
+mod pending;
+mod raw_tx;
+mod signer;
+mod stats;
+mod trace;
+mod validate;
+mod ws;
+
+#[cfg(test)]
+mod test_support;
+
+use access_log::{AccessLog, RpcMethod};
+use address::{
+    convert_eth_to_tron_address, normalize_address, normalize_address_strings, parse_address, repair_address_fields, AddressCache,
+};
+use canned::{CannedReply, CannedResponses};
+use chain_params::ChainParameterCache;
+use coalesce::InFlightRequests;
+use destination::{join_destination, parse_base_url, parse_destination, Destination};
+use error::ProxyError;
+use filters::FilterStore;
+use overrides::OverrideError;
+use path_rewrite::PathRewrites;
+use pending::PendingTxStore;
+use raw_tx::RawTransaction;
+use signer::Signer;
+use stats::Stats;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -18,25 +77,554 @@ struct Args {
     #[arg(short, long)]
     port: u16,
 
-    /// Destination URL to forward requests to
-    #[arg(short, long)]
-    dest: String,
+    /// Destination URL to forward requests to, with or without its /jsonrpc suffix
+    #[arg(short, long, value_parser = parse_destination)]
+    dest: Destination,
+
+    /// Base URL of the node's HTTP API (/wallet/..., /walletsolidity/...). Defaults to --dest
+    /// without its /jsonrpc suffix
+    #[arg(long, value_name = "URL", value_parser = parse_base_url)]
+    wallet_dest: Option<Url>,
+
+    /// Additional named upstream, as NAME=URL (repeatable). Selected by POSTing to /NAME or
+    /// with an X-Tron-Network header; other requests go to --dest
+    #[arg(long = "upstream", value_name = "NAME=URL", value_parser = parse_upstream)]
+    upstreams: Vec<(String, Destination)>,
+
+    /// Send requests for a method to a named upstream, as METHOD=NAME (repeatable). METHOD is
+    /// an exact name or a prefix ending in `*` (`eth_send*`); NAME is an --upstream name or
+    /// `default` for --dest. The first matching route wins; unrouted methods go to --dest
+    #[arg(long = "route", value_name = "METHOD=NAME", value_parser = parse_route)]
+    routes: Vec<(String, String)>,
+
+    /// Another node serving the same network as --dest (repeatable). Requests for the default
+    /// upstream take turns between --dest and these
+    #[arg(long = "dest-replica", value_name = "URL", value_parser = parse_destination)]
+    dest_replicas: Vec<Destination>,
+
+    /// Send every eth_sendRawTransaction/eth_sendTransaction from one sender to the same
+    /// --dest/--dest-replica node, so it sees that account's transactions in order
+    #[arg(long, requires = "dest_replicas")]
+    sticky_upstream_by_sender: bool,
+
+    /// Rewrite the `to` address of call objects, given as `from=to` address pairs (repeatable)
+    #[arg(long = "rewrite-to", alias = "rewrite-to-address", value_parser = parse_rewrite_pair)]
+    rewrite_to: Vec<(String, String)>,
+
+    /// Attach CORS headers to responses and answer preflight requests (allows any origin)
+    #[arg(long)]
+    cors: bool,
+
+    /// Enable CORS for a specific origin instead of any origin
+    #[arg(long, value_parser = parse_cors_origin)]
+    cors_origin: Option<HeaderValue>,
+
+    /// Maximum concurrent receipt lookups when emulating eth_getBlockReceipts
+    #[arg(long, default_value_t = 8)]
+    receipts_concurrency: usize,
+
+    /// Decimals of the `value` field in eth_call, eth_estimateGas and eth_sendTransaction
+    /// requests; values are scaled down to SUN (6 decimals). The default reads them as wei
+    #[arg(long, value_name = "N", default_value_t = 18, value_parser = clap::value_parser!(u32).range(6..=38))]
+    value_decimals: u32,
+
+    /// Gas reported per unit of TRON energy in receipt and block `gasUsed`/`cumulativeGasUsed`,
+    /// gas estimates and transaction gas limits
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    energy_to_gas_ratio: u64,
+
+    /// Gas reported per bandwidth point in receipt and block `gasUsed`/`cumulativeGasUsed`
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    bandwidth_to_gas_ratio: u64,
+
+    /// Map the `finalized` block tag to the latest solidified block instead of `latest`
+    #[arg(long)]
+    map_finalized_to_solidified: bool,
+
+    /// Resolve the `finalized` and `safe` tags to the latest block minus this many blocks
+    /// (default when given without a value: 19, TRON's solidification depth)
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "19",
+        conflicts_with = "map_finalized_to_solidified"
+    )]
+    finalized_offset: Option<u64>,
+
+    /// Exit at startup if the destination doesn't answer the connectivity probe
+    #[arg(long)]
+    require_upstream: bool,
+
+    /// Report eth_syncing as syncing when the latest upstream block is more than this many minutes old
+    #[arg(long, value_name = "MINUTES")]
+    probe_sync: Option<u64>,
+
+    /// Answer eth_syncing from the node's own sync status (wallet/getnodeinfo)
+    #[arg(long, conflicts_with = "probe_sync")]
+    report_syncing: bool,
+
+    /// Forward web3_clientVersion instead of answering with the proxy's own version string
+    #[arg(long)]
+    client_version_passthrough: bool,
+
+    /// Chain id to report (decimal or 0x-hex) instead of asking the upstream
+    #[arg(long, value_parser = parse_chain_id)]
+    chain_id: Option<u64>,
+
+    /// Serve the proxy under this path prefix (e.g. /tron-rpc); other paths return 404
+    #[arg(long, alias = "prefix-path", value_parser = parse_base_path)]
+    base_path: Option<String>,
+
+    /// Reject every method that broadcasts a transaction
+    #[arg(long)]
+    read_only: bool,
+
+    /// Only accept these JSON-RPC methods (repeatable); all others are rejected
+    #[arg(long = "allow-method", value_name = "METHOD")]
+    allowed_methods: Vec<String>,
+
+    /// Forward this method instead of answering it locally (repeatable)
+    #[arg(long = "disable-override", value_name = "METHOD")]
+    disabled_overrides: Vec<String>,
+
+    /// Forward this anvil_/hardhat_/evm_ method instead of rejecting it (repeatable)
+    #[arg(long = "forward-cheatcode", value_name = "METHOD")]
+    forwarded_cheatcodes: Vec<String>,
+
+    /// Owner address sent as eth_call's `from` when the caller omits it or uses the zero address
+    #[arg(long, value_name = "ADDRESS", value_parser = parse_owner_address)]
+    default_owner: Option<String>,
+
+    /// Give up on an upstream request after this many milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 30000)]
+    upstream_timeout_ms: u64,
+
+    /// Upstream timeout for eth_sendRawTransaction broadcasts, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 120000)]
+    broadcast_timeout_ms: u64,
+
+    /// Upstream timeout for a method, as METHOD=MILLISECONDS (repeatable)
+    #[arg(long = "method-timeout", value_name = "METHOD=MS", value_parser = parse_method_timeout)]
+    method_timeouts: Vec<(String, Duration)>,
+
+    /// Remove filters that haven't been polled for this many seconds
+    #[arg(long, value_name = "SECS", default_value_t = 300)]
+    filter_timeout: u64,
+
+    /// Hex private key used to sign eth_sendTransaction requests (with --sign-transactions)
+    #[arg(long, value_name = "HEX", value_parser = parse_signer_key)]
+    signer_key: Option<Arc<Signer>>,
+
+    /// Sign eth_sendTransaction requests with --signer-key instead of rejecting them
+    #[arg(long, requires = "signer_key")]
+    sign_transactions: bool,
+
+    /// What to do with eth_call's state override parameter, which TRON doesn't support
+    #[arg(long, value_enum, default_value_t = StateOverrides::Strip)]
+    state_overrides: StateOverrides,
+
+    /// Show addresses in logged request and response bodies in one format (evm by default),
+    /// without changing what's forwarded
+    #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "evm")]
+    normalize_addresses_in_logs: Option<AddressLogFormat>,
+
+    /// Check block, transaction, receipt and log responses against the shapes clients expect
+    /// and log what doesn't match (`strict` answers with an error instead)
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, default_missing_value = "warn")]
+    validate_responses: Option<ResponseValidation>,
+
+    /// Shorthand for --state-overrides reject
+    #[arg(long, conflicts_with = "state_overrides")]
+    reject_state_overrides: bool,
+
+    /// Highest fee_limit (in SUN) a broadcast transaction may carry
+    #[arg(long, value_name = "SUN")]
+    fee_limit_cap: Option<u64>,
+
+    /// Multiplier applied to gasLimit * gasPrice to get a transaction's fee_limit in SUN
+    #[arg(long, value_name = "FACTOR", default_value = "1", value_parser = parse_fee_limit_scale)]
+    fee_limit_scale: FeeLimitScale,
+
+    /// What to do with a broadcast whose fee_limit exceeds --fee-limit-cap
+    #[arg(long, value_enum, default_value_t = FeeLimitPolicy::Warn, requires = "fee_limit_cap")]
+    fee_limit_exceeded: FeeLimitPolicy,
+
+    /// Answer eth_getProof with empty proofs instead of an error
+    #[arg(long)]
+    fake_proofs: bool,
+
+    /// Split eth_getLogs queries spanning more than this many blocks into sequential queries
+    #[arg(long, value_name = "BLOCKS", default_value_t = 5000, value_parser = clap::value_parser!(u64).range(1..))]
+    logs_block_window: u64,
+
+    /// Most logs a split eth_getLogs query may return before it fails
+    #[arg(long, value_name = "COUNT", default_value_t = 10000)]
+    logs_max_results: usize,
+
+    /// JSON file of canned responses: an array of {"method", "params"?, "result" | "error"}
+    /// rules answered without contacting the upstream
+    #[arg(long, value_name = "FILE")]
+    canned: Option<PathBuf>,
+
+    /// JSON file of path rewrite rules: an array of {"match", "replace"} prefixes applied, first
+    /// match wins, to the path of forwarded non-JSON-RPC requests before it's joined with the
+    /// destination
+    #[arg(long, value_name = "FILE")]
+    path_rewrites: Option<PathBuf>,
+
+    /// Bridge methods with this prefix to the node's HTTP API, e.g. with `tron_`,
+    /// tron_getNowBlock becomes POST /wallet/getnowblock
+    #[arg(long, value_name = "PREFIX")]
+    native_prefix: Option<String>,
+
+    /// Bridge a method to a node HTTP API path, as METHOD=PATH (repeatable), e.g.
+    /// tron_getSolidityNowBlock=/walletsolidity/getnowblock
+    #[arg(long = "native-method", value_name = "METHOD=PATH", value_parser = parse_native_method)]
+    native_methods: Vec<(String, String)>,
+
+    /// How often to refresh the chain parameters (energy price and limits) from the node
+    #[arg(long, value_name = "SECS", default_value_t = 600, value_parser = clap::value_parser!(u64).range(1..))]
+    chain_params_refresh: u64,
+
+    /// Accept JSON-RPC over WebSocket on GET /, with eth_subscribe emulation
+    #[arg(long)]
+    ws: bool,
+
+    /// How often WebSocket subscriptions poll the upstream for new blocks
+    #[arg(long, value_name = "SECS", default_value_t = 3, value_parser = clap::value_parser!(u64).range(1..))]
+    ws_poll_interval: u64,
+
+    /// Tunnel WebSocket connections to this upstream ws endpoint instead of emulating
+    /// subscriptions (implies --ws)
+    #[arg(long, value_name = "URL")]
+    ws_dest: Option<String>,
+
+    /// Answer eth_getBalance with the account's balanceOf on this TRC20 contract instead of
+    /// its TRX balance
+    #[arg(long, value_name = "CONTRACT", value_parser = parse_contract_address)]
+    balance_token: Option<String>,
+
+    /// Append one JSON line per HTTP request (time, client, method, status, size, duration)
+    /// to this file
+    #[arg(long, value_name = "PATH")]
+    access_log: Option<PathBuf>,
+
+    /// How to treat POST bodies that aren't valid JSON-RPC
+    #[arg(long, value_enum, default_value_t = Strictness::Strict)]
+    strictness: Strictness,
+
+    /// Reject JSON-RPC requests without `"jsonrpc": "2.0"` (including ones that omit it) with
+    /// a -32600 error, whatever --strictness says
+    #[arg(long)]
+    strict_jsonrpc: bool,
+
+    /// Trust X-Forwarded-For from clients and append to it, instead of replacing it with the
+    /// client's address (for running behind another proxy)
+    #[arg(long)]
+    trust_forwarded: bool,
+
+    /// Reject request bodies larger than this many bytes with a 413
+    #[arg(long, value_name = "BYTES", default_value_t = 8 * 1024 * 1024)]
+    max_request_bytes: usize,
+
+    /// Reject JSON-RPC batches with more entries than this; also caps the concurrent receipt
+    /// lookups of eth_getBlockReceipts emulation
+    #[arg(long, value_name = "N", default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    max_batch_size: u64,
+
+    /// Gzip responses of 1 KiB or more for clients that accept it
+    #[arg(long)]
+    compress_responses: bool,
+
+    /// Authorization sent to the upstream when the client sends none, as basic:USER:PASS
+    /// or bearer:TOKEN
+    #[arg(long, value_name = "SCHEME:CREDENTIALS", value_parser = parse_upstream_auth)]
+    upstream_auth: Option<reqwest::header::HeaderValue>,
+
+    /// Replace the client's Authorization header with --upstream-auth instead of forwarding it
+    #[arg(long, requires = "upstream_auth")]
+    override_auth: bool,
+
+    /// How often tron_waitForConfirmation polls the node, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    confirmation_poll_ms: u64,
+
+    /// Longest tron_waitForConfirmation waits, and its default timeout, in seconds
+    #[arg(long, value_name = "SECS", default_value_t = 120)]
+    confirmation_max_wait: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum AddressLogFormat {
+    /// Lowercase 0x-prefixed 20-byte hex
+    Evm,
+    /// TRON base58check (T...)
+    Base58,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ResponseValidation {
+    /// Log a warning naming the missing or invalid fields
+    Warn,
+    /// Also replace the response with a -32603 error naming them
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum StateOverrides {
+    /// Drop the overrides and run the call against current state
+    Strip,
+    /// Answer with a JSON-RPC error
+    Reject,
 }
 
 #[derive(Clone)]
 struct AppState {
     client: reqwest::Client,
-    destination: String,
+    destination: Url,
+    /// Base URL of the node's HTTP API, from --wallet-dest or derived from `destination`
+    wallet_dest: Url,
+    /// Label of this upstream in metrics: `default` for --dest (and its replicas), or the
+    /// --upstream name
+    upstream_name: String,
+    /// States for the named upstreams, each with its own destination and caches. Empty in
+    /// the named states themselves
+    upstreams: Arc<HashMap<String, AppState>>,
+    /// --route rules: method pattern and upstream name, in order
+    routes: Arc<Vec<(String, String)>>,
+    /// --dest (with --wallet-dest) and its --dest-replica nodes, when there are replicas.
+    /// Empty otherwise, and for named upstreams
+    dest_pool: Arc<Vec<Destination>>,
+    /// Turn counter for spreading requests over `dest_pool`
+    next_pool_member: Arc<AtomicUsize>,
+    /// Pin broadcasts to a `dest_pool` node by sender
+    sticky_upstream_by_sender: bool,
+    /// Canonical EVM `to` address -> replacement address in TRON form
+    to_rewrites: Arc<HashMap<String, String>>,
+    /// Transactions broadcast through this proxy
+    pending_txs: Arc<PendingTxStore>,
+    /// Value of Access-Control-Allow-Origin, when CORS is enabled
+    cors_origin: Option<HeaderValue>,
+    /// Maximum concurrent receipt lookups when emulating eth_getBlockReceipts
+    receipts_concurrency: usize,
+    /// Gas per unit of energy and per bandwidth point in reported gas usage
+    gas_ratios: GasRatios,
+    /// Decimals of request `value`s, scaled down to SUN
+    value_decimals: u32,
+    /// Source of ids for requests that arrive without one
+    next_request_id: Arc<AtomicU64>,
+    /// Resolve `finalized` to the latest solidified block
+    map_finalized_to_solidified: bool,
+    /// Resolve `finalized`/`safe` to this many blocks below the latest block
+    finalized_offset: Option<u64>,
+    /// Lowest block the upstream can serve, resolved on first use of `earliest`
+    earliest_block: Arc<OnceCell<u64>>,
+    /// Maximum age of the latest block before eth_syncing reports syncing
+    probe_sync: Option<Duration>,
+    /// Answer eth_syncing from the node's reported sync status
+    report_syncing: bool,
+    /// Upstream chain id, fetched on first use
+    chain_id: Arc<OnceCell<u64>>,
+    /// `--chain-id`, written over `chainId` fields in transaction and block results
+    chain_id_override: Option<u64>,
+    /// Upstream web3_clientVersion, fetched on first use
+    upstream_client_version: Arc<OnceCell<String>>,
+    /// Forward web3_clientVersion untouched
+    client_version_passthrough: bool,
+    stats: Arc<Stats>,
+    /// Reject methods in WRITE_METHODS
+    read_only: bool,
+    /// When set, the only methods accepted
+    allowed_methods: Option<Arc<HashSet<String>>>,
+    /// Methods in overrides::METHOD_OVERRIDES that are forwarded instead
+    disabled_overrides: Arc<HashSet<String>>,
+    /// Cheatcode methods forwarded despite CHEATCODE_PREFIXES
+    forwarded_cheatcodes: Arc<HashSet<String>>,
+    /// Recently normalized client-supplied addresses
+    address_cache: Arc<AddressCache>,
+    /// Placeholder eth_call owner address, in TRON form
+    default_owner: Option<String>,
+    /// Upstream timeout for methods without their own
+    upstream_timeout: Duration,
+    /// Upstream timeout for eth_sendRawTransaction
+    broadcast_timeout: Duration,
+    /// Per-method upstream timeouts
+    method_timeouts: Arc<HashMap<String, Duration>>,
+    /// Emulated eth_newFilter filters
+    filters: Arc<FilterStore>,
+    /// Forwarded reads awaiting their upstream response, shared by identical requests
+    in_flight: Arc<InFlightRequests>,
+    /// Idle time after which a filter is removed
+    filter_timeout: Duration,
+    /// Key for eth_sendTransaction and eth_coinbase
+    signer: Option<Arc<Signer>>,
+    /// Sign eth_sendTransaction requests instead of rejecting them
+    sign_transactions: bool,
+    /// Handling of eth_call state overrides
+    state_overrides: StateOverrides,
+    /// Largest fee_limit in SUN for broadcasts
+    fee_limit_cap: Option<u64>,
+    /// gasLimit * gasPrice to fee_limit multiplier
+    fee_limit_scale: FeeLimitScale,
+    /// Handling of broadcasts over the fee_limit cap
+    fee_limit_exceeded: FeeLimitPolicy,
+    /// Answer eth_getProof with empty proofs
+    fake_proofs: bool,
+    /// Largest block range sent upstream in one eth_getLogs query
+    logs_block_window: u64,
+    /// Result cap for split eth_getLogs queries
+    logs_max_results: usize,
+    /// Interval between new-block polls of WebSocket subscriptions
+    ws_poll_interval: Duration,
+    /// Upstream WebSocket endpoint that client sockets are tunnelled to
+    ws_dest: Option<String>,
+    /// --upstream-auth, for the upstream connections the HTTP client doesn't make
+    upstream_auth: Option<HeaderValue>,
+    /// TRC20 contract whose balanceOf answers eth_getBalance
+    balance_token: Option<String>,
+    /// Request log from --access-log
+    access_log: Option<Arc<AccessLog>>,
+    /// Handling of POST bodies that aren't valid JSON-RPC
+    strictness: Strictness,
+    /// Whether requests must carry `"jsonrpc": "2.0"`
+    strict_jsonrpc: bool,
+    /// Whether inbound X-Forwarded-For headers are kept and appended to
+    trust_forwarded: bool,
+    /// Largest request body accepted
+    max_request_bytes: usize,
+    /// Largest batch answered, and the cap on receipt lookup fan-out
+    max_batch_size: usize,
+    /// Whether responses are gzipped for clients that accept it
+    compress_responses: bool,
+    /// Whether clients' Authorization headers are dropped in favour of --upstream-auth
+    override_auth: bool,
+    /// Address format for bodies in debug logs, from --normalize-addresses-in-logs
+    log_address_format: Option<AddressLogFormat>,
+    /// Shape checking of enhanced responses, from --validate-responses
+    validate_responses: Option<ResponseValidation>,
+    /// Delay between tron_waitForConfirmation polls
+    confirmation_poll_interval: Duration,
+    /// Upper bound on tron_waitForConfirmation timeouts
+    confirmation_max_wait: Duration,
+    /// Fixed answers from --canned
+    canned: Option<Arc<CannedResponses>>,
+    /// Path prefixes replaced by --path-rewrites
+    path_rewrites: Option<Arc<PathRewrites>>,
+    /// Method prefix bridged to the node's /wallet API
+    native_prefix: Option<String>,
+    /// Methods bridged to explicit node HTTP API paths
+    native_methods: Arc<HashMap<String, String>>,
+    /// Energy price and limits, fetched at startup and refreshed periodically
+    chain_params: Arc<ChainParameterCache>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Strictness {
+    /// Answer with a -32700 parse error or -32600 invalid request error
+    Strict,
+    /// Forward the body to the upstream as-is
+    Passthrough,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FeeLimitPolicy {
+    /// Forward it with a warning. The signed payload is forwarded as-is, so the fee_limit
+    /// can't be lowered
+    Warn,
+    /// Answer with a JSON-RPC error
+    Reject,
+}
+
+/// A `--fee-limit-scale` factor as an exact decimal fraction, so fee limits are worked out
+/// in whole SUN without going through floating point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FeeLimitScale {
+    numerator: u128,
+    /// A power of ten, at most 10^18
+    denominator: u128,
+}
+
+impl FeeLimitScale {
+    /// Scales an amount of SUN, dropping any fraction of a SUN. Saturates instead of
+    /// overflowing.
+    fn apply(self, sun: u128) -> u128 {
+        let whole = (sun / self.denominator).saturating_mul(self.numerator);
+        // Both factors are below 10^18 * 2^64, so the product fits
+        let fraction = (sun % self.denominator) * self.numerator / self.denominator;
+        whole.saturating_add(fraction)
+    }
 }
 
+/// Header naming the upstream a request goes to, when `--upstream` is used
+const NETWORK_HEADER: &str = "x-tron-network";
+
+/// Upstream name of --dest, in routes and metrics
+const DEFAULT_UPSTREAM: &str = "default";
+
+/// Header carrying the chain of client addresses to the upstream
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Methods answered locally with a zero quantity instead of being forwarded
+const SYNTHETIC_ZERO_METHODS: &[&str] = &[
+    // TRON has no account nonces
+    "eth_getTransactionCount",
+    // TRON has no priority fee market
+    "eth_maxPriorityFeePerGas",
+];
+
+/// Hop-by-hop headers (RFC 7230 section 6.1), which apply to a single connection and must
+/// not be copied between the client and upstream connections
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Content type of every JSON-RPC response the proxy sends, local or forwarded
+pub const JSON_CONTENT_TYPE: &str = "application/json; charset=utf-8";
+
+/// Prefixes of local-devnet cheatcode methods, which a live TRON network can't serve
+const CHEATCODE_PREFIXES: &[&str] = &["anvil_", "hardhat_", "evm_"];
+
+/// Selector of the ERC20/TRC20 `balanceOf(address)` function
+const BALANCE_OF_SELECTOR: &str = "0x70a08231";
+
+const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+/// Names under which object-shaped eth_call/eth_estimateGas params carry the call object,
+/// the block and the state overrides
+const NAMED_CALL_MEMBERS: &[&str] = &["transaction", "tx", "call"];
+const NAMED_BLOCK_MEMBERS: &[&str] = &["block", "blockNumber", "blockTag"];
+const NAMED_STATE_OVERRIDE_MEMBERS: &[&str] = &["stateOverrides", "stateOverride"];
+
+/// Fields of a per-account eth_call state override
+const STATE_OVERRIDE_FIELDS: &[&str] = &["balance", "nonce", "code", "state", "stateDiff"];
+
+/// Methods that broadcast transactions, rejected in read-only mode
+const WRITE_METHODS: &[&str] = &[
+    "eth_sendRawTransaction",
+    "eth_sendTransaction",
+];
+
+/// Only `method` is required for the proxy to apply its rewrites; some clients omit `jsonrpc`.
 #[derive(Debug, Serialize, Deserialize)]
 struct JsonRpcRequest {
+    #[serde(default = "default_jsonrpc_version")]
     jsonrpc: String,
     method: String,
     params: Option<Value>,
     id: Option<Value>,
 }
 
+fn default_jsonrpc_version() -> String {
+    "2.0".to_string()
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct JsonRpcResponse {
     jsonrpc: String,
@@ -48,6 +636,40 @@ struct JsonRpcResponse {
     id: Option<Value>,
 }
 
+impl JsonRpcResponse {
+    fn success(id: Option<Value>, result: Value) -> Self {
+        JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            result: Some(result),
+            error: None,
+            id,
+        }
+    }
+
+    fn failure(id: Option<Value>, code: i64, message: impl Into<String>) -> Self {
+        JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            result: None,
+            error: Some(json!({ "code": code, "message": message.into() })),
+            id,
+        }
+    }
+
+    /// Builds the HTTP response for a JSON-RPC response produced by the proxy itself.
+    fn into_http(self) -> Result<Response<String>, ProxyError> {
+        let response_body = serde_json::to_string(&self)
+            .map_err(ProxyError::translation)?;
+
+        debug!("Local response body: {}", response_body);
+
+        Response::builder()
+            .status(200)
+            .header(header::CONTENT_TYPE, JSON_CONTENT_TYPE)
+            .body(response_body)
+            .map_err(ProxyError::translation)
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Initialize tracing
@@ -56,461 +678,4625 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     let args = Args::parse();
+    let state = build_state(&args)?;
 
-    info!("Starting proxy server on port {} forwarding to {}", args.port, args.dest);
+    for upstream_state in std::iter::once(&state).chain(state.upstreams.values()) {
+        if let Err(e) = probe_upstream(upstream_state).await {
+            if args.require_upstream {
+                return Err(e.context(format!("upstream {} failed the startup probe", upstream_state.destination)));
+            }
+            warn!("Upstream {} failed the startup probe: {:#}", upstream_state.destination, e);
+        }
 
-    let state = AppState {
-        client: reqwest::Client::new(),
-        destination: args.dest,
-    };
+        chain_params::refresh_chain_parameters(upstream_state).await;
+        chain_params::spawn_refresh(upstream_state.clone(), Duration::from_secs(args.chain_params_refresh));
+    }
 
-    let app = Router::new()
-        .route("/", post(handle_post_request))
-        .route("/", get(handle_get_request))
-        .fallback(handle_fallback)
-        .with_state(state);
+    let app = build_router(state, &args);
 
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", args.port)).await?;
     info!("Proxy server listening on {}", listener.local_addr()?);
 
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
 
     Ok(())
 }
 
-async fn handle_post_request(
-    State(state): State<AppState>,
-    headers: HeaderMap,
-    body: String,
-) -> Result<Response<String>, StatusCode> {
-    info!("Received POST request, body length: {}", body.len());
-    debug!("Request body: {}", body);
-    debug!("Request headers:");
-    for (name, value) in &headers {
-        debug!("  {}: {:?}", name.as_str(), value);
+/// Builds the proxy's state, and those of the named upstreams, from the command line.
+fn build_state(args: &Args) -> anyhow::Result<AppState> {
+    let wallet_dest = args.wallet_dest.clone().unwrap_or_else(|| args.dest.api_root.clone());
+    info!("Starting proxy server on port {} forwarding to {}", args.port, args.dest.jsonrpc);
+    info!("Node HTTP API calls go to {}", wallet_dest);
+
+    if let Some(chain_id) = args.chain_id {
+        info!("Reporting chain id {} without contacting the upstream", chain_id);
     }
 
-    // Try to parse as JSON-RPC request
-    match serde_json::from_str::<JsonRpcRequest>(&body) {
-        Ok(mut rpc_request) => {
-            info!("Parsed JSON-RPC request: method={}", rpc_request.method);
+    if args.read_only {
+        info!("Read-only mode: rejecting {}", WRITE_METHODS.join(", "));
+    }
 
-            // Handle special cases
-            match rpc_request.method.as_str() {
-                "eth_getTransactionCount" => {
-                    info!("Overriding eth_getTransactionCount with 0x0");
-                    let response = JsonRpcResponse {
-                        jsonrpc: "2.0".to_string(),
-                        result: Some(json!("0x0")),
-                        error: None,
-                        id: rpc_request.id,
-                    };
-                    let response_body = serde_json::to_string(&response)
-                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !args.allowed_methods.is_empty() {
+        info!("Only accepting methods: {}", args.allowed_methods.join(", "));
+    }
 
-                    debug!("eth_getTransactionCount response body: {}", response_body);
+    for method in &args.disabled_overrides {
+        if overrides::is_registered(method) {
+            info!("Forwarding {} instead of answering it locally", method);
+        } else {
+            warn!("--disable-override {}: no such override", method);
+        }
+    }
 
-                    return Ok(Response::builder()
-                        .status(200)
-                        .header("content-type", "application/json")
-                        .body(response_body)
-                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?);
-                }
-                "eth_call" => {
-                    info!("Normalizing eth_call parameters");
-                    if let Some(params) = &mut rpc_request.params {
-                        if let Some(params_array) = params.as_array_mut() {
-                            if let Some(first_param) = params_array.get_mut(0) {
-                                if let Some(obj) = first_param.as_object_mut() {
-                                    // If both "input" and "data" exist, remove "input"
-                                    if obj.contains_key("input") && obj.contains_key("data") {
-                                        obj.remove("input");
-                                        info!("Removed 'input' field (keeping 'data')");
-                                    }
-                                    // If only "input" exists, rename to "data"
-                                    else if let Some(input_value) = obj.remove("input") {
-                                        obj.insert("data".to_string(), input_value);
-                                        info!("Renamed 'input' field to 'data'");
-                                    }
-
-                                    // Remove chainId field as TRON API doesn't support it
-                                    if obj.remove("chainId").is_some() {
-                                        info!("Removed 'chainId' field for TRON API compatibility");
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                "eth_estimateGas" => {
-                    info!("Processing eth_estimateGas parameters");
-                    debug!("Original eth_estimateGas params: {}", serde_json::to_string_pretty(&rpc_request.params).unwrap_or_else(|_| "Failed to serialize".to_string()));
-
-                    if let Some(params) = &mut rpc_request.params {
-                        if let Some(params_array) = params.as_array_mut() {
-                            // eth_estimateGas should only have one parameter (the transaction object)
-                            // Remove any extra parameters (i.e., "pending") that might cause issues
-                            if params_array.len() > 1 {
-                                info!("eth_estimateGas has {} parameters, truncating to 1", params_array.len());
-                                params_array.truncate(1);
-                            }
+    if !args.dest_replicas.is_empty() {
+        info!(
+            "Spreading requests over {} and replicas {}{}",
+            args.dest.jsonrpc,
+            args.dest_replicas.iter().map(|replica| replica.jsonrpc.as_str()).collect::<Vec<_>>().join(", "),
+            if args.sticky_upstream_by_sender { ", broadcasts pinned by sender" } else { "" }
+        );
+    }
 
-                            if let Some(first_param) = params_array.get_mut(0) {
-                                if let Some(obj) = first_param.as_object_mut() {
-                                    // Log all fields in the transaction object
-                                    debug!("eth_estimateGas transaction object fields: {:?}", obj.keys().collect::<Vec<_>>());
-
-                                    // // Convert Ethereum addresses to Tron format (add 0x41 prefix)
-                                    // if let Some(from_value) = obj.get("from").cloned() {
-                                    //     if let Some(from_str) = from_value.as_str() {
-                                    //         if let Some(tron_from) = convert_eth_to_tron_address(from_str) {
-                                    //             obj.insert("from".to_string(), json!(tron_from));
-                                    //             info!("Converted 'from' address from {} to {}", from_str, tron_from);
-                                    //         }
-                                    //     }
-                                    // }
-
-                                    // if let Some(to_value) = obj.get("to").cloned() {
-                                    //     if let Some(to_str) = to_value.as_str() {
-                                    //         if let Some(tron_to) = convert_eth_to_tron_address(to_str) {
-                                    //             obj.insert("to".to_string(), json!(tron_to));
-                                    //             info!("Converted 'to' address from {} to {}", to_str, tron_to);
-                                    //         }
-                                    //     } else if to_value.is_null() {
-                                    //         info!("'to' field is null (contract creation), leaving as-is");
-                                    //     }
-                                    // }
-
-                                    // Apply similar normalizations as eth_call
-                                    // If both "input" and "data" exist, remove "input"
-                                    if obj.contains_key("input") && obj.contains_key("data") {
-                                        obj.remove("input");
-                                        info!("Removed 'input' field (keeping 'data')");
-                                    }
-                                    // If only "input" exists, rename to "data"
-                                    else if let Some(input_value) = obj.remove("input") {
-                                        obj.insert("data".to_string(), input_value);
-                                        info!("Renamed 'input' field to 'data'");
-                                    }
-
-                                    // Remove chainId field as TRON API doesn't support it
-                                    if obj.remove("chainId").is_some() {
-                                        info!("Removed 'chainId' field for TRON API compatibility");
-                                    }
-
-                                    // // Remove gas and gasPrice fields as they might cause issues
-                                    // if obj.remove("gas").is_some() {
-                                    //     info!("Removed 'gas' field for TRON API compatibility");
-                                    // }
-                                    // if obj.remove("gasPrice").is_some() {
-                                    //     info!("Removed 'gasPrice' field for TRON API compatibility");
-                                    // }
-                                }
-                            }
-                        }
-                    }
+    for (from, to) in &args.rewrite_to {
+        info!("Rewriting calls to {} -> {}", from, to);
+    }
 
-                    debug!("Normalized eth_estimateGas params: {}", serde_json::to_string_pretty(&rpc_request.params).unwrap_or_else(|_| "Failed to serialize".to_string()));
-                    debug!("Final eth_estimateGas request being sent to Tron API: {}", serde_json::to_string(&rpc_request).unwrap_or_else(|_| "Failed to serialize".to_string()));
-                }
-                _ => {}
-            }
+    // Default headers only fill in what a request doesn't set, so a forwarded client
+    // Authorization wins unless --override-auth drops it
+    let mut default_headers = reqwest::header::HeaderMap::new();
+    if let Some(auth) = args.upstream_auth.clone() {
+        info!("Authenticating to upstreams with --upstream-auth{}", if args.override_auth { ", replacing client credentials" } else { "" });
+        default_headers.insert(reqwest::header::AUTHORIZATION, auth);
+    }
 
-            // Forward the (possibly modified) request
-            let modified_body = serde_json::to_string(&rpc_request)
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let client = reqwest::Client::builder()
+        // Compressed upstream responses are decoded transparently so enhancement sees plain JSON
+        .gzip(true)
+        .deflate(true)
+        .default_headers(default_headers)
+        .build()?;
 
-            debug!("Modified request body being sent to destination: {}", modified_body);
+    let mut state = AppState {
+        client,
+        destination: args.dest.jsonrpc.clone(),
+        wallet_dest: wallet_dest.clone(),
+        upstream_name: DEFAULT_UPSTREAM.to_string(),
+        upstreams: Arc::new(HashMap::new()),
+        routes: Arc::new(args.routes.clone()),
+        dest_pool: Arc::new(if args.dest_replicas.is_empty() {
+            Vec::new()
+        } else {
+            let dest = Destination { jsonrpc: args.dest.jsonrpc.clone(), api_root: wallet_dest };
+            std::iter::once(dest).chain(args.dest_replicas.iter().cloned()).collect()
+        }),
+        next_pool_member: Arc::new(AtomicUsize::new(0)),
+        sticky_upstream_by_sender: args.sticky_upstream_by_sender,
+        to_rewrites: Arc::new(args.rewrite_to.iter().cloned().collect()),
+        pending_txs: Arc::new(PendingTxStore::default()),
+        cors_origin: args.cors_origin.clone().or_else(|| args.cors.then(|| HeaderValue::from_static("*"))),
+        receipts_concurrency: args.receipts_concurrency.max(1),
+        gas_ratios: GasRatios { energy: args.energy_to_gas_ratio, bandwidth: args.bandwidth_to_gas_ratio },
+        value_decimals: args.value_decimals,
+        next_request_id: Arc::new(AtomicU64::new(1)),
+        map_finalized_to_solidified: args.map_finalized_to_solidified,
+        finalized_offset: args.finalized_offset,
+        earliest_block: Arc::new(OnceCell::new()),
+        probe_sync: args.probe_sync.map(|minutes| Duration::from_secs(minutes * 60)),
+        report_syncing: args.report_syncing,
+        chain_id: Arc::new(OnceCell::new_with(args.chain_id)),
+        chain_id_override: args.chain_id,
+        upstream_client_version: Arc::new(OnceCell::new()),
+        client_version_passthrough: args.client_version_passthrough,
+        stats: Arc::new(Stats::default()),
+        read_only: args.read_only,
+        allowed_methods: (!args.allowed_methods.is_empty())
+            .then(|| Arc::new(args.allowed_methods.iter().cloned().collect())),
+        disabled_overrides: Arc::new(args.disabled_overrides.iter().cloned().collect()),
+        forwarded_cheatcodes: Arc::new(args.forwarded_cheatcodes.iter().cloned().collect()),
+        address_cache: Arc::new(AddressCache::default()),
+        default_owner: args.default_owner.clone(),
+        upstream_timeout: Duration::from_millis(args.upstream_timeout_ms),
+        broadcast_timeout: Duration::from_millis(args.broadcast_timeout_ms),
+        method_timeouts: Arc::new(args.method_timeouts.iter().cloned().collect()),
+        filters: Arc::new(FilterStore::default()),
+        in_flight: Arc::new(InFlightRequests::default()),
+        filter_timeout: Duration::from_secs(args.filter_timeout),
+        signer: args.signer_key.clone(),
+        sign_transactions: args.sign_transactions,
+        state_overrides: if args.reject_state_overrides { StateOverrides::Reject } else { args.state_overrides },
+        fee_limit_cap: args.fee_limit_cap,
+        fee_limit_scale: args.fee_limit_scale,
+        fee_limit_exceeded: args.fee_limit_exceeded,
+        fake_proofs: args.fake_proofs,
+        logs_block_window: args.logs_block_window,
+        logs_max_results: args.logs_max_results,
+        ws_poll_interval: Duration::from_secs(args.ws_poll_interval),
+        ws_dest: args.ws_dest.clone(),
+        upstream_auth: args.upstream_auth.as_ref().and_then(|auth| HeaderValue::from_bytes(auth.as_bytes()).ok()),
+        balance_token: args.balance_token.clone(),
+        access_log: args.access_log.as_deref().map(AccessLog::open).transpose()?.map(Arc::new),
+        strictness: args.strictness,
+        strict_jsonrpc: args.strict_jsonrpc,
+        trust_forwarded: args.trust_forwarded,
+        max_request_bytes: args.max_request_bytes,
+        max_batch_size: args.max_batch_size as usize,
+        compress_responses: args.compress_responses,
+        override_auth: args.override_auth,
+        log_address_format: args.normalize_addresses_in_logs,
+        validate_responses: args.validate_responses,
+        confirmation_poll_interval: Duration::from_millis(args.confirmation_poll_ms),
+        confirmation_max_wait: Duration::from_secs(args.confirmation_max_wait),
+        native_prefix: args.native_prefix.clone(),
+        native_methods: Arc::new(args.native_methods.iter().cloned().collect()),
+        chain_params: Arc::new(ChainParameterCache::default()),
+        canned: args.canned.as_deref().map(CannedResponses::load).transpose()?.map(Arc::new),
+        path_rewrites: args.path_rewrites.as_deref().map(PathRewrites::load).transpose()?.map(Arc::new),
+    };
 
-            forward_request(&state, Method::POST, &headers, &modified_body, &rpc_request.method).await
-        }
-        Err(_) => {
-            // Not a valid JSON-RPC request, forward as-is
-            info!("Not a JSON-RPC request, forwarding as-is");
-            forward_request(&state, Method::POST, &headers, &body, "unknown").await
+    // Named upstreams share everything but the destination, what's cached about it, and the
+    // filters and transactions that belong to its chain
+    let upstreams: HashMap<String, AppState> = args
+        .upstreams
+        .iter()
+        .map(|(name, dest)| {
+            info!("Serving upstream {} at /{} -> {}", name, name, dest.jsonrpc);
+            let upstream_state = AppState {
+                wallet_dest: dest.api_root.clone(),
+                upstream_name: name.clone(),
+                destination: dest.jsonrpc.clone(),
+                earliest_block: Arc::new(OnceCell::new()),
+                chain_id: Arc::new(OnceCell::new()),
+                // --chain-id and --dest-replica belong to the default upstream
+                chain_id_override: None,
+                dest_pool: Arc::new(Vec::new()),
+                upstream_client_version: Arc::new(OnceCell::new()),
+                chain_params: Arc::new(ChainParameterCache::default()),
+                filters: Arc::new(FilterStore::default()),
+                pending_txs: Arc::new(PendingTxStore::default()),
+                // --ws-dest belongs to the default upstream
+                ws_dest: None,
+                ..state.clone()
+            };
+            (name.clone(), upstream_state)
+        })
+        .collect();
+    state.upstreams = Arc::new(upstreams);
+
+    for (pattern, name) in state.routes.iter() {
+        if name != DEFAULT_UPSTREAM && !state.upstreams.contains_key(name) {
+            anyhow::bail!("--route {}={}: no upstream named {}", pattern, name, name);
         }
+        info!("Routing {} to upstream {}", pattern, name);
+    }
+
+    if state.dest_pool.is_empty() {
+        state.stats.register_upstream(&state.upstream_name, state.destination.as_str());
+    }
+    for member in state.dest_pool.iter() {
+        state.stats.register_upstream(&state.upstream_name, member.jsonrpc.as_str());
+    }
+    for upstream_state in state.upstreams.values() {
+        state.stats.register_upstream(&upstream_state.upstream_name, upstream_state.destination.as_str());
+    }
+
+    if let Some(origin) = &state.cors_origin {
+        info!("CORS enabled for origin {}", origin.to_str().unwrap_or_default());
     }
+    if state.compress_responses {
+        info!("Compressing responses for clients that accept gzip");
+    }
+
+    Ok(state)
 }
 
-async fn handle_get_request(
+/// Builds the router serving the proxy's endpoints.
+fn build_router(state: AppState, args: &Args) -> Router {
+    let root_get = if let Some(ws_dest) = &state.ws_dest {
+        info!("Tunnelling WebSocket connections on / to {}", ws_dest);
+        get(ws::handle_root_get)
+    } else if args.ws {
+        info!("Accepting WebSocket connections on /");
+        get(ws::handle_root_get)
+    } else {
+        get(handle_get_request)
+    };
+
+    let mut routes = Router::new()
+        .route("/", post(handle_post_request))
+        .route("/", root_get.clone())
+        .route("/stats", get(handle_stats))
+        .route("/metrics", get(handle_metrics))
+        .fallback(handle_fallback);
+
+    for (name, upstream_state) in state.upstreams.iter() {
+        routes = routes.route(
+            &format!("/{}", name),
+            post(handle_post_request)
+                .get(handle_get_request)
+                .with_state(upstream_state.clone()),
+        );
+    }
+
+    // Under a base path, only the prefixed routes exist and everything else is a 404
+    let app = match &args.base_path {
+        Some(base_path) => {
+            info!("Serving under base path {}", base_path);
+            Router::new()
+                .route(&format!("{}/", base_path), root_get.post(handle_post_request))
+                .nest(base_path, routes)
+        }
+        None => routes,
+    };
+
+    app.layer(DefaultBodyLimit::max(args.max_request_bytes))
+        .layer(middleware::from_fn_with_state(state.clone(), compression::compression_middleware))
+        .layer(middleware::from_fn_with_state(state.clone(), cors_middleware))
+        .layer(middleware::from_fn_with_state(state.clone(), access_log::access_log_middleware))
+        .with_state(state)
+}
+
+/// Answers CORS preflight requests and adds CORS headers to every response when enabled.
+async fn cors_middleware(
     State(state): State<AppState>,
-    headers: HeaderMap,
-    query: Query<HashMap<String, String>>,
-) -> Result<Response<String>, StatusCode> {
-    info!("Received GET request with {} query parameters", query.len());
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(origin) = state.cors_origin.clone() else {
+        return next.run(request).await;
+    };
 
-    // Build query string
-    let query_string = if query.is_empty() {
-        String::new()
+    let allow_headers = request.headers()
+        .get(header::ACCESS_CONTROL_REQUEST_HEADERS)
+        .cloned()
+        .unwrap_or(HeaderValue::from_static("content-type, authorization"));
+
+    let mut response = if request.method() == Method::OPTIONS {
+        debug!("Answering CORS preflight request");
+        StatusCode::NO_CONTENT.into_response()
     } else {
-        format!("?{}",
-            query.iter()
-                .map(|(k, v)| format!("{}={}", k, v))
-                .collect::<Vec<_>>()
-                .join("&")
-        )
+        next.run(request).await
     };
 
-    forward_get_request(&state, &headers, &query_string).await
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+    headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static("GET, POST, OPTIONS"));
+    headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+    if origin != "*" {
+        headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+    }
+
+    response
 }
 
-async fn handle_fallback(
+/// Takes the body as bytes, so bodies that aren't UTF-8 reach the proxy instead of being
+/// rejected by the extractor. Those can't be JSON-RPC: they get a parse error, or under
+/// `--strictness passthrough` are forwarded verbatim.
+async fn handle_post_request(
     State(state): State<AppState>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-) -> Result<Response<String>, StatusCode> {
-    info!("Received fallback request");
-    forward_get_request(&state, &headers, "").await
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Response<Body>, ProxyError> {
+    let body = read_body(&state, body)?;
+    let body = match String::from_utf8(Vec::from(body)) {
+        Ok(body) => {
+            let response = handle_post_text(State(state), ConnectInfo(remote_addr), headers, body).await?;
+            return Ok(response.map(Body::from));
+        }
+        Err(e) => e,
+    };
+
+    warn!("Received POST body that isn't UTF-8 ({} bytes): {}", body.as_bytes().len(), body.utf8_error());
+    if state.strictness == Strictness::Strict {
+        let response = JsonRpcResponse::failure(Some(Value::Null), -32700, format!("parse error: {}", body.utf8_error()));
+        return Ok(response.into_http()?.map(Body::from));
+    }
+
+    let state = select_upstream(state, &headers)?;
+    let headers = with_forwarded_for(&state, headers, remote_addr);
+    forward_raw(&state, Method::POST, &headers, &state.destination, Bytes::from(body.into_bytes())).await
 }
 
-async fn forward_request(
-    state: &AppState,
-    method: Method,
-    headers: &HeaderMap,
-    body: &str,
-    rpc_method: &str,
-) -> Result<Response<String>, StatusCode> {
-    let url = &state.destination;
+/// Unwraps a request body, reporting one over `--max-request-bytes` as too large.
+fn read_body(state: &AppState, body: Result<Bytes, BytesRejection>) -> Result<Bytes, ProxyError> {
+    body.map_err(|rejection| {
+        if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            warn!("Rejected request body over {} bytes", state.max_request_bytes);
+            ProxyError::RequestTooLarge(state.max_request_bytes)
+        } else {
+            ProxyError::translation(rejection.body_text())
+        }
+    })
+}
 
-    info!("Forwarding {} request to {}", method, url);
+/// Handles a POST body as JSON-RPC text; shared by HTTP, batch entries and WebSocket messages.
+async fn handle_post_text(
+    State(state): State<AppState>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response<String>, ProxyError> {
+    let state = select_upstream(state, &headers)?;
 
-    let mut request_builder = match method {
-        Method::POST => state.client.post(url),
-        Method::GET => state.client.get(url),
-        _ => return Err(StatusCode::METHOD_NOT_ALLOWED),
-    };
+    info!("Received POST request, body length: {}", body.len());
+    debug!("Request body: {}", loggable_body(&state, &body));
+    debug!("Request headers:");
+    for (name, value) in &headers {
+        debug!("  {}: {:?}", name.as_str(), value);
+    }
 
-    // Copy relevant headers (excluding problematic ones)
-    for (name, value) in headers {
-        let header_name_str = name.as_str();
+    let rpc_method = state.access_log.is_some().then(|| access_log::rpc_method_label(&body));
+    let response = dispatch_post_request(state, remote_addr, headers, body).await;
 
-        // Skip headers that might cause issues with Tron API
-        if header_name_str.eq_ignore_ascii_case("content-length") {
-            debug!("Skipping problematic header: {}", header_name_str);
-            continue;
+    // Tell the access log which method was answered
+    response.map(|mut response| {
+        if let Some(rpc_method) = rpc_method {
+            response.extensions_mut().insert(RpcMethod(rpc_method));
         }
+        response
+    })
+}
+
+/// Answers a POST body: a batch, a notification or a single request.
+async fn dispatch_post_request(
+    state: AppState,
+    remote_addr: SocketAddr,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response<String>, ProxyError> {
+    if let Some(entries) = batch::parse_batch(&body) {
+        return batch::handle_batch(state, remote_addr, headers, entries).await;
+    }
 
-        if let Ok(header_name) = reqwest::header::HeaderName::from_bytes(name.as_str().as_bytes()) {
-            if let Ok(header_value) = reqwest::header::HeaderValue::from_bytes(value.as_bytes()) {
-                debug!("Forwarding header: {} = {:?}", header_name_str, header_value);
-                request_builder = request_builder.header(header_name, header_value);
+    let request = serde_json::from_str::<Value>(&body)
+        .ok()
+        .filter(|request| request.get("method").is_some());
+    if let Some(mut request) = request {
+        match request.get("id").cloned() {
+            // Notifications are handled like any request, but nothing is sent back
+            None => {
+                match answer_request(state, remote_addr, headers, body).await {
+                    Ok(_) => debug!("Discarding the response to a notification"),
+                    Err(e) => warn!("Notification failed: {}", e),
+                }
+                return Ok(empty_response());
+            }
+            // Ids the upstream may not echo verbatim are swapped for a generated one, and
+            // restored in the response
+            Some(id) if !upstream_preserves_id(&id) => {
+                if let Some(request) = request.as_object_mut() {
+                    request.remove("id");
+                }
+                let response = answer_request(state, remote_addr, headers, request.to_string()).await?;
+                return Ok(readdress_response(response, &id));
             }
+            Some(_) => {}
         }
     }
 
-    if method == Method::POST {
-        request_builder = request_builder.body(body.to_string());
+    answer_request(state, remote_addr, headers, body).await
+}
+
+/// Handles a single JSON-RPC request (or forwards a body that isn't one).
+async fn answer_request(
+    state: AppState,
+    remote_addr: SocketAddr,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response<String>, ProxyError> {
+    let headers = with_forwarded_for(&state, headers, remote_addr);
+
+    if state.strict_jsonrpc && let Some((id, message)) = jsonrpc_version_error(&body) {
+        warn!("Rejected request from {}: {}", remote_addr, message);
+        return JsonRpcResponse::failure(Some(id), -32600, message).into_http();
     }
 
-    match request_builder.send().await {
-        Ok(response) => {
-            let status = response.status();
-            let response_headers = response.headers().clone();
+    // Try to parse as JSON-RPC request
+    match serde_json::from_str::<JsonRpcRequest>(&body) {
+        Ok(mut rpc_request) if state.strictness == Strictness::Passthrough || rpc_request.jsonrpc == "2.0" => {
+            info!("Parsed JSON-RPC request: method={}", rpc_request.method);
 
-            match response.text().await {
-                Ok(mut response_body) => {
-                    info!("Received response from destination, status: {}, body length: {}",
-                          status, response_body.len());
+            if let Some(params) = rpc_request.params.as_ref().filter(|params| !params.is_array() && !params.is_object() && !params.is_null()) {
+                warn!("{} has params that are neither an array nor an object: {}", rpc_request.method, params);
+            }
 
-                    // Log the actual response content for debugging
-                    debug!("Raw response body: {}", response_body);
+            // Give id-less requests a generated id, so the upstream answers notifications too
+            if rpc_request.id.is_none() {
+                let generated_id = state.next_request_id.fetch_add(1, Ordering::Relaxed);
+                info!("Request has no id, using generated id {}", generated_id);
+                rpc_request.id = Some(json!(generated_id));
+            }
 
-                    // Log response headers for debugging
-                    debug!("Response headers from destination:");
-                    for (name, value) in &response_headers {
-                        debug!("  {}: {:?}", name.as_str(), value);
-                    }
+            if let Some(reply) = state.canned.as_ref().and_then(|canned| canned.find(&rpc_request.method, rpc_request.params.as_ref())) {
+                info!("Answering {} from the canned responses", rpc_request.method);
+                state.stats.record_local_answer(&rpc_request.method);
+                let (result, error) = match reply {
+                    CannedReply::Result(result) => (Some(result.clone()), None),
+                    CannedReply::Error(error) => (None, Some(error.clone())),
+                };
+                return JsonRpcResponse {
+                    jsonrpc: "2.0".to_string(),
+                    result,
+                    error,
+                    id: rpc_request.id,
+                }
+                .into_http();
+            }
 
-                    // Apply block response enhancement for specific methods
-                    let original_length = response_body.len();
-                    if matches!(rpc_method, "eth_getBlockByNumber" | "eth_getBlockByHash") {
-                        response_body = enhance_block_response(&response_body, rpc_method);
-                    }
-                    let modified_length = response_body.len();
+            positional_call_params(&mut rpc_request);
+            if let Some(params) = &mut rpc_request.params {
+                block_tags::rewrite_block_tags(&state, &rpc_request.method, params).await;
+            }
 
-                    // Log the final response being sent to client
-                    debug!("Final response body being sent to client: {}", response_body);
+            if let Err((code, message)) = check_method_policy(&state, &rpc_request.method, remote_addr) {
+                return JsonRpcResponse::failure(rpc_request.id, code, message).into_http();
+            }
 
-                    let mut response_builder = Response::builder().status(status.as_u16());
+            let state = select_pool_member(route_by_method(state, &rpc_request.method), &rpc_request);
 
-                    // Copy response headers, but update Content-Length if response was modified
-                    debug!("Copying response headers to client:");
-                    for (name, value) in response_headers {
-                        if let Some(name) = name {
-                            // Skip Content-Length if we modified the response body
-                            if name.as_str().eq_ignore_ascii_case("content-length") && original_length != modified_length {
-                                debug!("  Skipping original Content-Length header due to response modification");
-                                continue;
-                            }
+            if let Some(path) = native::native_path(&state, &rpc_request.method) {
+                if state.read_only && !native::is_read_only_path(&path) {
+                    warn!("Rejected write {} from {}: proxy is read-only", rpc_request.method, remote_addr);
+                    return JsonRpcResponse::failure(
+                        rpc_request.id,
+                        -32000,
+                        format!("proxy is read-only: {} is disabled", rpc_request.method),
+                    ).into_http();
+                }
 
-                            if let Ok(header_value) = axum::http::HeaderValue::from_bytes(value.as_bytes()) {
-                                debug!("  Copying header: {} = {:?}", name.as_str(), header_value);
-                                response_builder = response_builder.header(name.as_str(), header_value);
-                            } else {
-                                warn!("  Failed to convert header value for {}: {:?}", name.as_str(), value);
-                            }
+                info!("Bridging {} to the node HTTP API at {}", rpc_request.method, path);
+                let is_write = !native::is_read_only_path(&path);
+                let guard = DisconnectGuard::new(&rpc_request.method, remote_addr, is_write);
+                let result = if is_write {
+                    batch::leave_upstream_batch().await;
+                    call_native_write(&state, &rpc_request.method, path, rpc_request.params.clone(), &guard).await
+                } else {
+                    native::call_native(&state, &rpc_request.method, &path, rpc_request.params.as_ref()).await
+                };
+                guard.finish();
+                let response = match result {
+                    Ok(result) => JsonRpcResponse::success(rpc_request.id, result),
+                    Err((code, message)) => JsonRpcResponse::failure(rpc_request.id, code, message),
+                };
+                return response.into_http();
+            }
+
+            let mut broadcast_raw_tx = None;
+
+            if let Some(handler) = overrides::find(&state, &rpc_request.method) {
+                info!("Answering {} locally", rpc_request.method);
+                state.stats.record_local_answer(&rpc_request.method);
+                let response = match handler(&state, rpc_request.params.as_ref()).await {
+                    Ok(result) => JsonRpcResponse::success(rpc_request.id, result),
+                    Err((code, message)) => JsonRpcResponse::failure(rpc_request.id, code, message),
+                };
+                return response.into_http();
+            }
+
+            if SYNTHETIC_ZERO_METHODS.contains(&rpc_request.method.as_str()) {
+                info!("Overriding {} with 0x0", rpc_request.method);
+                state.stats.record_local_answer(&rpc_request.method);
+                return JsonRpcResponse::success(rpc_request.id, json!("0x0")).into_http();
+            }
+
+            if let Err((code, message)) = translate_call_request(&state, &mut rpc_request) {
+                return JsonRpcResponse::failure(rpc_request.id, code, message).into_http();
+            }
+
+            // Handle special cases
+            match rpc_request.method.as_str() {
+                "eth_getBalance" => {
+                    if let Some(token) = &state.balance_token {
+                        info!("Answering eth_getBalance with the balanceOf of token {}", token);
+                        return match token_balance(&state, token, rpc_request.params.as_ref()).await {
+                            Ok(balance) => JsonRpcResponse::success(rpc_request.id, json!(balance)),
+                            Err((code, message)) => JsonRpcResponse::failure(rpc_request.id, code, message),
                         }
+                        .into_http();
+                    }
+                }
+                "eth_gasPrice" => {
+                    // TRON's gas price is the energy fee, which the chain parameter cache holds
+                    if let Some(parameters) = state.chain_params.get() {
+                        state.stats.record_local_answer(&rpc_request.method);
+                        return JsonRpcResponse::success(rpc_request.id, json!(format!("0x{:x}", parameters.energy_fee))).into_http();
                     }
+                }
+                "eth_syncing" => {
+                    let syncing = match state.probe_sync {
+                        Some(max_lag) => probe_syncing(&state, max_lag).await,
+                        None if state.report_syncing => node_sync_status(&state).await,
+                        None => json!(false),
+                    };
+                    info!("Answering eth_syncing locally with {}", syncing);
+                    state.stats.record_local_answer(&rpc_request.method);
+                    return JsonRpcResponse::success(rpc_request.id, syncing).into_http();
+                }
+                "net_version" | "net_listening" | "net_peerCount" => {
+                    state.stats.record_local_answer(&rpc_request.method);
+                    let response = match rpc_request.method.as_str() {
+                        "net_version" => match chain_id(&state).await {
+                            Ok(chain_id) => JsonRpcResponse::success(rpc_request.id, json!(chain_id.to_string())),
+                            Err(e) => {
+                                error!("Failed to fetch chain id for net_version: {}", e);
+                                JsonRpcResponse::failure(rpc_request.id, -32603, format!("failed to fetch chain id: {}", e))
+                            }
+                        },
+                        "net_listening" => JsonRpcResponse::success(rpc_request.id, json!(true)),
+                        _ => JsonRpcResponse::success(rpc_request.id, json!("0x1")),
+                    };
+                    info!("Answering {} locally", rpc_request.method);
+                    return response.into_http();
+                }
+                "web3_clientVersion" if !state.client_version_passthrough => {
+                    let upstream_version = state.upstream_client_version
+                        .get_or_try_init(|| async {
+                            let version = upstream_call(&state, "web3_clientVersion", json!([])).await?;
+                            Ok::<_, anyhow::Error>(version.as_str().map_or_else(|| version.to_string(), str::to_string))
+                        })
+                        .await
+                        .map(String::as_str)
+                        .unwrap_or_else(|e| {
+                            warn!("Failed to fetch upstream client version: {}", e);
+                            "unavailable"
+                        });
 
-                    // Set correct Content-Length if response was modified
-                    if original_length != modified_length {
-                        debug!("  Setting new Content-Length: {} (was {})", modified_length, original_length);
-                        response_builder = response_builder.header("content-length", modified_length.to_string());
+                    let client_version = format!("tron-foundry-proxy/{} (upstream: {})", env!("CARGO_PKG_VERSION"), upstream_version);
+                    info!("Answering web3_clientVersion locally with {}", client_version);
+                    state.stats.record_local_answer(&rpc_request.method);
+                    return JsonRpcResponse::success(rpc_request.id, json!(client_version)).into_http();
+                }
+                "eth_chainId" => {
+                    // The chain id never changes for a destination, so it's only fetched once.
+                    // If it can't be fetched, the request is forwarded to surface the upstream's error.
+                    match chain_id(&state).await {
+                        Ok(chain_id) => {
+                            debug!("Answering eth_chainId from cache");
+                            state.stats.record_local_answer(&rpc_request.method);
+                            return JsonRpcResponse::success(rpc_request.id, json!(format!("0x{:x}", chain_id))).into_http();
+                        }
+                        Err(e) => warn!("Failed to fetch chain id, forwarding eth_chainId: {}", e),
+                    }
+                }
+                "web3_sha3" => {
+                    state.stats.record_local_answer(&rpc_request.method);
+                    let response = match web3_sha3(rpc_request.params.as_ref()) {
+                        Ok(digest) => JsonRpcResponse::success(rpc_request.id, json!(digest)),
+                        Err(message) => JsonRpcResponse::failure(rpc_request.id, -32602, message),
+                    };
+                    return response.into_http();
+                }
+                "eth_getTransactionByBlockHashAndIndex" => {
+                    // Addresses are sometimes pasted where a block hash belongs; TRON's answer to that is unhelpful
+                    let block_hash = rpc_request.params.as_ref()
+                        .and_then(|params| params.get(0))
+                        .and_then(Value::as_str);
+                    if let Some(block_hash) = block_hash.filter(|hash| normalize_address(&state.address_cache, hash).is_some()) {
+                        return JsonRpcResponse::failure(
+                            rpc_request.id,
+                            -32602,
+                            format!("expected a 32-byte block hash, got address {}", block_hash),
+                        ).into_http();
                     }
+                }
+                "eth_sendTransaction" => {
+                    let Some(signer) = state.signer.as_deref().filter(|_| state.sign_transactions) else {
+                        return JsonRpcResponse::failure(
+                            rpc_request.id,
+                            -32000,
+                            "eth_sendTransaction is not supported: the TRON node holds no keys. Sign the transaction \
+                             locally and send it with eth_sendRawTransaction, or start the proxy with --signer-key and \
+                             --sign-transactions",
+                        ).into_http();
+                    };
 
-                    response_builder
-                        .body(response_body)
-                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+                    match signer::sign_transaction_request(&state, signer, rpc_request.params.as_ref()).await {
+                        Ok(raw_tx) => {
+                            info!("Signed eth_sendTransaction as {}, broadcasting with eth_sendRawTransaction", signer.address);
+                            rpc_request.method = "eth_sendRawTransaction".to_string();
+                            rpc_request.params = Some(json!([raw_tx]));
+                            broadcast_raw_tx = Some(raw_tx);
+                        }
+                        Err(message) => {
+                            return JsonRpcResponse::failure(rpc_request.id, -32000, message).into_http();
+                        }
+                    }
                 }
-                Err(e) => {
-                    error!("Failed to read response body: {}", e);
-                    Err(StatusCode::BAD_GATEWAY)
+                "eth_sendRawTransaction" => {
+                    // Remember the transaction so receipts, transactions and txpool_* can report it later
+                    broadcast_raw_tx = rpc_request.params.as_ref()
+                        .and_then(|params| params.get(0))
+                        .and_then(Value::as_str)
+                        .map(str::to_string);
+                }
+                "eth_getLogs" => {
+                    // Queries spanning more blocks than the upstream allows are split locally
+                    let criteria = rpc_request.params.as_ref()
+                        .and_then(|params| params.get(0))
+                        .and_then(Value::as_object);
+                    if let Some(criteria) = criteria {
+                        let response = match logs::chunked_range(&state, criteria).await {
+                            Ok(None) => None,
+                            Ok(Some((from_block, to_block))) => {
+                                Some(logs::get_logs_chunked(&state, criteria.clone(), from_block, to_block).await)
+                            }
+                            Err(error) => Some(Err(error)),
+                        };
+                        if let Some(response) = response {
+                            return match response {
+                                Ok(logs) => JsonRpcResponse::success(rpc_request.id, logs),
+                                Err((code, message)) => JsonRpcResponse::failure(rpc_request.id, code, message),
+                            }
+                            .into_http();
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            if let Some(raw_tx) = &broadcast_raw_tx {
+                if let Err(message) = check_fee_limit(&state, raw_tx) {
+                    warn!("Rejecting broadcast from {}: {}", remote_addr, message);
+                    return JsonRpcResponse::failure(rpc_request.id, -32000, message).into_http();
+                }
+
+                // Retries of a broadcast that already succeeded get the same hash back rather
+                // than the node's duplicate-transaction error
+                if let Some(hash) = state.pending_txs.recent_broadcast(raw_tx) {
+                    info!("Transaction {} was already broadcast, answering the retry from {} locally", hash, remote_addr);
+                    state.stats.record_local_answer(&rpc_request.method);
+                    return JsonRpcResponse::success(rpc_request.id, json!(hash)).into_http();
+                }
+            }
+
+            // Forward the (possibly modified) request
+            let modified_body = serde_json::to_string(&rpc_request)
+                .map_err(ProxyError::translation)?;
+
+            debug!("Modified request body being sent to destination: {}", loggable_body(&state, &modified_body));
+
+            // Identical reads already in flight share their upstream response; broadcasts
+            // always go out on their own, even from a batch
+            let is_write = broadcast_raw_tx.is_some() || WRITE_METHODS.contains(&rpc_request.method.as_str());
+            let guard = DisconnectGuard::new(&rpc_request.method, remote_addr, is_write);
+            let response = if is_write {
+                batch::leave_upstream_batch().await;
+                forward_write(&state, &headers, modified_body, &rpc_request.method, broadcast_raw_tx, &guard).await
+            } else if batch::current_entry().is_some() {
+                // Batch entries share one upstream batch instead
+                forward_request(&state, Method::POST, &headers, &modified_body, &rpc_request.method).await
+            } else {
+                coalesce::forward_coalesced(
+                    &state,
+                    &headers,
+                    &rpc_request.method,
+                    rpc_request.params.as_ref(),
+                    rpc_request.id.as_ref(),
+                    &modified_body,
+                )
+                .await
+            };
+            guard.finish();
+            let response = match response {
+                Ok(response) => response,
+                Err(e) => return upstream_failure(&state, rpc_request.id, &e).into_http(),
+            };
+
+            if rpc_request.method == "eth_getLogs" && is_error_response(response.body()) {
+                let block_hash_filter = rpc_request.params.as_ref()
+                    .and_then(|params| params.get(0))
+                    .and_then(Value::as_object)
+                    .filter(|criteria| criteria.contains_key("blockHash"));
+                if let Some(criteria) = block_hash_filter {
+                    info!("Upstream rejected a blockHash eth_getLogs filter, retrying by block number");
+                    return match logs::get_logs_by_block_hash(&state, criteria.clone()).await {
+                        Ok(logs) => JsonRpcResponse::success(rpc_request.id, logs),
+                        Err((code, message)) => JsonRpcResponse::failure(rpc_request.id, code, message),
+                    }
+                    .into_http();
                 }
             }
+
+            if is_method_not_found(response.body())
+                && let Some(emulated) = emulate_missing_method(&state, &rpc_request).await
+            {
+                return emulated.into_http();
+            }
+
+            Ok(response)
+        }
+        Ok(rpc_request) => {
+            warn!("Rejected request from {} with jsonrpc version '{}'", remote_addr, rpc_request.jsonrpc);
+            JsonRpcResponse::failure(
+                Some(rpc_request.id.unwrap_or(Value::Null)),
+                -32600,
+                format!("invalid request: unsupported jsonrpc version '{}'", rpc_request.jsonrpc),
+            )
+            .into_http()
+        }
+        Err(e) if state.strictness == Strictness::Strict => {
+            // Garbage isn't worth an upstream round trip; answer with the JSON-RPC error
+            let response = match serde_json::from_str::<Value>(&body) {
+                Err(parse_error) => JsonRpcResponse::failure(Some(Value::Null), -32700, format!("parse error: {}", parse_error)),
+                Ok(value) => JsonRpcResponse::failure(
+                    Some(value.get("id").cloned().unwrap_or(Value::Null)),
+                    -32600,
+                    format!("invalid request: {}", e),
+                ),
+            };
+            warn!("Rejected malformed request from {}: {}", remote_addr, e);
+            response.into_http()
+        }
+        Err(_) => {
+            // Not a valid JSON-RPC request, forward as-is
+            info!("Not a JSON-RPC request, forwarding as-is");
+            forward_request(&state, Method::POST, &headers, &body, "unknown").await
+        }
+    }
+}
+
+/// Whether the upstream can be trusted to echo a request id back unchanged: a null id can
+/// read as a notification, and numbers beyond 64 bits may lose precision.
+fn upstream_preserves_id(id: &Value) -> bool {
+    match id {
+        Value::Null => false,
+        Value::Number(number) => number.is_i64() || number.is_u64(),
+        _ => true,
+    }
+}
+
+/// Replaces the id of a JSON-RPC response body.
+fn readdress(body: String, id: Option<&Value>) -> String {
+    match serde_json::from_str::<Value>(&body) {
+        Ok(Value::Object(mut response)) => {
+            response.insert("id".to_string(), id.cloned().unwrap_or(Value::Null));
+            Value::Object(response).to_string()
+        }
+        _ => body,
+    }
+}
+
+fn readdress_response(response: Response<String>, id: &Value) -> Response<String> {
+    let (mut parts, body) = response.into_parts();
+    // The body's length changes with the id
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, readdress(body, Some(id)))
+}
+
+/// The empty 200 answer to notifications.
+fn empty_response() -> Response<String> {
+    let mut response = Response::new(String::new());
+    response.headers_mut().insert(header::CONTENT_LENGTH, HeaderValue::from_static("0"));
+    response
+}
+
+async fn handle_stats(State(state): State<AppState>) -> Json<Value> {
+    let mut snapshot = state.stats.snapshot();
+    snapshot["max_batch_size"] = json!(state.max_batch_size);
+    Json(snapshot)
+}
+
+async fn handle_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.stats.render_prometheus(),
+    )
+}
+
+async fn handle_get_request(
+    State(state): State<AppState>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    query: Query<Vec<(String, String)>>,
+) -> Result<Response<Body>, ProxyError> {
+    let headers = with_forwarded_for(&state, headers, remote_addr);
+    info!("Received GET request with {} query parameters", query.len());
+
+    // Re-encode the decoded parameters in their original order, repeated keys included
+    let query_string = if query.is_empty() {
+        String::new()
+    } else {
+        let encoded = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(query.iter())
+            .finish();
+        format!("?{}", encoded)
+    };
+
+    // The root is the destination itself, unless a path rewrite maps it somewhere else
+    let path = rewritten_path(&state, "/");
+    let url = join_destination(&state.destination, &format!("{}{}", path, query_string)).map_err(ProxyError::translation)?;
+    forward_raw(&state, Method::GET, &headers, &url, Bytes::new()).await
+}
+
+/// A body as it should appear in debug logs: with `--normalize-addresses-in-logs`, a copy
+/// with every address in one format. The body itself is never changed.
+fn loggable_body<'a>(state: &AppState, body: &'a str) -> Cow<'a, str> {
+    let Some(format) = state.log_address_format else {
+        return Cow::Borrowed(body);
+    };
+    let Ok(mut value) = serde_json::from_str::<Value>(body) else {
+        return Cow::Borrowed(body);
+    };
+    normalize_address_strings(&mut value, format == AddressLogFormat::Base58);
+    Cow::Owned(value.to_string())
+}
+
+/// Sets X-Forwarded-For on headers bound upstream to the client's address, appended to the
+/// inbound chain with `--trust-forwarded` and replacing it otherwise.
+fn with_forwarded_for(state: &AppState, mut headers: HeaderMap, remote_addr: SocketAddr) -> HeaderMap {
+    let client = remote_addr.ip().to_string();
+    let inbound: Vec<&str> = headers
+        .get_all(FORWARDED_FOR_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+
+    let chain = if state.trust_forwarded && !inbound.is_empty() {
+        format!("{}, {}", inbound.join(", "), client)
+    } else {
+        client
+    };
+
+    if let Ok(chain) = HeaderValue::from_str(&chain) {
+        headers.insert(FORWARDED_FOR_HEADER, chain);
+    }
+    headers
+}
+
+/// Switches to the upstream named by the X-Tron-Network header, when upstreams are
+/// configured and the request didn't already pick one by path.
+fn select_upstream(state: AppState, headers: &HeaderMap) -> Result<AppState, ProxyError> {
+    let Some(network) = headers.get(NETWORK_HEADER) else {
+        return Ok(state);
+    };
+    if state.upstreams.is_empty() {
+        return Ok(state);
+    }
+
+    let network = network.to_str().unwrap_or_default();
+    match state.upstreams.get(network) {
+        Some(upstream_state) => {
+            debug!("Selected upstream {} from the {} header", network, NETWORK_HEADER);
+            Ok(upstream_state.clone())
+        }
+        None => Err(ProxyError::UnknownNetwork(network.to_string())),
+    }
+}
+
+/// Switches to the upstream the first matching `--route` names. Only requests for the default
+/// upstream are routed: one that picked a named upstream by path or header keeps it.
+fn route_by_method(state: AppState, method: &str) -> AppState {
+    if state.upstream_name != DEFAULT_UPSTREAM {
+        return state;
+    }
+
+    let route = state.routes.iter().find(|(pattern, _)| match pattern.strip_suffix('*') {
+        Some(prefix) => method.starts_with(prefix),
+        None => method == pattern,
+    });
+    let Some((pattern, name)) = route else {
+        return state;
+    };
+
+    match state.upstreams.get(name) {
+        Some(upstream_state) => {
+            debug!("Routing {} to upstream {} ({})", method, name, pattern);
+            upstream_state.clone()
+        }
+        // `default`, validated at startup
+        None => state,
+    }
+}
+
+/// Picks the `--dest-replica` pool node that serves a request. With
+/// `--sticky-upstream-by-sender`, broadcasts go to the node their sender hashes to, so one
+/// account's transactions all reach the same node in order; everything else takes turns.
+/// Node HTTP API calls made while answering go to the same node as its JSON-RPC calls.
+fn select_pool_member(state: AppState, rpc_request: &JsonRpcRequest) -> AppState {
+    if state.dest_pool.is_empty() {
+        return state;
+    }
+
+    let sender = if state.sticky_upstream_by_sender { broadcast_sender(&state, rpc_request) } else { None };
+    let member = match &sender {
+        Some(sender) => {
+            let member = sticky_pool_member(&state.dest_pool, sender);
+            debug!("Sending {}'s {} to {}", sender, rpc_request.method, member.jsonrpc);
+            member.clone()
         }
+        None => {
+            let turn = state.next_pool_member.fetch_add(1, Ordering::Relaxed);
+            state.dest_pool[turn % state.dest_pool.len()].clone()
+        }
+    };
+
+    AppState { destination: member.jsonrpc, wallet_dest: member.api_root, ..state }
+}
+
+/// Rendezvous hashing: the node with the highest keccak256(sender, url). A sender always maps
+/// to the same node, and dropping a node from the pool only moves the senders it served.
+fn sticky_pool_member<'a>(pool: &'a [Destination], sender: &str) -> &'a Destination {
+    pool.iter()
+        .max_by_key(|member| {
+            let mut hasher = Keccak256::new();
+            hasher.update(sender.as_bytes());
+            hasher.update(member.jsonrpc.as_str().as_bytes());
+            hasher.finalize().to_vec()
+        })
+        .expect("the pool is never empty here")
+}
+
+/// The sender of a broadcast: recovered from an eth_sendRawTransaction payload, or the
+/// `from` of an eth_sendTransaction (the `--signer-key` address without one), as lowercase
+/// EVM hex.
+fn broadcast_sender(state: &AppState, rpc_request: &JsonRpcRequest) -> Option<String> {
+    let first_param = rpc_request.params.as_ref().and_then(|params| params.get(0));
+    match rpc_request.method.as_str() {
+        "eth_sendRawTransaction" => raw_tx::decode(first_param?.as_str()?).map(|tx| tx.from),
+        "eth_sendTransaction" => match first_param.and_then(|tx| tx.get("from")).and_then(Value::as_str) {
+            Some(from) => parse_address(from),
+            None => state.signer.as_ref().map(|signer| signer.address.clone()),
+        },
+        _ => None,
+    }
+}
+
+/// Forwards anything no route matched with its HTTP method and raw body, so binary payloads
+/// reach the node untouched. The path (below `--base-path`, if any) and query string are
+/// kept, percent-encoding included; the node's HTTP API paths (`/wallet/...` and the like)
+/// go to `--wallet-dest`, everything else to the destination.
+async fn handle_fallback(
+    State(state): State<AppState>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Result<Bytes, BytesRejection>,
+) -> Result<Response<Body>, ProxyError> {
+    let body = read_body(&state, body)?;
+    let headers = with_forwarded_for(&state, headers, remote_addr);
+    let path_and_query = uri.path_and_query().map_or("/", |path_and_query| path_and_query.as_str());
+    info!("Received fallback {} request for {}, body length: {}", method, path_and_query, body.len());
+    let path = rewritten_path(&state, uri.path());
+    let base = if is_wallet_path(&path) { &state.wallet_dest } else { &state.destination };
+    let query = uri.query().map(|query| format!("?{}", query)).unwrap_or_default();
+    let url = join_destination(base, &format!("{}{}", path, query)).map_err(ProxyError::translation)?;
+    forward_raw(&state, method, &headers, &url, body).await
+}
+
+/// An upstream HTTP response, read in full.
+pub struct UpstreamResponse {
+    pub status: reqwest::StatusCode,
+    pub headers: reqwest::header::HeaderMap,
+    pub body: String,
+}
+
+async fn forward_request(
+    state: &AppState,
+    method: Method,
+    headers: &HeaderMap,
+    body: &str,
+    rpc_method: &str,
+) -> Result<Response<String>, ProxyError> {
+    let timeout = upstream_timeout(state, rpc_method);
+
+    // Entries of a client batch go upstream together, as one batch
+    let upstream_started = Instant::now();
+    let upstream_response = match batch::current_entry() {
+        Some(entry) if method == Method::POST => entry.forward(state, headers, body, timeout).await,
+        _ => send_upstream(state, method, headers, body, timeout).await,
+    };
+    state.stats.record_upstream_latency(rpc_method, upstream_started.elapsed());
+
+    let UpstreamResponse { status, headers: response_headers, body: mut response_body } = match upstream_response {
+        Ok(upstream_response) => upstream_response,
         Err(e) => {
-            error!("Failed to forward request: {}", e);
-            Err(StatusCode::BAD_GATEWAY)
+            if matches!(e, ProxyError::UpstreamTimeout(_)) {
+                state.stats.record_upstream_timeout(rpc_method);
+            }
+            return Err(e);
+        }
+    };
+
+    info!("Received response from destination, status: {}, body length: {}",
+          status, response_body.len());
+
+    // Log the actual response content for debugging
+    debug!("Raw response body: {}", loggable_body(state, &response_body));
+
+    // Log response headers for debugging
+    debug!("Response headers from destination:");
+    for (name, value) in &response_headers {
+        debug!("  {}: {:?}", name.as_str(), value);
+    }
+
+    // Apply block response enhancement for specific methods
+    let original_length = response_body.len();
+    let enhancement_started = Instant::now();
+    response_body = enhance_response(state, rpc_method, body, response_body).await;
+    state.stats.record_enhancement_latency(rpc_method, enhancement_started.elapsed());
+    let modified_length = response_body.len();
+
+    // Log the final response being sent to client
+    debug!("Final response body being sent to client: {}", loggable_body(state, &response_body));
+
+    let mut response_builder = Response::builder().status(status.as_u16());
+    let is_json = serde_json::from_str::<IgnoredAny>(&response_body).is_ok();
+
+    // Copy response headers, but update Content-Length if response was modified
+    debug!("Copying response headers to client:");
+    for (name, value) in response_headers {
+        if let Some(name) = name {
+            // The body is re-framed by axum, so the upstream's framing doesn't apply
+            if is_hop_by_hop(name.as_str()) {
+                debug!("  Skipping hop-by-hop header: {}", name.as_str());
+                continue;
+            }
+
+            // Skip Content-Length if we modified the response body
+            if name.as_str().eq_ignore_ascii_case("content-length") && original_length != modified_length {
+                debug!("  Skipping original Content-Length header due to response modification");
+                continue;
+            }
+
+            // Set below for JSON bodies, whatever the upstream labelled them
+            if name == reqwest::header::CONTENT_TYPE && is_json {
+                continue;
+            }
+
+            // reqwest removes the Content-Encoding of bodies it decoded, so one that's
+            // left is an encoding it couldn't undo, and the body was read as text anyway
+            if name.as_str().eq_ignore_ascii_case("content-encoding") {
+                warn!("  Upstream used unsupported Content-Encoding {:?}, serving the body as read", value);
+                continue;
+            }
+
+            if let Ok(header_value) = axum::http::HeaderValue::from_bytes(value.as_bytes()) {
+                debug!("  Copying header: {} = {:?}", name.as_str(), header_value);
+                response_builder = response_builder.header(name.as_str(), header_value);
+            } else {
+                warn!("  Failed to convert header value for {}: {:?}", name.as_str(), value);
+            }
         }
     }
+
+    if is_json {
+        response_builder = response_builder.header(header::CONTENT_TYPE, JSON_CONTENT_TYPE);
+    }
+
+    // Set correct Content-Length if response was modified
+    if original_length != modified_length {
+        debug!("  Setting new Content-Length: {} (was {})", modified_length, original_length);
+        response_builder = response_builder.header("content-length", modified_length.to_string());
+    }
+
+    response_builder
+        .body(response_body)
+        .map_err(ProxyError::translation)
 }
 
-async fn forward_get_request(
+/// Sends a request to the upstream with the client's headers and reads the whole response,
+/// counting the exchange towards the upstream's health.
+async fn send_upstream(
     state: &AppState,
+    method: Method,
     headers: &HeaderMap,
-    query_string: &str,
-) -> Result<Response<String>, StatusCode> {
-    // For GET requests, we need to modify the destination URL to include query parameters
-    let url = format!("{}{}", state.destination, query_string);
+    body: &str,
+    timeout: Duration,
+) -> Result<UpstreamResponse, ProxyError> {
+    let url = &state.destination;
+
+    info!("Forwarding {} request to {}", method, url);
 
-    info!("Forwarding GET request to {}", url);
+    let mut request_builder = match method {
+        Method::POST => state.client.post(url.clone()),
+        Method::GET => state.client.get(url.clone()),
+        _ => return Err(ProxyError::MethodNotAllowed(method)),
+    };
 
-    let mut request_builder = state.client.get(&url);
+    // Some nodes reject `application/json; charset=UTF-8` (or `text/plain`) with a 415, so JSON
+    // bodies go up labelled plain `application/json`. Anything else keeps the client's type
+    let json_body = method == Method::POST && serde_json::from_str::<IgnoredAny>(body).is_ok();
+    if json_body {
+        request_builder = request_builder.header(reqwest::header::CONTENT_TYPE, "application/json");
+    }
 
-    // Copy relevant headers
+    // Copy relevant headers (excluding problematic ones)
     for (name, value) in headers {
-        if let Ok(header_name) = reqwest::header::HeaderName::from_bytes(name.as_str().as_bytes()) {
-            if let Ok(header_value) = reqwest::header::HeaderValue::from_bytes(value.as_bytes()) {
-                request_builder = request_builder.header(header_name, header_value);
+        let header_name_str = name.as_str();
+
+        if name == header::CONTENT_TYPE && json_body {
+            if value != "application/json" {
+                debug!("Normalizing Content-Type {:?} to application/json", value);
             }
+            continue;
+        }
+
+        // Skip headers that might cause issues with Tron API. The client's Accept-Encoding
+        // is left to reqwest, which only asks for encodings it can decode before enhancement
+        if header_name_str.eq_ignore_ascii_case("content-length")
+            || header_name_str.eq_ignore_ascii_case("accept-encoding")
+            || is_hop_by_hop(header_name_str)
+        {
+            debug!("Skipping problematic header: {}", header_name_str);
+            continue;
         }
+        if name == header::AUTHORIZATION && state.override_auth {
+            debug!("Replacing client Authorization with --upstream-auth");
+            continue;
+        }
+
+        if let Ok(header_name) = reqwest::header::HeaderName::from_bytes(name.as_str().as_bytes())
+            && let Ok(mut header_value) = reqwest::header::HeaderValue::from_bytes(value.as_bytes())
+        {
+            // Credentials are logged as "Sensitive"
+            header_value.set_sensitive(name == header::AUTHORIZATION);
+            debug!("Forwarding header: {} = {:?}", header_name_str, header_value);
+            request_builder = request_builder.header(header_name, header_value);
+        }
+    }
+
+    if method == Method::POST {
+        request_builder = request_builder.body(body.to_string());
     }
 
+    request_builder = request_builder.timeout(timeout);
+
     match request_builder.send().await {
         Ok(response) => {
             let status = response.status();
-            let response_headers = response.headers().clone();
-
-            match response.text().await {
-                Ok(response_body) => {
-                    info!("Received GET response from destination, status: {}, body length: {}",
-                          status, response_body.len());
-
-                    let mut response_builder = Response::builder().status(status.as_u16());
-
-                    // Copy response headers
-                    for (name, value) in response_headers {
-                        if let Some(name) = name {
-                            if let Ok(header_value) = axum::http::HeaderValue::from_bytes(value.as_bytes()) {
-                                response_builder = response_builder.header(name.as_str(), header_value);
-                            }
-                        }
-                    }
+            let headers = response.headers().clone();
+            let body = response.text().await;
+            record_upstream_health(state, body.is_ok() && !is_upstream_failure_status(status));
 
-                    response_builder
-                        .body(response_body)
-                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
-                }
+            match body {
+                Ok(body) => Ok(UpstreamResponse { status, headers, body }),
                 Err(e) => {
-                    error!("Failed to read GET response body: {}", e);
-                    Err(StatusCode::BAD_GATEWAY)
+                    error!("Failed to read response body: {}", e);
+                    Err(ProxyError::decode(e, timeout))
                 }
             }
         }
         Err(e) => {
-            error!("Failed to forward GET request: {}", e);
-            Err(StatusCode::BAD_GATEWAY)
+            record_upstream_health(state, false);
+            error!("Failed to forward request: {}", e);
+            Err(ProxyError::upstream(e, timeout))
         }
     }
 }
 
-fn convert_eth_to_tron_address(eth_address: &str) -> Option<String> {
-    // Remove 0x prefix if present
-    let address_hex = if eth_address.starts_with("0x") {
-        &eth_address[2..]
-    } else {
-        eth_address
-    };
+/// Applies the allowlist, read-only and cheatcode restrictions to a request method.
+fn check_method_policy(state: &AppState, method: &str, remote_addr: SocketAddr) -> Result<(), OverrideError> {
+    if let Some(allowed_methods) = &state.allowed_methods && !allowed_methods.contains(method) {
+        warn!("Rejected {} from {}: method not in allowlist", method, remote_addr);
+        return Err((-32601, format!("method {} is not allowed by this proxy", method)));
+    }
 
-    // Ethereum addresses should be 40 hex characters (20 bytes)
-    if address_hex.len() != 40 {
-        warn!("Invalid Ethereum address length: {} (expected 40 hex chars, got {})", eth_address, address_hex.len());
-        return None;
+    if state.read_only && WRITE_METHODS.contains(&method) {
+        warn!("Rejected write {} from {}: proxy is read-only", method, remote_addr);
+        return Err((-32000, format!("proxy is read-only: {} is disabled", method)));
     }
 
-    // Validate that it's all hex characters
-    if !address_hex.chars().all(|c| c.is_ascii_hexdigit()) {
-        warn!("Invalid Ethereum address format (non-hex characters): {}", eth_address);
-        return None;
+    if let Some(error) = overrides::unsupported(state, method) {
+        info!("Rejected unsupported method {} from {}", method, remote_addr);
+        return Err(error);
     }
 
-    // Convert to Tron format by adding 0x41 prefix
-    let tron_address = format!("0x41{}", address_hex);
+    if CHEATCODE_PREFIXES.iter().any(|prefix| method.starts_with(prefix)) && !state.forwarded_cheatcodes.contains(method) {
+        warn!("Rejected cheatcode method {} from {}", method, remote_addr);
+        state.stats.record_cheatcode_call(method);
+        return Err((
+            -32601,
+            format!(
+                "{} is unavailable: this proxy targets a live TRON network, which doesn't support \
+                 anvil_/hardhat_/evm_ cheatcode methods",
+                method
+            ),
+        ));
+    }
 
-    debug!("Converted Ethereum address {} to Tron address {}", eth_address, tron_address);
-    Some(tron_address)
+    Ok(())
 }
 
-fn enhance_block_response(response_body: &str, method: &str) -> String {
-    match serde_json::from_str::<JsonRpcResponse>(response_body) {
-        Ok(mut rpc_response) => {
-            if let Some(result) = &mut rpc_response.result {
-                if let Some(block) = result.as_object_mut() {
-                    let mut modified = false;
-
-                    // Check if stateRoot is missing or invalid
-                    let needs_state_root_fix = match block.get("stateRoot") {
-                        None => {
-                            info!("Adding missing stateRoot to {} response", method);
-                            true
-                        }
-                        Some(state_root) => {
-                            if let Some(state_root_str) = state_root.as_str() {
-                                // Check if stateRoot is invalid (empty "0x" or not 66 characters)
-                                if state_root_str == "0x" || state_root_str.len() != 66 {
-                                    info!("Fixing invalid stateRoot '{}' in {} response", state_root_str, method);
-                                    true
-                                } else {
-                                    false
-                                }
-                            } else {
-                                info!("Fixing non-string stateRoot in {} response", method);
-                                true
-                            }
-                        }
-                    };
+/// Turns object-shaped `eth_call`/`eth_estimateGas` params into the positional array every
+/// later step expects. Either the object is the call itself (`{"to": ..., "data": ...}`) or
+/// it names its members (`{"transaction": {...}, "block": "latest"}`); in both, a `block`,
+/// `blockNumber` or `blockTag` member becomes the block parameter. eth_call without one
+/// gets `latest`.
+fn positional_call_params(rpc_request: &mut JsonRpcRequest) {
+    if rpc_request.method != "eth_call" && rpc_request.method != "eth_estimateGas" {
+        return;
+    }
+    let Some(Value::Object(params)) = &mut rpc_request.params else {
+        return;
+    };
+    let mut params = std::mem::take(params);
 
-                    if needs_state_root_fix {
-                        block.insert(
-                            "stateRoot".to_string(),
-                            json!("0x0101010101010101010101010101010101010101010101010101010101010101")
-                        );
-                        modified = true;
-                    }
+    let block = NAMED_BLOCK_MEMBERS.iter().find_map(|name| params.remove(*name));
+    let state_overrides = NAMED_STATE_OVERRIDE_MEMBERS.iter().find_map(|name| params.remove(*name));
+    let named_call = NAMED_CALL_MEMBERS
+        .iter()
+        .find(|name| params.get(**name).is_some_and(Value::is_object))
+        .and_then(|name| params.remove(*name));
 
-                    // Return the modified response if any changes were made
-                    if modified {
-                        if let Ok(modified_response) = serde_json::to_string(&rpc_response) {
-                            return modified_response;
-                        }
-                    }
-                }
+    let call = match named_call {
+        Some(call) => {
+            if !params.is_empty() {
+                warn!("Ignoring unknown {} params: {:?}", rpc_request.method, params.keys().collect::<Vec<_>>());
             }
+            call
         }
-        Err(e) => {
-            warn!("Failed to parse response as JSON-RPC for block enhancement: {}", e);
+        None => Value::Object(params),
+    };
+    info!("Converting object params of {} to positional params", rpc_request.method);
+
+    let mut positional = vec![call];
+    match block {
+        Some(block) => positional.push(block),
+        None if rpc_request.method == "eth_call" => positional.push(json!("latest")),
+        None => {}
+    }
+    if let Some(state_overrides) = state_overrides {
+        if positional.len() == 1 {
+            positional.push(json!("latest"));
         }
+        positional.push(state_overrides);
     }
+    rpc_request.params = Some(Value::Array(positional));
+}
 
-    // Return original response if no modification was needed or possible
-    response_body.to_string()
+/// Removes eth_call parameters after the block: a state override set, which TRON's jsonrpc
+/// rejects along with any extra parameter. Overrides that change something are dropped
+/// with a warning naming what was ignored, or rejected under `--state-overrides reject`.
+fn strip_state_overrides(state: &AppState, params_array: &mut Vec<Value>) -> Result<(), OverrideError> {
+    if params_array.len() <= 2 {
+        return Ok(());
+    }
+
+    let overrides = params_array[2].as_object().filter(|overrides| !overrides.is_empty());
+    if let Some(overrides) = overrides {
+        if state.state_overrides == StateOverrides::Reject {
+            return Err((-32602, "eth_call state overrides are not supported on TRON".to_string()));
+        }
+
+        let unknown_fields: BTreeSet<&str> = overrides
+            .values()
+            .filter_map(Value::as_object)
+            .flat_map(|account| account.keys())
+            .map(String::as_str)
+            .filter(|field| !STATE_OVERRIDE_FIELDS.contains(field))
+            .collect();
+        if unknown_fields.is_empty() {
+            warn!("Ignoring eth_call state overrides for {} accounts, which TRON doesn't support", overrides.len());
+        } else {
+            warn!(
+                "Ignoring eth_call state overrides for {} accounts, which TRON doesn't support, including unknown fields {:?}",
+                overrides.len(),
+                unknown_fields
+            );
+        }
+    } else {
+        debug!("Dropping eth_call parameters after the block that override nothing: {:?}", &params_array[2..]);
+    }
+
+    params_array.truncate(2);
+    Ok(())
+}
+
+/// Normalizes the call object of `eth_call` and `eth_estimateGas` requests into the shape
+/// TRON's jsonrpc accepts. Fails for requests that can't be translated faithfully.
+fn translate_call_request(state: &AppState, rpc_request: &mut JsonRpcRequest) -> Result<(), OverrideError> {
+    match rpc_request.method.as_str() {
+        "eth_call" => {
+            info!("Normalizing eth_call parameters");
+            if let Some(params) = &mut rpc_request.params && let Some(params_array) = params.as_array_mut() {
+                strip_state_overrides(state, params_array)?;
+
+                if let Some(first_param) = params_array.get_mut(0) && let Some(obj) = first_param.as_object_mut() {
+                    normalize_call_object(obj, state.value_decimals)?;
+                    apply_to_rewrite(obj, &state.to_rewrites, &state.address_cache);
+                    normalize_call_owner(obj, state.default_owner.as_deref(), &state.address_cache);
+                }
+            }
+        }
+        "eth_estimateGas" => {
+            info!("Processing eth_estimateGas parameters");
+            debug!("Original eth_estimateGas params: {}", loggable_body(state, &serde_json::to_string_pretty(&rpc_request.params).unwrap_or_else(|_| "Failed to serialize".to_string())));
+
+            if let Some(params) = &mut rpc_request.params && let Some(params_array) = params.as_array_mut() {
+                // eth_estimateGas should only have one parameter (the transaction object)
+                // Remove any extra parameters (i.e., "pending") that might cause issues
+                if params_array.len() > 1 {
+                    info!("eth_estimateGas has {} parameters, truncating to 1", params_array.len());
+                    params_array.truncate(1);
+                }
+
+                if let Some(first_param) = params_array.get_mut(0) && let Some(obj) = first_param.as_object_mut() {
+                    // Log all fields in the transaction object
+                    debug!("eth_estimateGas transaction object fields: {:?}", obj.keys().collect::<Vec<_>>());
+
+                    // Apply the same normalizations as eth_call
+                    normalize_call_object(obj, state.value_decimals)?;
+                    apply_to_rewrite(obj, &state.to_rewrites, &state.address_cache);
+                }
+            }
+
+            debug!("Normalized eth_estimateGas params: {}", loggable_body(state, &serde_json::to_string_pretty(&rpc_request.params).unwrap_or_else(|_| "Failed to serialize".to_string())));
+            debug!("Final eth_estimateGas request being sent to Tron API: {}", serde_json::to_string(&rpc_request).unwrap_or_else(|_| "Failed to serialize".to_string()));
+        }
+        "eth_getLogs" | "eth_newFilter" | "eth_subscribe" => {
+            if let Some(params) = &mut rpc_request.params {
+                logs::normalize_filter_params(&state.address_cache, &rpc_request.method, params);
+            }
+        }
+        "eth_sendTransaction" => {
+            // Normalized before signing, so a gas estimate for it is a valid TRON request
+            if let Some(obj) = rpc_request.params.as_mut().and_then(|params| params.get_mut(0)).and_then(Value::as_object_mut) {
+                normalize_call_object(obj, state.value_decimals)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Applies the per-method enhancement to an upstream JSON-RPC response. `request_body` is
+/// the request it answers.
+async fn enhance_response(state: &AppState, rpc_method: &str, request_body: &str, response_body: String) -> String {
+    let response_body = match rpc_method {
+        "eth_getBlockByNumber" | "eth_getBlockByHash" => {
+            // The second parameter selects full transaction objects over hashes
+            let full_transactions = serde_json::from_str::<JsonRpcRequest>(request_body)
+                .ok()
+                .and_then(|request| request.params)
+                .and_then(|params| params.get(1).and_then(Value::as_bool))
+                .unwrap_or(false);
+            let block_number = serde_json::from_str::<JsonRpcResponse>(&response_body)
+                .ok()
+                .and_then(|response| response.result)
+                .and_then(|block| block.get("number").and_then(parse_quantity));
+            let net_usage = block_net_usage(state, block_number).await;
+            enhance_block_response(&response_body, rpc_method, full_transactions, &state.pending_txs, state.gas_ratios, net_usage)
+        }
+        "eth_getTransactionReceipt" => enhance_receipt_response(state, &response_body).await,
+        "eth_getLogs" => enhance_logs_response(&response_body),
+        "eth_call" => enhance_call_response(&response_body),
+        "eth_estimateGas" => enhance_estimate_response(state, &response_body),
+        "eth_getTransactionByHash"
+        | "eth_getTransactionByBlockNumberAndIndex"
+        | "eth_getTransactionByBlockHashAndIndex" => {
+            enhance_transaction_response(&response_body, &state.pending_txs, state.gas_ratios.energy)
+        }
+        _ => response_body,
+    };
+
+    let response_body = match state.chain_id_override {
+        Some(chain_id) if CHAIN_ID_REWRITE_METHODS.contains(&rpc_method) => rewrite_chain_ids(response_body, chain_id),
+        _ => response_body,
+    };
+
+    match state.validate_responses {
+        Some(mode) => validate::validate_response(mode, rpc_method, response_body),
+        None => response_body,
+    }
+}
+
+/// Methods whose transaction or block results get their `chainId` fields rewritten to `--chain-id`
+const CHAIN_ID_REWRITE_METHODS: &[&str] = &[
+    "eth_getBlockByNumber",
+    "eth_getBlockByHash",
+    "eth_getTransactionByHash",
+    "eth_getTransactionByBlockNumberAndIndex",
+    "eth_getTransactionByBlockHashAndIndex",
+];
+
+/// Overwrites the `chainId` of a transaction result, or of each full transaction in a block
+/// result, with the advertised chain id, so clients cross-checking it against eth_chainId
+/// don't see a conflict. Only existing `chainId` keys are touched.
+fn rewrite_chain_ids(response_body: String, chain_id: u64) -> String {
+    let mut rpc_response = match serde_json::from_str::<JsonRpcResponse>(&response_body) {
+        Ok(rpc_response) => rpc_response,
+        Err(_) => return response_body,
+    };
+    let Some(result) = rpc_response.result.as_mut().and_then(Value::as_object_mut) else {
+        return response_body;
+    };
+
+    let chain_id = json!(format!("0x{:x}", chain_id));
+    let mut rewritten = 0;
+    let mut rewrite = |object: &mut serde_json::Map<String, Value>| {
+        if let Some(value) = object.get_mut("chainId").filter(|value| **value != chain_id) {
+            debug!("Rewriting chainId {} to {}", value, chain_id);
+            *value = chain_id.clone();
+            rewritten += 1;
+        }
+    };
+
+    rewrite(result);
+    if let Some(transactions) = result.get_mut("transactions").and_then(Value::as_array_mut) {
+        transactions.iter_mut().filter_map(Value::as_object_mut).for_each(&mut rewrite);
+    }
+
+    if rewritten == 0 {
+        return response_body;
+    }
+    info!("Rewrote {} chainId field(s) to the configured chain id", rewritten);
+    serde_json::to_string(&rpc_response).unwrap_or(response_body)
+}
+
+/// Reports a failed upstream exchange as a JSON-RPC error for the request, naming the
+/// upstream so clients can tell it apart from the proxy being down.
+fn upstream_failure(state: &AppState, id: Option<Value>, e: &ProxyError) -> JsonRpcResponse {
+    let upstream = state.destination.host_str().unwrap_or_else(|| state.destination.as_str());
+    JsonRpcResponse::failure(id, e.code(), format!("{} (upstream {})", e, upstream))
+}
+
+/// Sends a JSON-RPC request on the proxy's own behalf and returns its result.
+/// Transport failures and JSON-RPC error objects are both reported as errors.
+async fn upstream_call(state: &AppState, method: &str, params: Value) -> anyhow::Result<Value> {
+    let request = JsonRpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method: method.to_string(),
+        params: Some(params),
+        id: Some(json!(1)),
+    };
+
+    debug!("Internal upstream call: {}", serde_json::to_string(&request)?);
+
+    let response = async {
+        state
+            .client
+            .post(state.destination.clone())
+            .json(&request)
+            .timeout(upstream_timeout(state, method))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<JsonRpcResponse>()
+            .await
+    }
+    .await;
+    record_upstream_health(state, response.is_ok());
+    let response = response?;
+
+    if let Some(error) = response.error {
+        anyhow::bail!("{} failed upstream: {}", method, error);
+    }
+
+    Ok(response.result.unwrap_or(Value::Null))
+}
+
+/// Counts a JSON-RPC exchange with the request's upstream towards its health gauges.
+/// Transport failures, timeouts and the statuses `is_upstream_failure_status` picks out
+/// count as failures.
+fn record_upstream_health(state: &AppState, success: bool) {
+    state.stats.record_upstream_result(&state.upstream_name, state.destination.as_str(), success);
+}
+
+/// Whether an upstream answer means the upstream can't serve us: a 5xx, rate limiting (429),
+/// or rejected credentials (401, 403). Other 4xx answers blame the request.
+fn is_upstream_failure_status(status: reqwest::StatusCode) -> bool {
+    status.is_server_error()
+        || matches!(
+            status,
+            reqwest::StatusCode::TOO_MANY_REQUESTS | reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN
+        )
+}
+
+/// How long to wait on the upstream for `method`: its `--method-timeout`, the broadcast
+/// timeout for eth_sendRawTransaction, or `--upstream-timeout-ms`.
+fn upstream_timeout(state: &AppState, method: &str) -> Duration {
+    if let Some(timeout) = state.method_timeouts.get(method) {
+        return *timeout;
+    }
+    if method == "eth_sendRawTransaction" {
+        return state.broadcast_timeout;
+    }
+    state.upstream_timeout
+}
+
+/// Checks at startup that the destination answers JSON-RPC, logging its chain id and latest block.
+async fn probe_upstream(state: &AppState) -> anyhow::Result<()> {
+    info!("Probing upstream {}", state.destination);
+
+    let chain_id = upstream_call(state, "eth_chainId", json!([])).await?;
+    let latest_block = upstream_call(state, "eth_blockNumber", json!([])).await?;
+
+    info!(
+        "Upstream is reachable: chain id {}, latest block {}",
+        chain_id.as_str().and_then(parse_hex_u64).map_or_else(|| chain_id.to_string(), |id| id.to_string()),
+        latest_block.as_str().and_then(parse_hex_u64).map_or_else(|| latest_block.to_string(), |number| number.to_string())
+    );
+
+    Ok(())
+}
+
+/// Answers `eth_getBalance` for `--balance-token`: calls the token's `balanceOf(address)`
+/// at the requested block, translated like a client's eth_call, and returns the uint256 as
+/// a quantity.
+async fn token_balance(state: &AppState, token: &str, params: Option<&Value>) -> Result<String, OverrideError> {
+    let params = params.and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    let (account, block) = match params {
+        [account] => (account, &json!("latest")),
+        [account, block, ..] => (account, block),
+        _ => return Err((-32602, "eth_getBalance expects an address".to_string())),
+    };
+    let account = account
+        .as_str()
+        .and_then(|account| normalize_address(&state.address_cache, account))
+        .ok_or_else(|| (-32602, format!("invalid address {}", account)))?;
+
+    let mut call = JsonRpcRequest {
+        jsonrpc: "2.0".to_string(),
+        method: "eth_call".to_string(),
+        params: Some(json!([
+            { "to": token, "data": format!("{}{:0>64}", BALANCE_OF_SELECTOR, &account[2..]) },
+            block,
+        ])),
+        id: None,
+    };
+    translate_call_request(state, &mut call)?;
+
+    let result = upstream_call(state, "eth_call", call.params.unwrap_or_default())
+        .await
+        .map_err(|e| (-32000, format!("balanceOf on token {} failed: {}", token, e)))?;
+
+    let digits = result
+        .as_str()
+        .and_then(|result| result.strip_prefix("0x"))
+        .filter(|digits| digits.len() == 64 && digits.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| (-32000, format!("token {} returned an invalid balanceOf result {}", token, result)))?;
+
+    let digits = digits.trim_start_matches('0');
+    Ok(if digits.is_empty() { "0x0".to_string() } else { format!("0x{}", digits) })
+}
+
+/// Computes keccak256 of the single hex-string parameter of `web3_sha3`.
+fn web3_sha3(params: Option<&Value>) -> Result<String, String> {
+    let params = params.and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    let [data] = params else {
+        return Err(format!("web3_sha3 expects 1 parameter, got {}", params.len()));
+    };
+
+    let data_hex = data.as_str()
+        .and_then(|data| data.strip_prefix("0x"))
+        .ok_or("web3_sha3 expects a 0x-prefixed hex string")?;
+    let data = hex::decode(data_hex).map_err(|e| format!("invalid hex data: {}", e))?;
+
+    Ok(format!("0x{}", hex::encode(Keccak256::digest(&data))))
+}
+
+/// Returns the upstream's chain id, fetching it on first use so the same binary works
+/// against mainnet, Shasta and Nile.
+async fn chain_id(state: &AppState) -> anyhow::Result<u64> {
+    state.chain_id
+        .get_or_try_init(|| async {
+            let chain_id = upstream_call(state, "eth_chainId", json!([])).await?;
+            let chain_id = chain_id.as_str()
+                .and_then(parse_hex_u64)
+                .ok_or_else(|| anyhow::anyhow!("invalid eth_chainId result {}", chain_id))?;
+            info!("Cached upstream chain id {}", chain_id);
+            Ok(chain_id)
+        })
+        .await
+        .copied()
+}
+
+/// TRON produces a block every 3 seconds
+const BLOCK_INTERVAL_SECS: u64 = 3;
+
+/// Compares the latest upstream block's timestamp with the wall clock, returning `false` when
+/// the node is current and a syncing object when it lags by more than `max_lag`.
+async fn probe_syncing(state: &AppState, max_lag: Duration) -> Value {
+    let block = match upstream_call(state, "eth_getBlockByNumber", json!(["latest", false])).await {
+        Ok(block) => block,
+        Err(e) => {
+            warn!("Sync probe failed, reporting not syncing: {}", e);
+            return json!(false);
+        }
+    };
+
+    let number = block.get("number").and_then(parse_quantity);
+    let timestamp = block.get("timestamp").and_then(parse_quantity);
+    let (Some(number), Some(timestamp)) = (number, timestamp) else {
+        warn!("Sync probe got a block without number/timestamp, reporting not syncing");
+        return json!(false);
+    };
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let lag = now.saturating_sub(timestamp);
+    if lag <= max_lag.as_secs() {
+        return json!(false);
+    }
+
+    warn!("Upstream latest block {} is {}s behind wall clock", number, lag);
+    json!({
+        "startingBlock": format!("0x{:x}", number),
+        "currentBlock": format!("0x{:x}", number),
+        "highestBlock": format!("0x{:x}", number + lag / BLOCK_INTERVAL_SECS),
+    })
+}
+
+/// Builds an eth_syncing answer from `wallet/getnodeinfo`: the node's sync start, its head
+/// block, and the head plus the most blocks any peer still has to send.
+async fn node_sync_status(state: &AppState) -> Value {
+    let node_info = match join_destination(&state.wallet_dest, "/wallet/getnodeinfo") {
+        Ok(url) => match state.client.get(url.clone()).timeout(upstream_timeout(state, "eth_syncing")).send().await {
+            Ok(response) => response.json::<Value>().await.ok(),
+            Err(e) => {
+                warn!("Failed to fetch node info from {}: {}", url, e);
+                None
+            }
+        },
+        Err(e) => {
+            warn!("Failed to fetch node info: {}", e);
+            None
+        }
+    };
+
+    // The head block is reported as "Num:<number>,ID:<hash>"
+    let current = node_info.as_ref()
+        .and_then(|info| info.get("block"))
+        .and_then(Value::as_str)
+        .and_then(|block| block.strip_prefix("Num:"))
+        .and_then(|block| block.split(',').next())
+        .and_then(|number| number.parse::<u64>().ok());
+
+    let (Some(node_info), Some(current)) = (node_info, current) else {
+        warn!("Could not read node sync status, reporting not syncing");
+        return json!(false);
+    };
+
+    let starting = node_info.get("beginSyncNum").and_then(Value::as_u64).unwrap_or(current);
+    let remaining = node_info.get("peerInfoList")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|peer| peer.get("remainNum").and_then(Value::as_u64))
+        .max()
+        .unwrap_or(0);
+
+    if remaining == 0 {
+        return json!(false);
+    }
+
+    json!({
+        "startingBlock": format!("0x{:x}", starting),
+        "currentBlock": format!("0x{:x}", current),
+        "highestBlock": format!("0x{:x}", current + remaining),
+    })
+}
+
+/// Whether an upstream response body is a JSON-RPC error response of any kind.
+fn is_error_response(response_body: &str) -> bool {
+    serde_json::from_str::<JsonRpcResponse>(response_body).is_ok_and(|rpc_response| rpc_response.error.is_some())
+}
+
+/// Whether an upstream response body is a JSON-RPC "method not found" error.
+fn is_method_not_found(response_body: &str) -> bool {
+    let Ok(rpc_response) = serde_json::from_str::<JsonRpcResponse>(response_body) else {
+        return false;
+    };
+
+    let Some(error) = rpc_response.error else {
+        return false;
+    };
+
+    // TRON nodes answer "the method eth_foo does not exist/is not available"
+    error.get("code").and_then(Value::as_i64) == Some(-32601)
+        || error.get("message")
+            .and_then(Value::as_str)
+            .and_then(|message| message.strip_prefix("the method "))
+            .is_some_and(|rest| rest.contains(" does not exist"))
+}
+
+/// Forwards a request that isn't handled as JSON-RPC, with its HTTP method and body as raw
+/// bytes, and passes the upstream's response back byte for byte. A JSON body the upstream
+/// left unlabelled gets the JSON content type.
+async fn forward_raw(
+    state: &AppState,
+    method: Method,
+    headers: &HeaderMap,
+    url: &Url,
+    body: Bytes,
+) -> Result<Response<Body>, ProxyError> {
+    info!("Forwarding {} request to {}, body length: {}", method, url, body.len());
+
+    let upstream_method = reqwest::Method::from_bytes(method.as_str().as_bytes()).map_err(ProxyError::translation)?;
+    let timeout = state.upstream_timeout;
+    let mut request_builder = state.client.request(upstream_method, url.clone()).timeout(timeout);
+
+    // Copy relevant headers; reqwest sets Content-Length for the body itself
+    for (name, value) in headers {
+        if name.as_str().eq_ignore_ascii_case("content-length") || is_hop_by_hop(name.as_str()) {
+            continue;
+        }
+        if name == header::AUTHORIZATION && state.override_auth {
+            continue;
+        }
+
+        if let Ok(header_name) = reqwest::header::HeaderName::from_bytes(name.as_str().as_bytes())
+            && let Ok(header_value) = reqwest::header::HeaderValue::from_bytes(value.as_bytes())
+        {
+            request_builder = request_builder.header(header_name, header_value);
+        }
+    }
+
+    if !body.is_empty() {
+        request_builder = request_builder.body(body);
+    }
+
+    match request_builder.send().await {
+        Ok(response) => {
+            let status = response.status();
+            let response_headers = response.headers().clone();
+
+            match response.bytes().await {
+                Ok(response_body) => {
+                    info!("Received {} response from destination, status: {}, body length: {}",
+                          method, status, response_body.len());
+
+                    let mut response_builder = Response::builder().status(status.as_u16());
+                    if !response_headers.contains_key(reqwest::header::CONTENT_TYPE)
+                        && serde_json::from_slice::<IgnoredAny>(&response_body).is_ok()
+                    {
+                        response_builder = response_builder.header(header::CONTENT_TYPE, JSON_CONTENT_TYPE);
+                    }
+
+                    // Copy response headers
+                    for (name, value) in response_headers {
+                        if let Some(name) = name {
+                            if is_hop_by_hop(name.as_str()) {
+                                continue;
+                            }
+
+                            if let Ok(header_value) = axum::http::HeaderValue::from_bytes(value.as_bytes()) {
+                                response_builder = response_builder.header(name.as_str(), header_value);
+                            }
+                        }
+                    }
+
+                    response_builder
+                        .body(Body::from(response_body))
+                        .map_err(ProxyError::translation)
+                }
+                Err(e) => {
+                    error!("Failed to read {} response body: {}", method, e);
+                    if e.is_timeout() {
+                        state.stats.record_upstream_timeout(method.as_str());
+                    }
+                    Err(ProxyError::decode(e, timeout))
+                }
+            }
+        }
+        Err(e) => {
+            error!("Failed to forward {} request: {}", method, e);
+            if e.is_timeout() {
+                state.stats.record_upstream_timeout(method.as_str());
+            }
+            Err(ProxyError::upstream(e, timeout))
+        }
+    }
+}
+
+fn is_hop_by_hop(header_name: &str) -> bool {
+    HOP_BY_HOP_HEADERS.iter().any(|hop| header_name.eq_ignore_ascii_case(hop))
+}
+
+/// Under `--strict-jsonrpc`, the id and message of the `-32600` error for a request object
+/// whose `jsonrpc` member is missing or isn't `"2.0"`. Bodies that aren't JSON objects are
+/// left to the usual parsing.
+fn jsonrpc_version_error(body: &str) -> Option<(Value, String)> {
+    let request = serde_json::from_str::<Value>(body).ok()?;
+    let request = request.as_object()?;
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+
+    match request.get("jsonrpc") {
+        Some(Value::String(version)) if version == "2.0" => None,
+        None => Some((id, "invalid request: missing jsonrpc version".to_string())),
+        Some(version) => Some((id, format!("invalid request: unsupported jsonrpc version {}", version))),
+    }
+}
+
+/// Notices a client disconnecting mid-request. Hyper drops the handler future when the
+/// connection closes, which drops this guard before `finish` is called; for reads that also
+/// drops (and so cancels) the upstream request.
+struct DisconnectGuard {
+    method: String,
+    remote_addr: SocketAddr,
+    is_write: bool,
+    disconnected: Arc<AtomicBool>,
+    finished: bool,
+}
+
+impl DisconnectGuard {
+    fn new(method: &str, remote_addr: SocketAddr, is_write: bool) -> Self {
+        Self {
+            method: method.to_string(),
+            remote_addr,
+            is_write,
+            disconnected: Arc::new(AtomicBool::new(false)),
+            finished: false,
+        }
+    }
+
+    fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for DisconnectGuard {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.disconnected.store(true, Ordering::Relaxed);
+        if self.is_write {
+            warn!("Client {} disconnected during {}, letting it run to completion", self.remote_addr, self.method);
+        } else {
+            info!("Client {} disconnected, cancelling its {} request", self.remote_addr, self.method);
+        }
+    }
+}
+
+/// Forwards a write on its own task, so a client that disconnects (e.g. Ctrl-C in forge)
+/// can't leave a broadcast half-sent or unrecorded: it runs to completion either way, and
+/// its outcome is logged if nobody is left to receive it.
+async fn forward_write(
+    state: &AppState,
+    headers: &HeaderMap,
+    body: String,
+    method: &str,
+    broadcast_raw_tx: Option<String>,
+    guard: &DisconnectGuard,
+) -> Result<Response<String>, ProxyError> {
+    let task = tokio::spawn({
+        let state = state.clone();
+        let headers = headers.clone();
+        let method = method.to_string();
+        let disconnected = guard.disconnected.clone();
+        let remote_addr = guard.remote_addr;
+        async move {
+            let response = forward_request(&state, Method::POST, &headers, &body, &method).await;
+            if let (Ok(response), Some(raw_tx)) = (&response, &broadcast_raw_tx) {
+                record_broadcast(&state, response.body(), raw_tx);
+            }
+            if disconnected.load(Ordering::Relaxed) {
+                match &response {
+                    Ok(response) => warn!("{} for disconnected client {} completed: {}", method, remote_addr, response.body()),
+                    Err(e) => warn!("{} for disconnected client {} failed: {}", method, remote_addr, e),
+                }
+            }
+            response
+        }
+    });
+
+    task.await.map_err(ProxyError::translation)?
+}
+
+/// Bridges a native write (anything outside `native::is_read_only_path`) on its own task,
+/// like `forward_write`, so a disconnecting client can't cancel a broadcast mid-flight.
+async fn call_native_write(
+    state: &AppState,
+    method: &str,
+    path: String,
+    params: Option<Value>,
+    guard: &DisconnectGuard,
+) -> Result<Value, OverrideError> {
+    let task = tokio::spawn({
+        let state = state.clone();
+        let method = method.to_string();
+        let disconnected = guard.disconnected.clone();
+        let remote_addr = guard.remote_addr;
+        async move {
+            let result = native::call_native(&state, &method, &path, params.as_ref()).await;
+            if disconnected.load(Ordering::Relaxed) {
+                match &result {
+                    Ok(result) => warn!("{} for disconnected client {} completed: {}", method, remote_addr, result),
+                    Err((_, message)) => warn!("{} for disconnected client {} failed: {}", method, remote_addr, message),
+                }
+            }
+            result
+        }
+    });
+
+    task.await.map_err(|e| (-32603, format!("{} failed: {}", method, e)))?
+}
+
+/// Records the hash returned for a successful `eth_sendRawTransaction` in the pending-tx store.
+fn record_broadcast(state: &AppState, response_body: &str, raw_tx: &str) {
+    match serde_json::from_str::<JsonRpcResponse>(response_body) {
+        Ok(rpc_response) => {
+            if let Some(hash) = rpc_response.result.as_ref().and_then(Value::as_str) {
+                state.pending_txs.record(hash, raw_tx);
+            }
+        }
+        Err(e) => {
+            warn!("Failed to parse eth_sendRawTransaction response: {}", e);
+        }
+    }
+}
+
+/// TRON's fee_limit in SUN for a transaction: `gasLimit * gasPrice`, times `scale`.
+/// Saturates instead of overflowing.
+fn fee_limit_sun(tx: &RawTransaction, scale: FeeLimitScale) -> u128 {
+    let gas = parse_hex_u128(&tx.gas).unwrap_or(u128::MAX);
+    let gas_price = parse_hex_u128(&tx.gas_price).unwrap_or(u128::MAX);
+    scale.apply(gas.saturating_mul(gas_price))
+}
+
+/// Checks a broadcast's fee_limit against `--fee-limit-cap`, returning the error message
+/// when it's over the cap and `--fee-limit-exceeded reject` is set.
+fn check_fee_limit(state: &AppState, raw_tx: &str) -> Result<(), String> {
+    let Some(tx) = raw_tx::decode(raw_tx) else {
+        return Ok(());
+    };
+
+    let fee_limit = fee_limit_sun(&tx, state.fee_limit_scale);
+    debug!("Broadcast from {} has a fee_limit of {} SUN", tx.from, fee_limit);
+
+    let Some(cap) = state.fee_limit_cap else {
+        return Ok(());
+    };
+    if fee_limit <= u128::from(cap) {
+        return Ok(());
+    }
+
+    match state.fee_limit_exceeded {
+        FeeLimitPolicy::Reject => Err(format!(
+            "fee limit of {} SUN (gas {} * gas price {}) exceeds the cap of {} SUN",
+            fee_limit, tx.gas, tx.gas_price, cap
+        )),
+        FeeLimitPolicy::Warn => {
+            warn!("Broadcast from {} has a fee_limit of {} SUN, over the cap of {} SUN", tx.from, fee_limit, cap);
+            Ok(())
+        }
+    }
+}
+
+fn parse_hex_u128(value: &str) -> Option<u128> {
+    u128::from_str_radix(value.strip_prefix("0x")?, 16).ok()
+}
+
+fn parse_hex_u64(value: &str) -> Option<u64> {
+    u64::from_str_radix(value.strip_prefix("0x")?, 16).ok()
+}
+
+/// Reads a quantity given as a hex string, a decimal string, or a JSON number.
+fn parse_quantity(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(number) => number.as_u64(),
+        Value::String(text) if text.starts_with("0x") => parse_hex_u64(text),
+        Value::String(text) => text.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Normalizes a call or transaction object (of `eth_call`, `eth_estimateGas` or
+/// `eth_sendTransaction`) into fields TRON's jsonrpc accepts: `input` becomes `data`, the
+/// data is validated as hex, and the fields TRON rejects (`chainId`, `accessList`, `type`
+/// and the EIP-1559 fee caps) are removed. Without a `gasPrice`, `maxFeePerGas` becomes it.
+/// `gas`, `gasPrice` and `value` are put in canonical hex, and `value` is scaled from
+/// `value_decimals` to SUN.
+fn normalize_call_object(obj: &mut serde_json::Map<String, Value>, value_decimals: u32) -> Result<(), OverrideError> {
+    // If both "input" and "data" exist, remove "input"
+    if obj.contains_key("input") && obj.contains_key("data") {
+        obj.remove("input");
+        info!("Removed 'input' field (keeping 'data')");
+    }
+    // If only "input" exists, rename to "data"
+    else if let Some(input_value) = obj.remove("input") {
+        obj.insert("data".to_string(), input_value);
+        info!("Renamed 'input' field to 'data'");
+    }
+    normalize_call_data(obj)?;
+
+    for field in ["chainId", "accessList", "type"] {
+        if obj.remove(field).is_some() {
+            info!("Removed '{}' field for TRON API compatibility", field);
+        }
+    }
+
+    // TRON prices energy at a single fixed price, so the fee cap is the gas price
+    let max_fee = obj.remove("maxFeePerGas");
+    let max_priority_fee = obj.remove("maxPriorityFeePerGas");
+    match max_fee {
+        Some(max_fee) if !obj.contains_key("gasPrice") => {
+            info!("Using 'maxFeePerGas' as 'gasPrice'");
+            obj.insert("gasPrice".to_string(), max_fee);
+        }
+        Some(_) => info!("Removed EIP-1559 fee fields for TRON API compatibility"),
+        None if max_priority_fee.is_some() => info!("Removed EIP-1559 fee fields for TRON API compatibility"),
+        None => {}
+    }
+
+    for field in CALL_QUANTITY_FIELDS {
+        normalize_call_quantity(obj, field)?;
+    }
+    scale_call_value(obj, value_decimals)?;
+
+    Ok(())
+}
+
+/// Decimals of TRX amounts on TRON, where the unit is the SUN
+const SUN_DECIMALS: u32 = 6;
+
+/// Converts a call object's canonical hex `value` from `value_decimals` (18 for the wei EVM
+/// tooling sends) to SUN. Amounts with a fraction of a SUN are rejected rather than
+/// truncated, since they'd move a different amount than the client asked for.
+fn scale_call_value(obj: &mut serde_json::Map<String, Value>, value_decimals: u32) -> Result<(), OverrideError> {
+    let Some(value) = obj.get("value").and_then(Value::as_str).and_then(parse_hex_u128) else {
+        return Ok(());
+    };
+
+    let divisor = 10u128.pow(value_decimals.saturating_sub(SUN_DECIMALS));
+    if value % divisor != 0 {
+        return Err((
+            -32602,
+            format!(
+                "invalid value {}: not a whole number of SUN (with --value-decimals {}, values must be multiples of {})",
+                value, value_decimals, divisor
+            ),
+        ));
+    }
+
+    if divisor > 1 {
+        let sun = value / divisor;
+        info!("Scaled 'value' {} to {} SUN", value, sun);
+        obj.insert("value".to_string(), json!(format!("0x{:x}", sun)));
+    }
+    Ok(())
+}
+
+/// Quantity fields of call objects that TRON only accepts as canonical `0x` hex
+const CALL_QUANTITY_FIELDS: [&str; 3] = ["gas", "gasPrice", "value"];
+
+/// Rewrites a call object quantity given as a decimal string or number, with an upper-case
+/// `0X` prefix, leading zeros or stray whitespace, as canonical `0x` hex. `null` is dropped;
+/// anything that isn't a non-negative integer is rejected.
+fn normalize_call_quantity(obj: &mut serde_json::Map<String, Value>, field: &str) -> Result<(), OverrideError> {
+    let Some(value) = obj.get(field) else {
+        return Ok(());
+    };
+    if value.is_null() {
+        obj.remove(field);
+        return Ok(());
+    }
+
+    let quantity = match value {
+        Value::Number(number) => number.to_string().parse::<u128>().ok(),
+        Value::String(text) => {
+            let text = text.trim();
+            match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+                Some(hex) => u128::from_str_radix(hex, 16).ok().filter(|_| !hex.starts_with('+')),
+                None => text.parse::<u128>().ok().filter(|_| !text.starts_with('+')),
+            }
+        }
+        _ => None,
+    };
+    let Some(quantity) = quantity else {
+        return Err((-32602, format!("invalid {} {}: expected a hex or decimal quantity", field, value)));
+    };
+
+    let canonical = json!(format!("0x{:x}", quantity));
+    if *value != canonical {
+        info!("Normalized '{}' {} to {}", field, value, canonical);
+        obj.insert(field.to_string(), canonical);
+    }
+    Ok(())
+}
+
+/// Puts a call object's `data` in the `0x`-prefixed form TRON expects, accepting 0x-less
+/// hex and stray whitespace. Data that isn't hex bytes at all is rejected.
+fn normalize_call_data(obj: &mut serde_json::Map<String, Value>) -> Result<(), OverrideError> {
+    let Some(Value::String(data)) = obj.get("data") else {
+        return Ok(());
+    };
+
+    let trimmed = data.trim();
+    let digits = trimmed.strip_prefix("0x").or_else(|| trimmed.strip_prefix("0X")).unwrap_or(trimmed);
+    if !digits.len().is_multiple_of(2) || !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err((-32602, format!("invalid call data '{}': expected hex-encoded bytes", data)));
+    }
+
+    let normalized = format!("0x{}", digits);
+    if normalized != *data {
+        info!("Normalized call data to 0x-prefixed hex");
+        obj.insert("data".to_string(), json!(normalized));
+    }
+    Ok(())
+}
+
+/// Rewrites a quantity field to canonical hex (`0x` prefix, no leading zeros).
+/// Returns whether the field was changed.
+fn normalize_quantity_field(obj: &mut serde_json::Map<String, Value>, field: &str) -> bool {
+    let Some(value) = obj.get(field) else {
+        return false;
+    };
+
+    let Some(quantity) = parse_quantity(value) else {
+        return false;
+    };
+
+    let canonical = json!(format!("0x{:x}", quantity));
+    if *value == canonical {
+        return false;
+    }
+
+    obj.insert(field.to_string(), canonical);
+    true
+}
+
+/// Inserts `type` when the upstream omitted it, preferring the type recorded at broadcast time.
+fn ensure_tx_type(obj: &mut serde_json::Map<String, Value>, hash_field: &str, pending_txs: &PendingTxStore) -> bool {
+    let recorded_type = obj.get(hash_field)
+        .and_then(Value::as_str)
+        .and_then(|hash| pending_txs.get(hash))
+        .map(|tx| tx.tx_type);
+
+    match (obj.get("type"), recorded_type) {
+        (Some(_), _) => false,
+        (None, Some(tx_type)) => {
+            info!("Adding recorded type 0x{:x} for broadcast transaction", tx_type);
+            obj.insert("type".to_string(), json!(format!("0x{:x}", tx_type)));
+            true
+        }
+        (None, None) => {
+            info!("Adding missing type 0x0");
+            obj.insert("type".to_string(), json!("0x0"));
+            true
+        }
+    }
+}
+
+/// Address fields of receipt objects
+const RECEIPT_ADDRESS_FIELDS: &[&str] = &["from", "to", "contractAddress"];
+
+/// Address fields of transaction objects
+const TRANSACTION_ADDRESS_FIELDS: &[&str] = &["from", "to", "creates"];
+
+/// Address fields of log objects
+const LOG_ADDRESS_FIELDS: &[&str] = &["address"];
+
+/// Fills `removed`, `transactionIndex`, `blockNumber`, `blockHash` and `logIndex` on receipt
+/// logs where the upstream omitted them, and normalizes present quantities to canonical hex.
+fn fill_receipt_logs(receipt: &mut serde_json::Map<String, Value>) -> bool {
+    let inherited: Vec<(&str, Value)> = ["transactionIndex", "blockNumber", "blockHash", "transactionHash"]
+        .into_iter()
+        .filter_map(|field| receipt.get(field).map(|value| (field, value.clone())))
+        .collect();
+
+    let Some(logs) = receipt.get_mut("logs").and_then(Value::as_array_mut) else {
+        return false;
+    };
+
+    let mut modified = false;
+    for (position, log) in logs.iter_mut().enumerate() {
+        let Some(log) = log.as_object_mut() else {
+            continue;
+        };
+
+        for (field, value) in &inherited {
+            if !log.contains_key(*field) {
+                log.insert(field.to_string(), value.clone());
+                modified = true;
+            }
+        }
+
+        if !log.contains_key("logIndex") {
+            log.insert("logIndex".to_string(), json!(format!("0x{:x}", position)));
+            modified = true;
+        }
+
+        modified |= normalize_log(log);
+    }
+
+    if modified {
+        info!("Filled missing fields on receipt logs");
+    }
+
+    modified
+}
+
+/// Converts TRON hex log addresses, fills a missing `removed` and normalizes quantities to
+/// canonical hex. Shared by receipt logs, eth_getLogs and filter results.
+fn normalize_log(log: &mut serde_json::Map<String, Value>) -> bool {
+    let mut modified = repair_address_fields(log, LOG_ADDRESS_FIELDS);
+
+    if !log.contains_key("removed") {
+        log.insert("removed".to_string(), json!(false));
+        modified = true;
+    }
+
+    for field in ["logIndex", "transactionIndex", "blockNumber"] {
+        modified |= normalize_quantity_field(log, field);
+    }
+
+    modified
+}
+
+/// Normalizes every log object in an array of logs. Returns whether any was changed.
+fn normalize_logs(logs: &mut Value) -> bool {
+    let Some(logs) = logs.as_array_mut() else {
+        return false;
+    };
+
+    let mut modified = false;
+    for log in logs.iter_mut().filter_map(Value::as_object_mut) {
+        modified |= normalize_log(log);
+    }
+    modified
+}
+
+/// Returns `data` as `0x`-prefixed lowercase hex, or `None` if it isn't hex.
+fn revert_data_hex(data: &str) -> Option<String> {
+    let data = data.strip_prefix("0x").unwrap_or(data);
+    if !data.len().is_multiple_of(2) || !data.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(format!("0x{}", data.to_ascii_lowercase()))
+}
+
+/// Builds the error geth returns for a reverted call: code 3, the decoded reason in the
+/// message, and the raw revert payload in `data`.
+fn revert_error(revert_data: &str) -> Value {
+    let reason = hex::decode(&revert_data[2..]).ok().as_deref().and_then(trace::decode_revert_reason);
+    let message = match reason {
+        Some(reason) => format!("execution reverted: {}", reason),
+        None => "execution reverted".to_string(),
+    };
+    json!({ "code": 3, "message": message, "data": revert_data })
+}
+
+/// Message of the error java-tron answers a reverted `eth_call` with
+const TRON_REVERT_MESSAGE: &str = "REVERT opcode executed";
+
+/// Reshapes TRON's reverted constant-call responses into the Ethereum revert error, so
+/// clients can decode the reason from `data`.
+///
+/// TRON reports a revert either as an error with the return data in `data` (sometimes
+/// without `0x`) and the message "REVERT opcode executed", or, behind some gateways, as a
+/// triggerconstantcontract-style result object with `constant_result` and a failed
+/// `result.result`. Errors with another message and no hex `data` pass through, even when
+/// they mention a revert.
+fn enhance_call_response(response_body: &str) -> String {
+    let mut rpc_response = match serde_json::from_str::<JsonRpcResponse>(response_body) {
+        Ok(rpc_response) => rpc_response,
+        Err(e) => {
+            warn!("Failed to parse response as JSON-RPC for eth_call enhancement: {}", e);
+            return response_body.to_string();
+        }
+    };
+
+    let mut modified = false;
+
+    if let Some(error) = rpc_response.error.as_ref() {
+        let message = error.get("message").and_then(Value::as_str).unwrap_or_default();
+        let revert_data = error.get("data").and_then(Value::as_str).and_then(revert_data_hex);
+        let is_revert = message == TRON_REVERT_MESSAGE || revert_data.is_some();
+
+        if is_revert && error.get("code").and_then(Value::as_i64) != Some(3) {
+            let revert_data = revert_data.unwrap_or_else(|| "0x".to_string());
+            info!("Reformatting TRON eth_call revert ({}) as an Ethereum revert error", message);
+            rpc_response.error = Some(revert_error(&revert_data));
+            modified = true;
+        }
+    } else if let Some(result) = rpc_response.result.as_ref().and_then(Value::as_object)
+        && let Some(constant_result) = result.get("constant_result")
+    {
+        let output = constant_result
+            .get(0)
+            .and_then(Value::as_str)
+            .and_then(revert_data_hex)
+            .unwrap_or_else(|| "0x".to_string());
+        let succeeded = result.get("result").and_then(|result| result.get("result")).and_then(Value::as_bool) != Some(false);
+
+        if succeeded {
+            rpc_response.result = Some(json!(output));
+        } else {
+            info!("Reformatting TRON constant-call failure as an Ethereum revert error");
+            rpc_response.result = None;
+            rpc_response.error = Some(revert_error(&output));
+        }
+        modified = true;
+    }
+
+    if modified && let Ok(modified_response) = serde_json::to_string(&rpc_response) {
+        return modified_response;
+    }
+
+    response_body.to_string()
+}
+
+fn enhance_logs_response(response_body: &str) -> String {
+    match serde_json::from_str::<JsonRpcResponse>(response_body) {
+        Ok(mut rpc_response) => {
+            if let Some(logs) = rpc_response.result.as_mut()
+                && normalize_logs(logs)
+                && let Ok(modified_response) = serde_json::to_string(&rpc_response)
+            {
+                return modified_response;
+            }
+        }
+        Err(e) => {
+            warn!("Failed to parse response as JSON-RPC for log normalization: {}", e);
+        }
+    }
+
+    response_body.to_string()
+}
+
+/// Derives `yParity` from `v` when the upstream omitted it.
+fn ensure_y_parity(tx: &mut serde_json::Map<String, Value>) -> bool {
+    if tx.contains_key("yParity") {
+        return false;
+    }
+
+    let Some(v) = tx.get("v").and_then(Value::as_str).and_then(parse_hex_u64) else {
+        return false;
+    };
+
+    // Typed transactions carry the parity directly, legacy ones use 27/28 or EIP-155 (chainId * 2 + 35/36)
+    let y_parity = match v {
+        0 | 1 => v,
+        27 | 28 => v - 27,
+        v if v >= 35 => (v - 35) % 2,
+        _ => {
+            warn!("Cannot derive yParity from unexpected v value {}", v);
+            return false;
+        }
+    };
+
+    tx.insert("yParity".to_string(), json!(format!("0x{:x}", y_parity)));
+    true
+}
+
+/// Receipt status for every TRON `contractResult` value. DEFAULT is reported for
+/// transactions that didn't execute contract code, which succeed if they were included.
+const CONTRACT_RESULT_STATUS: &[(&str, bool)] = &[
+    ("DEFAULT", true),
+    ("SUCCESS", true),
+    ("REVERT", false),
+    ("BAD_JUMP_DESTINATION", false),
+    ("OUT_OF_MEMORY", false),
+    ("PRECOMPILED_CONTRACT", false),
+    ("STACK_TOO_SMALL", false),
+    ("STACK_TOO_LARGE", false),
+    ("ILLEGAL_OPERATION", false),
+    ("STACK_OVERFLOW", false),
+    ("OUT_OF_ENERGY", false),
+    ("OUT_OF_TIME", false),
+    ("JVM_STACK_OVER_FLOW", false),
+    ("UNKNOWN", false),
+    ("TRANSFER_FAILED", false),
+    ("INVALID_CODE", false),
+];
+
+/// Maps a TRON `contractResult` to an EVM receipt status.
+fn contract_result_status(contract_result: &str) -> &'static str {
+    match CONTRACT_RESULT_STATUS.iter().find(|(name, _)| *name == contract_result) {
+        Some((_, true)) => "0x1",
+        Some((_, false)) => "0x0",
+        None => {
+            warn!("Unknown TRON contract result '{}', treating as failure", contract_result);
+            "0x0"
+        }
+    }
+}
+
+/// Extracts TRON-style execution result information embedded in an upstream receipt.
+fn inline_contract_result(receipt: &serde_json::Map<String, Value>) -> Option<String> {
+    receipt.get("ret")
+        .and_then(|ret| ret.get(0))
+        .and_then(|ret| ret.get("contractRet"))
+        .or_else(|| receipt.get("contractRet"))
+        .or_else(|| receipt.get("contractResult"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+/// A forwarded request's path after `--path-rewrites`.
+fn rewritten_path<'a>(state: &AppState, path: &'a str) -> Cow<'a, str> {
+    match &state.path_rewrites {
+        Some(path_rewrites) => path_rewrites.rewrite(path),
+        None => Cow::Borrowed(path),
+    }
+}
+
+/// First path segments of the node's HTTP API
+const WALLET_PATH_PREFIXES: &[&str] = &["wallet", "walletsolidity", "walletpbft"];
+
+/// Whether a client request path belongs to the node's HTTP API rather than its jsonrpc.
+fn is_wallet_path(path: &str) -> bool {
+    let first_segment = path.trim_start_matches('/').split('/').next().unwrap_or_default();
+    WALLET_PATH_PREFIXES.contains(&first_segment)
+}
+
+/// POSTs a JSON body to a path of the node's HTTP API (`--wallet-dest`) and returns the
+/// JSON it answers with. Used wherever the proxy needs the native API on its own behalf.
+async fn wallet_post(state: &AppState, path: &str, body: &Value) -> anyhow::Result<Value> {
+    let url = join_destination(&state.wallet_dest, path).with_context(|| format!("invalid node API path {}", path))?;
+    let response = state
+        .client
+        .post(url.clone())
+        .json(body)
+        .timeout(state.upstream_timeout)
+        .send()
+        .await
+        .with_context(|| format!("{} failed", url))?;
+    response.json().await.with_context(|| format!("{} returned an invalid body", url))
+}
+
+/// Fetches a confirmed transaction's `wallet/gettransactioninfobyid` object.
+async fn fetch_transaction_info(state: &AppState, tx_hash: &str) -> Option<Value> {
+    let tx_id = tx_hash.strip_prefix("0x").unwrap_or(tx_hash);
+
+    debug!("Fetching transaction info for {}", tx_hash);
+
+    let info = match wallet_post(state, "/wallet/gettransactioninfobyid", &json!({ "value": tx_id })).await {
+        Ok(info) => info,
+        Err(e) => {
+            warn!("Failed to fetch transaction info for {}: {:#}", tx_hash, e);
+            return None;
+        }
+    };
+
+    // Unknown or unconfirmed transactions come back as an empty object
+    info.get("id")?;
+
+    Some(info)
+}
+
+/// Fetches the execution result of a transaction from `wallet/gettransactioninfobyid`.
+async fn fetch_contract_result(state: &AppState, tx_hash: &str) -> Option<String> {
+    let info = fetch_transaction_info(state, tx_hash).await?;
+
+    let contract_result = info.get("receipt")
+        .and_then(|receipt| receipt.get("result"))
+        .and_then(Value::as_str)
+        .unwrap_or("DEFAULT");
+
+    // Transactions that failed outside contract execution only carry a top-level FAILED result
+    if contract_result == "DEFAULT" && info.get("result").and_then(Value::as_str) == Some("FAILED") {
+        return Some("UNKNOWN".to_string());
+    }
+
+    Some(contract_result.to_string())
+}
+
+/// Gas reported per unit of each TRON resource, from `--energy-to-gas-ratio` and
+/// `--bandwidth-to-gas-ratio`
+#[derive(Clone, Copy, Debug)]
+struct GasRatios {
+    energy: u64,
+    bandwidth: u64,
+}
+
+impl GasRatios {
+    /// Whether reported gas is the node's energy figures unchanged
+    fn is_identity(&self) -> bool {
+        self.energy == 1 && self.bandwidth == 0
+    }
+}
+
+/// TRON resources a transaction consumed, and its block's running totals up to and
+/// including it
+#[derive(Clone, Copy, Debug, Default)]
+struct TronResources {
+    energy_used: u64,
+    net_usage: u64,
+    cumulative_energy_used: u64,
+    cumulative_net_usage: u64,
+}
+
+/// Converts TRON resources into the gas reported to EVM clients, returning `(gasUsed,
+/// cumulativeGasUsed)`: energy (`energy_usage_total`) times the energy ratio plus bandwidth
+/// (`net_usage`) times the bandwidth ratio. Receipts and blocks both go through here so a
+/// block's `gasUsed` keeps matching its last receipt's `cumulativeGasUsed`.
+fn tron_resources_to_gas(resources: TronResources, ratios: GasRatios) -> (u64, u64) {
+    let gas = |energy: u64, net_usage: u64| {
+        energy.saturating_mul(ratios.energy).saturating_add(net_usage.saturating_mul(ratios.bandwidth))
+    };
+    (
+        gas(resources.energy_used, resources.net_usage),
+        gas(resources.cumulative_energy_used, resources.cumulative_net_usage),
+    )
+}
+
+/// Bandwidth (`net_usage`) of each transaction in a block, in block order, keyed by
+/// transaction id, from `wallet/gettransactioninfobyblocknum`. Bandwidth paid for by burning
+/// TRX has no `net_usage` and counts as 0.
+async fn fetch_block_net_usage(state: &AppState, block_number: u64) -> Option<Vec<(String, u64)>> {
+    let infos = match wallet_post(state, "/wallet/gettransactioninfobyblocknum", &json!({ "num": block_number })).await {
+        Ok(infos) => infos,
+        Err(e) => {
+            warn!("Failed to fetch transaction info for block {}: {:#}", block_number, e);
+            return None;
+        }
+    };
+
+    // Blocks without transactions come back as an empty object
+    let infos = infos.as_array().map(Vec::as_slice).unwrap_or_default();
+    Some(
+        infos
+            .iter()
+            .map(|info| {
+                let id = info.get("id").and_then(Value::as_str).unwrap_or_default().to_ascii_lowercase();
+                let net_usage = info.pointer("/receipt/net_usage").and_then(Value::as_u64).unwrap_or(0);
+                (id, net_usage)
+            })
+            .collect(),
+    )
+}
+
+/// Total bandwidth used by a block's transactions, or 0 when bandwidth isn't counted as gas.
+async fn block_net_usage(state: &AppState, block_number: Option<u64>) -> u64 {
+    if state.gas_ratios.bandwidth == 0 {
+        return 0;
+    }
+    let Some(block_number) = block_number else {
+        return 0;
+    };
+
+    fetch_block_net_usage(state, block_number)
+        .await
+        .map(|net_usage| net_usage.iter().map(|(_, net_usage)| net_usage).sum())
+        .unwrap_or(0)
+}
+
+/// A receipt's transaction bandwidth and its block's running bandwidth total, or zeros when
+/// bandwidth isn't counted as gas.
+async fn receipt_net_usage(state: &AppState, receipt: &serde_json::Map<String, Value>) -> (u64, u64) {
+    if state.gas_ratios.bandwidth == 0 {
+        return (0, 0);
+    }
+    let (Some(block_number), Some(tx_hash)) = (
+        receipt.get("blockNumber").and_then(parse_quantity),
+        receipt.get("transactionHash").and_then(Value::as_str),
+    ) else {
+        return (0, 0);
+    };
+
+    let Some(net_usage) = fetch_block_net_usage(state, block_number).await else {
+        return (0, 0);
+    };
+    let tx_id = tx_hash.strip_prefix("0x").unwrap_or(tx_hash).to_ascii_lowercase();
+    let Some(position) = net_usage.iter().position(|(id, _)| *id == tx_id) else {
+        warn!("Transaction {} is missing from block {}'s transaction info", tx_hash, block_number);
+        return (0, 0);
+    };
+
+    let cumulative_net_usage = net_usage[..=position].iter().map(|(_, net_usage)| net_usage).sum();
+    (net_usage[position].1, cumulative_net_usage)
+}
+
+/// Rescales a receipt's energy-denominated `gasUsed` and `cumulativeGasUsed` by the gas
+/// ratios, adding the transaction's bandwidth and the block's running bandwidth total from
+/// `net_usage`. Returns whether it was changed.
+fn convert_receipt_gas(receipt: &mut serde_json::Map<String, Value>, ratios: GasRatios, net_usage: (u64, u64)) -> bool {
+    if ratios.is_identity() {
+        return false;
+    }
+
+    let energy_used = receipt.get("gasUsed").and_then(parse_quantity);
+    let cumulative_energy_used = receipt.get("cumulativeGasUsed").and_then(parse_quantity);
+    let (gas_used, cumulative_gas_used) = tron_resources_to_gas(
+        TronResources {
+            energy_used: energy_used.unwrap_or(0),
+            net_usage: net_usage.0,
+            cumulative_energy_used: cumulative_energy_used.unwrap_or(0),
+            cumulative_net_usage: net_usage.1,
+        },
+        ratios,
+    );
+
+    if energy_used.is_some() {
+        receipt.insert("gasUsed".to_string(), json!(format!("0x{:x}", gas_used)));
+    }
+    if cumulative_energy_used.is_some() {
+        receipt.insert("cumulativeGasUsed".to_string(), json!(format!("0x{:x}", cumulative_gas_used)));
+    }
+    energy_used.is_some() || cumulative_energy_used.is_some()
+}
+
+/// Rescales an energy-denominated gas limit or estimate by `--energy-to-gas-ratio`, so it
+/// stays comparable with the `gasUsed` of receipts. Returns whether it was changed.
+fn convert_gas_limit(value: &mut Value, energy_to_gas_ratio: u64) -> bool {
+    if energy_to_gas_ratio == 1 {
+        return false;
+    }
+    let Some(energy) = parse_quantity(value) else {
+        return false;
+    };
+
+    *value = json!(format!("0x{:x}", energy.saturating_mul(energy_to_gas_ratio)));
+    true
+}
+
+/// Checks an energy estimate against the most energy a transaction can pay for under the
+/// cached chain parameters (`getMaxFeeLimit / getEnergyFee`), failing it with geth's
+/// "gas required exceeds allowance" error when it's over, then rescales it to gas.
+fn enhance_estimate_response(state: &AppState, response_body: &str) -> String {
+    let Ok(mut rpc_response) = serde_json::from_str::<JsonRpcResponse>(response_body) else {
+        return response_body.to_string();
+    };
+    let Some(estimate) = rpc_response.result.as_mut() else {
+        return response_body.to_string();
+    };
+
+    let max_energy = state
+        .chain_params
+        .get()
+        .and_then(|parameters| Some(parameters.max_fee_limit? / parameters.energy_fee.max(1)));
+    if let (Some(energy), Some(max_energy)) = (parse_quantity(estimate), max_energy) && energy > max_energy {
+        warn!("Estimated {} energy is over the {} a transaction's fee limit can buy", energy, max_energy);
+        let allowance = max_energy.saturating_mul(state.gas_ratios.energy);
+        let response = JsonRpcResponse::failure(rpc_response.id, -32000, format!("gas required exceeds allowance ({})", allowance));
+        return serde_json::to_string(&response).unwrap_or_else(|_| response_body.to_string());
+    }
+
+    if !convert_gas_limit(estimate, state.gas_ratios.energy) {
+        return response_body.to_string();
+    }
+    serde_json::to_string(&rpc_response).unwrap_or_else(|_| response_body.to_string())
+}
+
+/// Applies every receipt enhancement to a single receipt object. Returns whether it was changed.
+async fn enhance_receipt(state: &AppState, receipt: &mut serde_json::Map<String, Value>) -> bool {
+    let mut modified = ensure_tx_type(receipt, "transactionHash", &state.pending_txs);
+    mark_if_mined(receipt, "transactionHash", &state.pending_txs);
+    modified |= repair_address_fields(receipt, RECEIPT_ADDRESS_FIELDS);
+    modified |= fill_receipt_logs(receipt);
+    let net_usage = receipt_net_usage(state, receipt).await;
+    modified |= convert_receipt_gas(receipt, state.gas_ratios, net_usage);
+
+    let tx_hash = receipt.get("transactionHash").and_then(Value::as_str).map(str::to_string);
+    let mut contract_result = inline_contract_result(receipt);
+    if contract_result.is_none()
+        && let Some(tx_hash) = tx_hash.as_deref().filter(|hash| state.pending_txs.get(hash).is_some())
+    {
+        contract_result = fetch_contract_result(state, tx_hash).await;
+    }
+
+    if let Some(contract_result) = contract_result {
+        let status = contract_result_status(&contract_result);
+        match receipt.get("status").and_then(Value::as_str) {
+            Some(upstream_status) if upstream_status == status => {}
+            upstream_status => {
+                if let Some(upstream_status) = upstream_status {
+                    warn!(
+                        "Receipt status {} for {} disagrees with TRON result {}, using {}",
+                        upstream_status,
+                        tx_hash.as_deref().unwrap_or("unknown transaction"),
+                        contract_result,
+                        status
+                    );
+                }
+                receipt.insert("status".to_string(), json!(status));
+                modified = true;
+            }
+        }
+    }
+
+    modified
+}
+
+async fn enhance_receipt_response(state: &AppState, response_body: &str) -> String {
+    match serde_json::from_str::<JsonRpcResponse>(response_body) {
+        Ok(mut rpc_response) => {
+            if let Some(receipt) = rpc_response.result.as_mut().and_then(Value::as_object_mut)
+                && enhance_receipt(state, receipt).await
+                && let Ok(modified_response) = serde_json::to_string(&rpc_response)
+            {
+                return modified_response;
+            }
+        }
+        Err(e) => {
+            warn!("Failed to parse response as JSON-RPC for receipt enhancement: {}", e);
+        }
+    }
+
+    response_body.to_string()
+}
+
+/// Answers methods the upstream reported as missing by composing other upstream calls.
+/// Returns `None` for methods without an emulation, leaving the upstream error in place.
+async fn emulate_missing_method(state: &AppState, rpc_request: &JsonRpcRequest) -> Option<JsonRpcResponse> {
+    let block_param = rpc_request.params.as_ref()
+        .and_then(|params| params.get(0))
+        .cloned()
+        .unwrap_or(json!("latest"));
+
+    let result = match rpc_request.method.as_str() {
+        "eth_getBlockReceipts" => {
+            info!("Upstream lacks eth_getBlockReceipts, emulating with eth_getTransactionReceipt");
+            fetch_block_receipts(state, block_param).await
+        }
+        "eth_getBlockTransactionCountByNumber" => {
+            info!("Upstream lacks eth_getBlockTransactionCountByNumber, counting block transactions instead");
+            fetch_block_transaction_count(state, "eth_getBlockByNumber", block_param).await
+        }
+        "eth_getBlockTransactionCountByHash" => {
+            info!("Upstream lacks eth_getBlockTransactionCountByHash, counting block transactions instead");
+            fetch_block_transaction_count(state, "eth_getBlockByHash", block_param).await
+        }
+        _ => return None,
+    };
+
+    Some(match result {
+        Ok(result) => JsonRpcResponse::success(rpc_request.id.clone(), result),
+        Err(e) => {
+            error!("{} emulation failed: {}", rpc_request.method, e);
+            JsonRpcResponse::failure(rpc_request.id.clone(), -32603, format!("{} emulation failed: {}", rpc_request.method, e))
+        }
+    })
+}
+
+/// Fetches a block by hash or number with transaction hashes only.
+async fn fetch_block_hashes_only(state: &AppState, block_param: Value) -> anyhow::Result<Value> {
+    let block_method = match block_param.as_str() {
+        Some(hash) if hash.len() == 66 => "eth_getBlockByHash",
+        _ => "eth_getBlockByNumber",
+    };
+
+    upstream_call(state, block_method, json!([block_param, false])).await
+}
+
+/// Counts a block's transactions, looked up with `block_method`, returning null for unknown
+/// blocks like geth does. The lookup follows the method asked for rather than the shape of
+/// the parameter, so a hash without `0x` isn't mistaken for a block number.
+async fn fetch_block_transaction_count(state: &AppState, block_method: &str, block_param: Value) -> anyhow::Result<Value> {
+    let block = upstream_call(state, block_method, json!([block_param, false])).await?;
+    if block.is_null() {
+        return Ok(Value::Null);
+    }
+
+    let count = block.get("transactions").and_then(Value::as_array).map_or(0, Vec::len);
+    Ok(json!(format!("0x{:x}", count)))
+}
+
+/// Builds an `eth_getBlockReceipts` result from the block's transaction hashes and
+/// individual `eth_getTransactionReceipt` calls, issued with bounded concurrency.
+async fn fetch_block_receipts(state: &AppState, block_param: Value) -> anyhow::Result<Value> {
+    let block = fetch_block_hashes_only(state, block_param).await?;
+    if block.is_null() {
+        return Ok(Value::Null);
+    }
+
+    let tx_hashes: Vec<String> = block.get("transactions")
+        .and_then(Value::as_array)
+        .map(|txs| txs.iter().filter_map(Value::as_str).map(str::to_string).collect())
+        .unwrap_or_default();
+
+    let concurrency = state.receipts_concurrency.min(state.max_batch_size);
+    info!("Fetching {} receipts with concurrency {}", tx_hashes.len(), concurrency);
+
+    let receipts: Vec<Value> = stream::iter(tx_hashes)
+        .map(|tx_hash| async move {
+            let mut receipt = upstream_call(state, "eth_getTransactionReceipt", json!([tx_hash])).await?;
+            let Some(receipt_obj) = receipt.as_object_mut() else {
+                anyhow::bail!("no receipt for transaction {}", tx_hash);
+            };
+            enhance_receipt(state, receipt_obj).await;
+            Ok(receipt)
+        })
+        .buffered(concurrency)
+        .try_collect()
+        .await?;
+
+    Ok(Value::Array(receipts))
+}
+
+/// Fills fields `cast tx` expects but TRON transactions lack. `gas` and `gasPrice` are
+/// derived from the fee limit (in SUN) so that `gas * gasPrice` equals the maximum fee.
+fn fill_transaction_fields(tx: &mut serde_json::Map<String, Value>) -> bool {
+    let mut modified = false;
+
+    let fee_limit = ["feeLimit", "fee_limit"]
+        .into_iter()
+        .find_map(|field| tx.get(field).and_then(parse_quantity));
+
+    let defaults = [
+        ("nonce", json!("0x0")),
+        ("gas", json!(format!("0x{:x}", fee_limit.unwrap_or(0)))),
+        ("gasPrice", json!(if fee_limit.is_some() { "0x1" } else { "0x0" })),
+        ("value", json!("0x0")),
+        ("input", json!("0x")),
+        ("v", json!("0x0")),
+        ("r", json!("0x0")),
+        ("s", json!("0x0")),
+        ("blockHash", Value::Null),
+        ("blockNumber", Value::Null),
+        ("transactionIndex", Value::Null),
+    ];
+
+    for (field, default) in defaults {
+        if !tx.contains_key(field) {
+            debug!("Adding missing transaction field {} = {}", field, default);
+            tx.insert(field.to_string(), default);
+            modified = true;
+        }
+    }
+
+    for field in ["nonce", "gas", "gasPrice", "value", "blockNumber", "transactionIndex"] {
+        modified |= normalize_quantity_field(tx, field);
+    }
+
+    modified
+}
+
+/// Marks a broadcast transaction as confirmed once a receipt or transaction object shows it in a block.
+fn mark_if_mined(obj: &serde_json::Map<String, Value>, hash_field: &str, pending_txs: &PendingTxStore) {
+    if obj.get("blockNumber").is_some_and(|number| !number.is_null())
+        && let Some(hash) = obj.get(hash_field).and_then(Value::as_str)
+    {
+        pending_txs.mark_confirmed(hash);
+    }
+}
+
+/// Applies every transaction enhancement to a single transaction object. Returns whether it was changed.
+fn enhance_transaction(tx: &mut serde_json::Map<String, Value>, pending_txs: &PendingTxStore, energy_to_gas_ratio: u64) -> bool {
+    let mut modified = ensure_tx_type(tx, "hash", pending_txs);
+    mark_if_mined(tx, "hash", pending_txs);
+    // Only a node-reported limit is in energy; one derived from the fee limit is in SUN
+    if let Some(gas) = tx.get_mut("gas") {
+        modified |= convert_gas_limit(gas, energy_to_gas_ratio);
+    }
+    modified |= fill_transaction_fields(tx);
+    modified |= ensure_y_parity(tx);
+    modified |= repair_address_fields(tx, TRANSACTION_ADDRESS_FIELDS);
+    modified
+}
+
+fn enhance_transaction_response(response_body: &str, pending_txs: &PendingTxStore, energy_to_gas_ratio: u64) -> String {
+    match serde_json::from_str::<JsonRpcResponse>(response_body) {
+        Ok(mut rpc_response) => {
+            // Pending or unknown hashes come back as null, which is forwarded untouched
+            if let Some(tx) = rpc_response.result.as_mut().and_then(Value::as_object_mut)
+                && enhance_transaction(tx, pending_txs, energy_to_gas_ratio)
+                && let Ok(modified_response) = serde_json::to_string(&rpc_response)
+            {
+                return modified_response;
+            }
+        }
+        Err(e) => {
+            warn!("Failed to parse response as JSON-RPC for transaction enhancement: {}", e);
+        }
+    }
+
+    response_body.to_string()
+}
+
+fn parse_method_timeout(pair: &str) -> Result<(String, Duration), String> {
+    let (method, millis) = pair
+        .split_once('=')
+        .ok_or_else(|| format!("expected `method=ms`, got '{}'", pair))?;
+    let millis: u64 = millis
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid timeout '{}': {}", millis, e))?;
+
+    Ok((method.trim().to_string(), Duration::from_millis(millis)))
+}
+
+fn parse_native_method(pair: &str) -> Result<(String, String), String> {
+    let (method, path) = pair
+        .split_once('=')
+        .ok_or_else(|| format!("expected `method=path`, got '{}'", pair))?;
+    let path = path.trim();
+    if !path.starts_with('/') {
+        return Err(format!("path '{}' must start with '/'", path));
+    }
+
+    Ok((method.trim().to_string(), path.to_string()))
+}
+
+fn parse_upstream(pair: &str) -> Result<(String, Destination), String> {
+    let (name, url) = pair
+        .split_once('=')
+        .ok_or_else(|| format!("expected `name=url`, got '{}'", pair))?;
+    let name = name.trim();
+
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("upstream name '{}' must be letters, digits, '-' or '_'", name));
+    }
+    if name == "stats" || name == "metrics" {
+        return Err(format!("upstream name '{}' clashes with the /{} endpoint", name, name));
+    }
+    if name == DEFAULT_UPSTREAM {
+        return Err(format!("upstream name '{}' is reserved for --dest", name));
+    }
+
+    Ok((name.to_string(), parse_destination(url)?))
+}
+
+fn parse_route(pair: &str) -> Result<(String, String), String> {
+    let (pattern, name) = pair
+        .split_once('=')
+        .ok_or_else(|| format!("expected `method=upstream`, got '{}'", pair))?;
+    let (pattern, name) = (pattern.trim(), name.trim());
+
+    if pattern.is_empty() || pattern.trim_end_matches('*').contains('*') {
+        return Err(format!("route method '{}' must be a method name or a prefix ending in '*'", pattern));
+    }
+    if name.is_empty() {
+        return Err(format!("route for '{}' has no upstream name", pattern));
+    }
+
+    Ok((pattern.to_string(), name.to_string()))
+}
+
+/// Parses a positive decimal like `1`, `0.5` or `2.25`, with at most 18 decimal places.
+fn parse_fee_limit_scale(scale: &str) -> Result<FeeLimitScale, String> {
+    let invalid = || format!("invalid scale '{}': expected a positive decimal number like 1.5", scale);
+    let (whole, fraction) = scale.split_once('.').unwrap_or((scale, ""));
+    if (whole.is_empty() && fraction.is_empty()) || !format!("{}{}", whole, fraction).bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    if fraction.len() > 18 {
+        return Err(format!("scale '{}' has more than 18 decimal places", scale));
+    }
+
+    let digits = format!("{}{}", whole, fraction);
+    let numerator = digits.parse::<u64>().map_err(|_| invalid())?;
+    if numerator == 0 {
+        return Err(format!("scale must be a positive number, got {}", scale));
+    }
+    Ok(FeeLimitScale { numerator: u128::from(numerator), denominator: 10u128.pow(fraction.len() as u32) })
+}
+
+fn parse_signer_key(private_key: &str) -> Result<Arc<Signer>, String> {
+    Signer::from_hex(private_key).map(Arc::new)
+}
+
+/// Builds the Authorization header for `--upstream-auth` from `basic:USER:PASS` or
+/// `bearer:TOKEN`, marked sensitive so it's never logged.
+fn parse_upstream_auth(auth: &str) -> Result<reqwest::header::HeaderValue, String> {
+    let (scheme, credentials) = auth
+        .split_once(':')
+        .ok_or_else(|| "expected basic:USER:PASS or bearer:TOKEN".to_string())?;
+
+    let value = match scheme.to_ascii_lowercase().as_str() {
+        "basic" => {
+            if !credentials.contains(':') {
+                return Err("basic auth expects basic:USER:PASS".to_string());
+            }
+            format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(credentials))
+        }
+        "bearer" if !credentials.is_empty() => format!("Bearer {}", credentials),
+        "bearer" => return Err("bearer auth expects a token".to_string()),
+        _ => return Err(format!("unknown auth scheme '{}': expected basic or bearer", scheme)),
+    };
+
+    let mut value = reqwest::header::HeaderValue::from_str(&value).map_err(|_| "credentials contain invalid characters".to_string())?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+fn parse_chain_id(chain_id: &str) -> Result<u64, String> {
+    match chain_id.strip_prefix("0x") {
+        Some(chain_id_hex) => u64::from_str_radix(chain_id_hex, 16),
+        None => chain_id.parse(),
+    }
+    .map_err(|e| format!("invalid chain id '{}': {}", chain_id, e))
+}
+
+fn parse_base_path(base_path: &str) -> Result<String, String> {
+    let trimmed = base_path.trim_end_matches('/');
+    if !trimmed.starts_with('/') {
+        return Err(format!("base path '{}' must start with '/'", base_path));
+    }
+    Ok(trimmed.to_string())
+}
+
+/// Accepts `*` or a single origin, `scheme://host[:port]` with nothing after it.
+fn parse_cors_origin(origin: &str) -> Result<HeaderValue, String> {
+    let invalid = || format!("invalid CORS origin '{}': expected * or an origin such as https://app.example.com", origin);
+    if origin != "*" {
+        let url = Url::parse(origin).map_err(|_| invalid())?;
+        if url.origin().ascii_serialization() != origin.trim_end_matches('/') {
+            return Err(invalid());
+        }
+    }
+    HeaderValue::from_str(origin.trim_end_matches('/')).map_err(|_| invalid())
+}
+
+fn parse_rewrite_pair(pair: &str) -> Result<(String, String), String> {
+    let (from, to) = pair
+        .split_once('=')
+        .ok_or_else(|| format!("expected `from=to`, got '{}'", pair))?;
+
+    let from = parse_address(from.trim())
+        .ok_or_else(|| format!("invalid address '{}'", from))?;
+    let to = parse_address(to.trim())
+        .and_then(|to| convert_eth_to_tron_address(&to))
+        .ok_or_else(|| format!("invalid address '{}'", to))?;
+
+    Ok((from, to))
+}
+
+/// Replaces the `to` field of a call object when it matches a `--rewrite-to` mapping.
+/// Not applied to `eth_sendRawTransaction`: its `to` is covered by the signature, so a
+/// rewritten payload would no longer verify.
+fn apply_to_rewrite(obj: &mut serde_json::Map<String, Value>, rewrites: &HashMap<String, String>, address_cache: &AddressCache) {
+    if rewrites.is_empty() {
+        return;
+    }
+
+    let Some(original) = obj.get("to").and_then(Value::as_str).map(str::to_string) else {
+        return;
+    };
+
+    if let Some(replacement) = normalize_address(address_cache, &original).and_then(|to| rewrites.get(&to)) {
+        info!("Rewrote 'to' address {} -> {}", original, replacement);
+        obj.insert("to".to_string(), json!(replacement));
+    }
+}
+
+fn parse_contract_address(address: &str) -> Result<String, String> {
+    parse_address(address.trim()).ok_or_else(|| format!("invalid address '{}'", address))
+}
+
+fn parse_owner_address(address: &str) -> Result<String, String> {
+    parse_address(address.trim())
+        .and_then(|address| convert_eth_to_tron_address(&address))
+        .ok_or_else(|| format!("invalid address '{}'", address))
+}
+
+/// Sends eth_call's `from` in the TRON form the node uses as `owner_address`. An absent or
+/// zero `from` is replaced with `--default-owner` when one is configured.
+fn normalize_call_owner(obj: &mut serde_json::Map<String, Value>, default_owner: Option<&str>, address_cache: &AddressCache) {
+    let from = obj.get("from").and_then(Value::as_str).and_then(|from| normalize_address(address_cache, from));
+
+    let owner = match from {
+        Some(from) if from != ZERO_ADDRESS => convert_eth_to_tron_address(&from),
+        _ => default_owner.map(str::to_string),
+    };
+
+    if let Some(owner) = owner && obj.get("from").and_then(Value::as_str) != Some(owner.as_str()) {
+        info!("Using '{}' as eth_call owner address", owner);
+        obj.insert("from".to_string(), json!(owner));
+    }
+}
+
+/// Makes a block's `transactions` match what was asked for: enhanced transaction objects
+/// when `full_transactions` is set, otherwise `0x`-prefixed 32-byte hashes.
+/// Returns whether the array was changed.
+fn normalize_block_transactions(
+    block: &mut serde_json::Map<String, Value>,
+    full_transactions: bool,
+    pending_txs: &PendingTxStore,
+    energy_to_gas_ratio: u64,
+) -> bool {
+    let Some(transactions) = block.get_mut("transactions").and_then(Value::as_array_mut) else {
+        return false;
+    };
+
+    let mut modified = false;
+
+    if full_transactions {
+        for tx in transactions.iter_mut().filter_map(Value::as_object_mut) {
+            modified |= enhance_transaction(tx, pending_txs, energy_to_gas_ratio);
+        }
+        return modified;
+    }
+
+    let original_len = transactions.len();
+    let mut hashes = Vec::with_capacity(original_len);
+    for tx in transactions.iter() {
+        let hash = match tx {
+            Value::String(hash) => Some(hash.as_str()),
+            // Some nodes embed objects even when only hashes were requested
+            Value::Object(tx) => tx.get("hash").and_then(Value::as_str),
+            _ => None,
+        };
+
+        let normalized = hash.map(|hash| hash.strip_prefix("0x").unwrap_or(hash).to_ascii_lowercase())
+            .filter(|hash| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+            .map(|hash| json!(format!("0x{}", hash)));
+
+        match normalized {
+            Some(normalized) => {
+                modified |= normalized != *tx;
+                hashes.push(normalized);
+            }
+            None => {
+                warn!("Dropping malformed transaction hash {} from block", tx);
+                modified = true;
+            }
+        }
+    }
+
+    if modified {
+        info!("Normalized {} transaction hashes in block", original_len);
+        *transactions = hashes;
+    }
+
+    modified
+}
+
+fn enhance_block_response(
+    response_body: &str,
+    method: &str,
+    full_transactions: bool,
+    pending_txs: &PendingTxStore,
+    gas_ratios: GasRatios,
+    net_usage: u64,
+) -> String {
+    match serde_json::from_str::<JsonRpcResponse>(response_body) {
+        Ok(mut rpc_response) => {
+            if let Some(result) = &mut rpc_response.result && let Some(block) = result.as_object_mut() {
+                let mut modified = false;
+
+                // Check if stateRoot is missing or invalid
+                let needs_state_root_fix = match block.get("stateRoot") {
+                    None => {
+                        info!("Adding missing stateRoot to {} response", method);
+                        true
+                    }
+                    Some(state_root) => {
+                        if let Some(state_root_str) = state_root.as_str() {
+                            // Check if stateRoot is invalid (empty "0x" or not 66 characters)
+                            if state_root_str == "0x" || state_root_str.len() != 66 {
+                                info!("Fixing invalid stateRoot '{}' in {} response", state_root_str, method);
+                                true
+                            } else {
+                                false
+                            }
+                        } else {
+                            info!("Fixing non-string stateRoot in {} response", method);
+                            true
+                        }
+                    }
+                };
+
+                if needs_state_root_fix {
+                    block.insert(
+                        "stateRoot".to_string(),
+                        json!("0x0101010101010101010101010101010101010101010101010101010101010101")
+                    );
+                    modified = true;
+                }
+
+                modified |= normalize_block_transactions(block, full_transactions, pending_txs, gas_ratios.energy);
+
+                // A block's resources are also the running totals after its last transaction
+                if !gas_ratios.is_identity()
+                    && let Some(energy_used) = block.get("gasUsed").and_then(parse_quantity)
+                {
+                    let resources = TronResources {
+                        energy_used,
+                        net_usage,
+                        cumulative_energy_used: energy_used,
+                        cumulative_net_usage: net_usage,
+                    };
+                    let (gas_used, _) = tron_resources_to_gas(resources, gas_ratios);
+                    block.insert("gasUsed".to_string(), json!(format!("0x{:x}", gas_used)));
+                    modified = true;
+                }
+
+                // Return the modified response if any changes were made
+                if modified && let Ok(modified_response) = serde_json::to_string(&rpc_response) {
+                    return modified_response;
+                }
+            }
+        }
+        Err(e) => {
+            warn!("Failed to parse response as JSON-RPC for block enhancement: {}", e);
+        }
+    }
+
+    // Return original response if no modification was needed or possible
+    response_body.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{rpc_reply, MockReply, MockUpstream, TestProxy};
+
+    #[test]
+    fn method_not_found_is_recognized_by_code_or_tron_message() {
+        let by_code = r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32601,"message":"unknown method"}}"#;
+        let by_message = r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"the method eth_getBlockReceipts does not exist/is not available"}}"#;
+        assert!(is_method_not_found(by_code));
+        assert!(is_method_not_found(by_message));
+    }
+
+    #[test]
+    fn other_not_found_errors_are_not_method_not_found() {
+        let block = r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"block not found"}}"#;
+        let account = r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"account does not exist"}}"#;
+        let success = r#"{"jsonrpc":"2.0","id":1,"result":"not found"}"#;
+        assert!(!is_method_not_found(block));
+        assert!(!is_method_not_found(account));
+        assert!(!is_method_not_found(success));
+    }
+
+    #[test]
+    fn named_upstreams_keep_their_own_filters_and_transactions() {
+        let args = Args::try_parse_from([
+            "tron-foundry-proxy",
+            "--port",
+            "0",
+            "--dest",
+            "http://127.0.0.1:1",
+            "--upstream",
+            "nile=http://127.0.0.1:2",
+        ])
+        .unwrap();
+        let state = build_state(&args).unwrap();
+        let nile = &state.upstreams["nile"];
+
+        assert!(!Arc::ptr_eq(&state.filters, &nile.filters));
+        assert!(!Arc::ptr_eq(&state.pending_txs, &nile.pending_txs));
+    }
+
+    #[tokio::test]
+    async fn filters_are_only_visible_on_their_upstream() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x10"))).await;
+        let nile = format!("nile={}/jsonrpc", upstream.url);
+        let proxy = TestProxy::start(&upstream, &["--upstream", &nile]).await;
+
+        let id = proxy.rpc("eth_newBlockFilter", json!([])).await["result"].clone();
+        let uninstall = json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_uninstallFilter", "params": [id] }).to_string();
+
+        let on_nile: Value = proxy.post_text("/nile", &uninstall).await.json().await.unwrap();
+        assert_eq!(on_nile["result"], false);
+        let on_default: Value = proxy.post_text("/", &uninstall).await.json().await.unwrap();
+        assert_eq!(on_default["result"], true);
+    }
+
+    #[test]
+    fn cors_origin_must_be_an_origin() {
+        assert_eq!(parse_cors_origin("*").unwrap(), "*");
+        assert_eq!(parse_cors_origin("https://app.example.com").unwrap(), "https://app.example.com");
+        assert_eq!(parse_cors_origin("http://localhost:3000/").unwrap(), "http://localhost:3000");
+
+        assert!(parse_cors_origin("app.example.com").is_err());
+        assert!(parse_cors_origin("https://app.example.com/path").is_err());
+        assert!(parse_cors_origin("https://app.example.com\nx-injected: 1").is_err());
+        assert!(Args::try_parse_from(["tron-foundry-proxy", "--port", "0", "--dest", "http://127.0.0.1:1", "--cors-origin", "bad origin"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn cors_origin_is_sent_as_given() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x10"))).await;
+        let proxy = TestProxy::start(&upstream, &["--cors-origin", "https://app.example.com"]).await;
+
+        let response = proxy.post(&json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": [] })).await;
+        assert_eq!(response.headers()["access-control-allow-origin"], "https://app.example.com");
+        assert_eq!(response.headers()["vary"], "Origin");
+    }
+
+    const TX_HASH: &str = "0x5c1d3e2f8a9b7c6d5e4f30211203f4e5d6c7b8a99a8b7c6d5e4f3a2b1c0d9e8f";
+
+    /// A node whose block 0x10 holds one transaction with a 1000 energy limit that used 100
+    /// energy and 268 bandwidth.
+    async fn gas_node() -> MockUpstream {
+        MockUpstream::start(|hit| match hit.path.as_str() {
+            "/wallet/gettransactioninfobyblocknum" => MockReply::json(json!([
+                { "id": &TX_HASH[2..], "receipt": { "energy_usage_total": 100, "net_usage": 268 } }
+            ])),
+            _ => rpc_reply(hit, |method, _| match method {
+                "eth_getBlockByNumber" => Ok(json!({
+                    "number": "0x10",
+                    "gasUsed": "0x64",
+                    "transactions": [{ "hash": TX_HASH, "blockNumber": "0x10", "gas": "0x3e8", "gasPrice": "0x1a4" }],
+                })),
+                "eth_getTransactionByHash" => Ok(json!({ "hash": TX_HASH, "blockNumber": "0x10", "gas": "0x3e8", "gasPrice": "0x1a4" })),
+                "eth_getTransactionReceipt" => Ok(json!({
+                    "transactionHash": TX_HASH,
+                    "blockNumber": "0x10",
+                    "gasUsed": "0x64",
+                    "cumulativeGasUsed": "0x64",
+                    "status": "0x1",
+                    "logs": [],
+                })),
+                "eth_estimateGas" => Ok(json!("0x3e8")),
+                _ => Err(json!({ "code": -32601, "message": "method not found" })),
+            }),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn receipt_and_block_report_the_same_gas_used() {
+        let upstream = gas_node().await;
+        let proxy = TestProxy::start(&upstream, &["--energy-to-gas-ratio", "3", "--bandwidth-to-gas-ratio", "2"]).await;
+
+        let receipt = proxy.rpc("eth_getTransactionReceipt", json!([TX_HASH])).await["result"].clone();
+        let block = proxy.rpc("eth_getBlockByNumber", json!(["0x10", true])).await["result"].clone();
+
+        // 100 energy * 3 + 268 bandwidth * 2
+        assert_eq!(receipt["gasUsed"], "0x344");
+        assert_eq!(receipt["cumulativeGasUsed"], "0x344");
+        assert_eq!(block["gasUsed"], receipt["cumulativeGasUsed"]);
+    }
+
+    #[tokio::test]
+    async fn gas_limits_and_estimates_use_the_energy_ratio() {
+        let upstream = gas_node().await;
+        let proxy = TestProxy::start(&upstream, &["--energy-to-gas-ratio", "3"]).await;
+
+        let estimate = proxy.rpc("eth_estimateGas", json!([{ "to": "0x95222290dd7278aa3ddd389cc1e1d165cc4bafe5" }])).await;
+        let tx = proxy.rpc("eth_getTransactionByHash", json!([TX_HASH])).await["result"].clone();
+        let block = proxy.rpc("eth_getBlockByNumber", json!(["0x10", true])).await["result"].clone();
+        let receipt = proxy.rpc("eth_getTransactionReceipt", json!([TX_HASH])).await["result"].clone();
+
+        assert_eq!(estimate["result"], "0xbb8");
+        assert_eq!(tx["gas"], "0xbb8");
+        assert_eq!(block["transactions"][0]["gas"], "0xbb8");
+        assert_eq!(receipt["gasUsed"], "0x12c");
+        // Bandwidth isn't fetched unless it counts as gas
+        assert!(upstream.hits().iter().all(|hit| hit.path == "/jsonrpc"));
+    }
+
+    #[test]
+    fn resources_convert_to_gas_by_ratio() {
+        let resources = TronResources { energy_used: 10, net_usage: 5, cumulative_energy_used: 30, cumulative_net_usage: 15 };
+
+        assert_eq!(tron_resources_to_gas(resources, GasRatios { energy: 1, bandwidth: 0 }), (10, 30));
+        assert_eq!(tron_resources_to_gas(resources, GasRatios { energy: 2, bandwidth: 1 }), (25, 75));
+        let huge = TronResources { energy_used: u64::MAX, ..resources };
+        assert_eq!(tron_resources_to_gas(huge, GasRatios { energy: 2, bandwidth: 1 }).0, u64::MAX);
+    }
+
+    /// ABI encoding of `Error("nope")`
+    const NOPE_REVERT: &str = concat!(
+        "08c379a0",
+        "0000000000000000000000000000000000000000000000000000000000000020",
+        "0000000000000000000000000000000000000000000000000000000000000004",
+        "6e6f706500000000000000000000000000000000000000000000000000000000",
+    );
+
+    #[test]
+    fn tron_revert_becomes_a_geth_revert() {
+        let tron = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": { "code": -32000, "message": "REVERT opcode executed", "data": NOPE_REVERT },
+        });
+
+        let enhanced: Value = serde_json::from_str(&enhance_call_response(&tron.to_string())).unwrap();
+        assert_eq!(
+            enhanced["error"],
+            json!({ "code": 3, "message": "execution reverted: nope", "data": format!("0x{}", NOPE_REVERT) })
+        );
+    }
+
+    #[test]
+    fn revert_without_data_gets_empty_revert_data() {
+        let tron = json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": -32000, "message": "REVERT opcode executed" } });
+
+        let enhanced: Value = serde_json::from_str(&enhance_call_response(&tron.to_string())).unwrap();
+        assert_eq!(enhanced["error"], json!({ "code": 3, "message": "execution reverted", "data": "0x" }));
+    }
+
+    #[test]
+    fn errors_that_only_mention_reverts_pass_through() {
+        let unrelated = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": { "code": -32000, "message": "cannot revert to snapshot: not supported" },
+        })
+        .to_string();
+
+        assert_eq!(enhance_call_response(&unrelated), unrelated);
+    }
+
+    #[test]
+    fn failed_constant_result_becomes_a_revert() {
+        let gateway = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": { "constant_result": [NOPE_REVERT], "result": { "result": false } },
+        });
+        let succeeded = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": { "constant_result": ["00000000000000000000000000000000000000000000000000000000000000ff"], "result": { "result": true } },
+        });
+
+        let reverted: Value = serde_json::from_str(&enhance_call_response(&gateway.to_string())).unwrap();
+        assert_eq!(reverted["error"]["message"], "execution reverted: nope");
+        assert!(reverted.get("result").is_none());
+        let returned: Value = serde_json::from_str(&enhance_call_response(&succeeded.to_string())).unwrap();
+        assert_eq!(returned["result"], "0x00000000000000000000000000000000000000000000000000000000000000ff");
+    }
+
+    /// Sends a request from a client that gives up after 100ms and closes its connection.
+    async fn abandon(proxy: &TestProxy, method: &str, params: Value) {
+        let client = reqwest::Client::builder().timeout(Duration::from_millis(100)).build().unwrap();
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        assert!(client.post(&proxy.url).json(&request).send().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn slow_reads_are_cancelled_with_a_disconnected_client() {
+        let upstream = MockUpstream::rpc_with_delay(Duration::from_millis(300), |_, _| Ok(json!("0x"))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        abandon(&proxy, "eth_call", json!([{ "to": "0x95222290dd7278aa3ddd389cc1e1d165cc4bafe5" }, "latest"])).await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(upstream.methods(), ["eth_call"]);
+        assert!(upstream.completed().is_empty());
+    }
+
+    #[tokio::test]
+    async fn broadcasts_outlive_a_disconnected_client() {
+        let upstream = MockUpstream::rpc_with_delay(Duration::from_millis(300), |_, _| Ok(json!(TX_HASH))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        abandon(&proxy, "eth_sendRawTransaction", json!(["0x02f8"])).await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(upstream.completed(), ["/jsonrpc"]);
+    }
+
+    #[tokio::test]
+    async fn replicas_serve_native_calls_with_their_own_api() {
+        let dest = MockUpstream::start(|_| MockReply::json(json!({ "blockID": "dest" }))).await;
+        let replica = MockUpstream::start(|_| MockReply::json(json!({ "blockID": "replica" }))).await;
+        let replica_url = format!("{}/jsonrpc", replica.url);
+        let proxy = TestProxy::start(&dest, &["--dest-replica", &replica_url, "--native-prefix", "tron_"]).await;
+
+        let first = proxy.rpc("tron_getNowBlock", json!([])).await;
+        let second = proxy.rpc("tron_getNowBlock", json!([])).await;
+
+        assert_eq!(first["result"]["blockID"], "dest");
+        assert_eq!(second["result"]["blockID"], "replica");
+        assert_eq!(replica.hits()[0].path, "/wallet/getnowblock");
+    }
+
+    #[tokio::test]
+    async fn rate_limits_and_auth_failures_mark_the_upstream_down() {
+        for status in [StatusCode::TOO_MANY_REQUESTS, StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN] {
+            let upstream = MockUpstream::start(move |_| MockReply { status, ..MockReply::json(json!({})) }).await;
+            let proxy = TestProxy::start(&upstream, &[]).await;
+
+            proxy.rpc("eth_blockNumber", json!([])).await;
+
+            let metrics = proxy.client.get(format!("{}/metrics", proxy.url)).send().await.unwrap().text().await.unwrap();
+            let labels = format!("upstream=\"default\",url=\"{}/jsonrpc\"", upstream.url);
+            assert!(metrics.contains(&format!("proxy_upstream_up{{{}}} 0\n", labels)), "{}", status);
+            assert!(metrics.contains(&format!("proxy_upstream_failures_total{{{}}} 1\n", labels)), "{}", status);
+        }
+    }
+
+    fn call_object(value: Value) -> serde_json::Map<String, Value> {
+        json!({ "to": "0x41d8da6bf26964af9d7eed9e03e53415d37aa96045", "value": value }).as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn one_ether_is_scaled_to_a_million_sun() {
+        let mut obj = call_object(json!("0xde0b6b3a7640000"));
+        scale_call_value(&mut obj, 18).unwrap();
+        assert_eq!(obj["value"], json!(format!("0x{:x}", 1_000_000)));
+    }
+
+    #[test]
+    fn fractions_of_a_sun_are_rejected() {
+        // 1 SUN and 1 wei
+        let mut obj = call_object(json!("0xe8d4a51001"));
+        let (code, message) = scale_call_value(&mut obj, 18).unwrap_err();
+        assert_eq!(code, -32602);
+        assert!(message.contains("not a whole number of SUN"), "{}", message);
+        assert_eq!(obj["value"], "0xe8d4a51001");
+    }
+
+    #[test]
+    fn values_already_in_sun_pass_through() {
+        let mut obj = call_object(json!("0xf4241"));
+        scale_call_value(&mut obj, SUN_DECIMALS).unwrap();
+        assert_eq!(obj["value"], "0xf4241");
+    }
+
+    #[test]
+    fn mismatched_chain_ids_are_rewritten() {
+        let tx = r#"{"jsonrpc":"2.0","id":1,"result":{"hash":"0x01","chainId":"0x1","value":"0x1","blockNumber":"0x10"}}"#;
+        let rewritten: Value = serde_json::from_str(&rewrite_chain_ids(tx.to_string(), 3448148188)).unwrap();
+        assert_eq!(rewritten["result"]["chainId"], "0xcd8690dc");
+        assert_eq!(rewritten["result"]["value"], "0x1");
+        assert_eq!(rewritten["result"]["blockNumber"], "0x10");
+
+        let block = r#"{"jsonrpc":"2.0","id":1,"result":{"number":"0x10","transactions":[{"chainId":"0x1"},{"chainId":"0xcd8690dc"},{"hash":"0x02"}]}}"#;
+        let rewritten: Value = serde_json::from_str(&rewrite_chain_ids(block.to_string(), 3448148188)).unwrap();
+        assert_eq!(
+            rewritten["result"]["transactions"],
+            json!([{ "chainId": "0xcd8690dc" }, { "chainId": "0xcd8690dc" }, { "hash": "0x02" }])
+        );
+        assert!(rewritten["result"].get("chainId").is_none());
+    }
+
+    #[test]
+    fn matching_chain_ids_are_left_alone() {
+        let tx = r#"{"jsonrpc":"2.0","id":1,"result":{"chainId":"0xcd8690dc","value":"0x01"}}"#;
+        assert_eq!(rewrite_chain_ids(tx.to_string(), 3448148188), tx);
+    }
+
+    #[tokio::test]
+    async fn forwarded_transactions_report_the_configured_chain_id() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!({ "hash": TX_HASH, "chainId": "0x1", "input": "0x" }))).await;
+        let proxy = TestProxy::start(&upstream, &["--chain-id", "0x2b6653dc"]).await;
+
+        let response = proxy.rpc("eth_getTransactionByHash", json!([TX_HASH])).await;
+        assert_eq!(response["result"]["chainId"], "0x2b6653dc");
+        assert_eq!(response["result"]["hash"], TX_HASH);
+    }
+
+    #[tokio::test]
+    async fn json_requests_go_upstream_as_plain_application_json() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x10"))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        for content_type in ["application/json; charset=UTF-8", "text/plain"] {
+            let response = proxy
+                .client
+                .post(&proxy.url)
+                .header("content-type", content_type)
+                .body(json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": [] }).to_string())
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+        }
+
+        let content_types: Vec<_> = upstream.hits().iter().map(|hit| hit.headers["content-type"].clone()).collect();
+        assert_eq!(content_types, ["application/json", "application/json"]);
+    }
+
+    #[tokio::test]
+    async fn responses_without_a_content_type_are_labelled_json() {
+        let upstream = MockUpstream::start(|hit| MockReply {
+            headers: vec![],
+            ..rpc_reply(hit, |_, _| Ok(json!("0x10")))
+        })
+        .await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let posted = proxy.post(&json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": [] })).await;
+        assert_eq!(posted.headers()["content-type"], JSON_CONTENT_TYPE);
+        let fetched = proxy.client.get(format!("{}/wallet/getnowblock", proxy.url)).send().await.unwrap();
+        assert_eq!(fetched.headers()["content-type"], JSON_CONTENT_TYPE);
+    }
+
+    fn normalized_quantity(value: Value) -> Result<Value, OverrideError> {
+        let mut obj = json!({ "gas": value }).as_object().unwrap().clone();
+        normalize_call_quantity(&mut obj, "gas")?;
+        Ok(obj.get("gas").cloned().unwrap_or(Value::Null))
+    }
+
+    #[test]
+    fn call_quantities_become_canonical_hex() {
+        assert_eq!(normalized_quantity(json!("21000")).unwrap(), "0x5208");
+        assert_eq!(normalized_quantity(json!(21000)).unwrap(), "0x5208");
+        assert_eq!(normalized_quantity(json!("0X5208")).unwrap(), "0x5208");
+        assert_eq!(normalized_quantity(json!("0x0005208")).unwrap(), "0x5208");
+        assert_eq!(normalized_quantity(json!(" 0x5208 ")).unwrap(), "0x5208");
+        assert_eq!(normalized_quantity(json!("0x0")).unwrap(), "0x0");
+        assert_eq!(normalized_quantity(Value::Null).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn malformed_call_quantities_are_rejected() {
+        for value in [json!("+21000"), json!("0x+5208"), json!(-1), json!(1.5), json!("0xg"), json!(true)] {
+            let (code, _) = normalized_quantity(value.clone()).unwrap_err();
+            assert_eq!(code, -32602, "{}", value);
+        }
+    }
+
+    #[test]
+    fn estimate_objects_with_mixed_encodings_are_normalized() {
+        let mut obj = json!({
+            "from": "0x41d8da6bf26964af9d7eed9e03e53415d37aa96045",
+            "gas": 21000,
+            "maxFeePerGas": "420",
+            "maxPriorityFeePerGas": "0x1",
+            "value": "1000000000000000000",
+            "input": "a9059cbb",
+        })
+        .as_object()
+        .unwrap()
+        .clone();
+        normalize_call_object(&mut obj, 18).unwrap();
+
+        assert_eq!(obj["gas"], "0x5208");
+        assert_eq!(obj["gasPrice"], "0x1a4");
+        assert_eq!(obj["value"], json!(format!("0x{:x}", 1_000_000)));
+        assert_eq!(obj["data"], "0xa9059cbb");
+        assert!(!obj.contains_key("maxFeePerGas") && !obj.contains_key("maxPriorityFeePerGas"));
+    }
+
+    #[test]
+    fn senders_stick_to_one_pool_member() {
+        let pool: Vec<_> = (1..=4).map(|n| parse_destination(&format!("http://node{}.example.com", n)).unwrap()).collect();
+        let senders: Vec<_> = (0..32).map(|n| format!("0x{:040x}", n)).collect();
+        let chosen: Vec<_> = senders.iter().map(|sender| sticky_pool_member(&pool, sender).jsonrpc.clone()).collect();
+
+        // The same sender always gets the same member, whatever order the pool is in
+        let mut reversed = pool.clone();
+        reversed.reverse();
+        for (sender, member) in senders.iter().zip(&chosen) {
+            assert_eq!(&sticky_pool_member(&pool, sender).jsonrpc, member);
+            assert_eq!(&sticky_pool_member(&reversed, sender).jsonrpc, member);
+        }
+        // ...and senders are spread over the pool
+        assert!(pool.iter().all(|member| chosen.contains(&member.jsonrpc)));
+
+        // Dropping a member only moves the senders it served
+        let dropped = &pool[0].jsonrpc;
+        for (sender, member) in senders.iter().zip(&chosen) {
+            if member != dropped {
+                assert_eq!(&sticky_pool_member(&pool[1..], sender).jsonrpc, member);
+            }
+        }
+    }
+
+    #[test]
+    fn broadcasts_from_one_sender_go_to_one_replica() {
+        let args = Args::try_parse_from([
+            "tron-foundry-proxy",
+            "--port",
+            "0",
+            "--dest",
+            "http://node1.example.com",
+            "--dest-replica",
+            "http://node2.example.com",
+            "--dest-replica",
+            "http://node3.example.com",
+            "--sticky-upstream-by-sender",
+        ])
+        .unwrap();
+        let state = build_state(&args).unwrap();
+        let send = |from: &str| JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            method: "eth_sendTransaction".to_string(),
+            params: Some(json!([{ "from": from, "to": from }])),
+            id: Some(json!(1)),
+        };
+
+        let sender = "0xd8da6bf26964af9d7eed9e03e53415d37aa96045";
+        let first = select_pool_member(state.clone(), &send(sender));
+        for _ in 0..5 {
+            let again = select_pool_member(state.clone(), &send(sender));
+            assert_eq!(again.destination, first.destination);
+            assert_eq!(again.wallet_dest, first.wallet_dest);
+        }
+    }
+
+    #[tokio::test]
+    async fn unmatched_gets_keep_their_path() {
+        let upstream = MockUpstream::start(|_| MockReply::json(json!({ "blockID": "00" }))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let response = proxy.client.get(format!("{}/wallet/getnowblock?visible=true", proxy.url)).send().await.unwrap();
+        assert_eq!(response.json::<Value>().await.unwrap()["blockID"], "00");
+        proxy.client.get(format!("{}/net/info", proxy.url)).send().await.unwrap();
+
+        let hits = upstream.hits();
+        assert_eq!(hits[0].method, Method::GET);
+        assert_eq!((hits[0].path.as_str(), hits[0].query.as_deref()), ("/wallet/getnowblock", Some("visible=true")));
+        // Paths outside the node's HTTP API stay below the destination
+        assert_eq!(hits[1].path, "/jsonrpc/net/info");
+    }
+
+    #[tokio::test]
+    async fn unmatched_posts_keep_their_body() {
+        let upstream = MockUpstream::start(|hit| MockReply::json(json!({ "echo": hit.body }))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let body = r#"{"address":"TLa2f6VPqDgRE67v1736s7bJ8Ray5wYjU7","visible":true}"#;
+        let response = proxy.post_text("/wallet/getaccount", body).await;
+        assert_eq!(response.json::<Value>().await.unwrap()["echo"], body);
+
+        let hit = &upstream.hits()[0];
+        assert_eq!((&hit.method, hit.path.as_str()), (&Method::POST, "/wallet/getaccount"));
+        assert_eq!(hit.body, body);
+    }
+
+    #[tokio::test]
+    async fn unmatched_paths_keep_their_encoding() {
+        let upstream = MockUpstream::start(|_| MockReply::json(json!({}))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        proxy.client.get(format!("{}/wallet/get%20asset/%E2%9C%93?name=a%2Fb", proxy.url)).send().await.unwrap();
+
+        let hit = &upstream.hits()[0];
+        assert_eq!(hit.path, "/wallet/get%20asset/%E2%9C%93");
+        assert_eq!(hit.query.as_deref(), Some("name=a%2Fb"));
+    }
+
+    /// The query pairs the upstream received for a GET of the proxy's root with `query`.
+    async fn forwarded_query(query: &str) -> (String, Vec<(String, String)>) {
+        let upstream = MockUpstream::start(|_| MockReply::json(json!({}))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+        proxy.client.get(format!("{}/?{}", proxy.url, query)).send().await.unwrap();
+
+        let hit = &upstream.hits()[0];
+        assert_eq!(hit.path, "/jsonrpc");
+        let query = hit.query.clone().unwrap_or_default();
+        let pairs = url::form_urlencoded::parse(query.as_bytes()).into_owned().collect();
+        (query, pairs)
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn get_queries_keep_reserved_characters_in_their_values() {
+        let (query, received) = forwarded_query("data=YT0xJmI9Mg%3D%3D&q=a%26b%3Dc%20d&name=%E2%9C%93").await;
+        assert_eq!(received, pairs(&[("data", "YT0xJmI9Mg=="), ("q", "a&b=c d"), ("name", "✓")]));
+        assert!(!query.contains("a&b"), "{}", query);
+    }
+
+    #[tokio::test]
+    async fn get_queries_keep_duplicate_keys_in_order() {
+        let (_, received) = forwarded_query("tag=b&limit=2&tag=a").await;
+        assert_eq!(received, pairs(&[("tag", "b"), ("limit", "2"), ("tag", "a")]));
+    }
+
+    #[tokio::test]
+    async fn get_queries_keep_empty_values() {
+        let (_, received) = forwarded_query("cursor=&visible=true").await;
+        assert_eq!(received, pairs(&[("cursor", ""), ("visible", "true")]));
+    }
+
+    #[test]
+    fn only_jsonrpc_2_0_passes_the_version_check() {
+        assert_eq!(jsonrpc_version_error(r#"{"jsonrpc":"2.0","id":1,"method":"eth_chainId"}"#), None);
+        assert_eq!(
+            jsonrpc_version_error(r#"{"id":1,"method":"eth_chainId"}"#),
+            Some((json!(1), "invalid request: missing jsonrpc version".to_string()))
+        );
+        assert_eq!(
+            jsonrpc_version_error(r#"{"jsonrpc":"1.0","id":"a","method":"eth_chainId"}"#),
+            Some((json!("a"), "invalid request: unsupported jsonrpc version \"1.0\"".to_string()))
+        );
+        assert_eq!(
+            jsonrpc_version_error(r#"{"jsonrpc":2.0,"method":"eth_chainId"}"#),
+            Some((Value::Null, "invalid request: unsupported jsonrpc version 2.0".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn strict_jsonrpc_rejects_other_versions() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x10"))).await;
+        let proxy = TestProxy::start(&upstream, &["--strict-jsonrpc"]).await;
+
+        for request in [
+            json!({ "id": 1, "method": "eth_blockNumber", "params": [] }),
+            json!({ "jsonrpc": "1.0", "id": 1, "method": "eth_blockNumber", "params": [] }),
+        ] {
+            let response: Value = proxy.post(&request).await.json().await.unwrap();
+            assert_eq!(response["error"]["code"], -32600, "{}", request);
+            assert_eq!(response["id"], 1);
+        }
+        assert_eq!(proxy.rpc("eth_blockNumber", json!([])).await["result"], "0x10");
+        assert_eq!(upstream.hits().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn strict_jsonrpc_checks_each_batch_entry() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x10"))).await;
+        let proxy = TestProxy::start(&upstream, &["--strict-jsonrpc"]).await;
+
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": [] },
+            { "id": 2, "method": "eth_blockNumber", "params": [] },
+            { "jsonrpc": "2.1", "id": 3, "method": "eth_blockNumber", "params": [] },
+        ]);
+        let responses: Value = proxy.post(&batch).await.json().await.unwrap();
+
+        assert_eq!(responses[0]["result"], "0x10");
+        assert_eq!((responses[1]["id"].clone(), responses[1]["error"]["code"].clone()), (json!(2), json!(-32600)));
+        assert_eq!((responses[2]["id"].clone(), responses[2]["error"]["code"].clone()), (json!(3), json!(-32600)));
+        assert_eq!(upstream.methods(), ["eth_blockNumber"]);
+    }
+
+    #[tokio::test]
+    async fn lenient_jsonrpc_takes_a_missing_version_as_2_0() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x10"))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let batch = json!([
+            { "id": 1, "method": "eth_blockNumber", "params": [] },
+            { "jsonrpc": "2.0", "id": 2, "method": "eth_blockNumber", "params": [] },
+        ]);
+        let responses: Value = proxy.post(&batch).await.json().await.unwrap();
+        assert_eq!(responses[0]["result"], "0x10");
+        assert_eq!(responses[1]["result"], "0x10");
+
+        let lone: Value = proxy.post(&json!({ "id": 3, "method": "eth_blockNumber", "params": [] })).await.json().await.unwrap();
+        assert_eq!(lone["result"], "0x10");
+        assert!(upstream.hits().iter().all(|hit| hit.body.contains(r#""jsonrpc":"2.0""#)));
+        assert_eq!(upstream.methods().len(), 3);
+    }
+
+    fn broadcast(gas: u128, gas_price: u128) -> RawTransaction {
+        RawTransaction {
+            tx_type: 0,
+            from: "0xd8da6bf26964af9d7eed9e03e53415d37aa96045".to_string(),
+            nonce: 0,
+            to: None,
+            value: "0x0".to_string(),
+            gas: format!("0x{:x}", gas),
+            gas_price: format!("0x{:x}", gas_price),
+            input: "0x".to_string(),
+        }
+    }
+
+    #[test]
+    fn fee_limits_are_whole_sun() {
+        let scale = |scale: &str| parse_fee_limit_scale(scale).unwrap();
+        assert_eq!(fee_limit_sun(&broadcast(21_000, 420), scale("1")), 8_820_000);
+        assert_eq!(fee_limit_sun(&broadcast(21_000, 420), scale("0.5")), 4_410_000);
+        assert_eq!(fee_limit_sun(&broadcast(3_000_000, 210), scale("1.1")), 693_000_000);
+        assert_eq!(fee_limit_sun(&broadcast(100_000, 1), scale("0.000001")), 0);
+        // Fractions of a SUN are dropped
+        assert_eq!(fee_limit_sun(&broadcast(1, 3), scale("0.5")), 1);
+        // Past the 53 bits a float keeps exact
+        assert_eq!(fee_limit_sun(&broadcast((1 << 53) + 1, 1), scale("1")), (1 << 53) + 1);
+        assert_eq!(fee_limit_sun(&broadcast(u128::from(u64::MAX), u128::from(u64::MAX)), scale("2")), u128::MAX);
+    }
+
+    #[test]
+    fn fee_limit_scales_must_be_positive_decimals() {
+        assert_eq!(parse_fee_limit_scale("2.25"), Ok(FeeLimitScale { numerator: 225, denominator: 100 }));
+        assert_eq!(parse_fee_limit_scale(".5"), Ok(FeeLimitScale { numerator: 5, denominator: 10 }));
+        for invalid in ["0", "0.0", "-1", "1e3", "", ".", "1.2.3", "inf", "0.0000000000000000001"] {
+            assert!(parse_fee_limit_scale(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    /// A transaction the way java-tron's JSON-RPC reports a TRC-20 transfer: TRON hex
+    /// addresses, a fee limit instead of gas and gas price, and no nonce or signature.
+    fn tron_transaction() -> Value {
+        json!({
+            "hash": TX_HASH,
+            "blockHash": "0x0000000003a1f5e2c4b6d8f0a2c4e6f8a0b2c4d6e8f0a2c4e6f8a0b2c4d6e8f0",
+            "blockNumber": "0x3a1f5e2",
+            "transactionIndex": "0x03",
+            "from": "0x41d8da6bf26964af9d7eed9e03e53415d37aa96045",
+            "to": "0x41a614f803b6fd780986a42c78ec9c7f77e6ded13c",
+            "value": "0x0",
+            "input": "0xa9059cbb",
+            "feeLimit": 100_000_000,
+        })
+    }
+
+    fn enhanced_transaction(tx: Value) -> Value {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "result": tx }).to_string();
+        let enhanced: Value = serde_json::from_str(&enhance_transaction_response(&body, &PendingTxStore::default(), 1)).unwrap();
+        enhanced["result"].clone()
+    }
+
+    #[test]
+    fn tron_transactions_get_the_fields_cast_expects() {
+        let tx = enhanced_transaction(tron_transaction());
+
+        assert_eq!(tx["nonce"], "0x0");
+        // gas * gasPrice is the fee limit
+        assert_eq!((tx["gas"].clone(), tx["gasPrice"].clone()), (json!("0x5f5e100"), json!("0x1")));
+        assert_eq!((tx["v"].clone(), tx["r"].clone(), tx["s"].clone()), (json!("0x0"), json!("0x0"), json!("0x0")));
+        assert_eq!(tx["from"], "0xd8da6bf26964af9d7eed9e03e53415d37aa96045");
+        assert_eq!(tx["to"], "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c");
+        assert_eq!(tx["transactionIndex"], "0x3");
+        assert_eq!(tx["blockNumber"], "0x3a1f5e2");
+        assert_eq!(tx["input"], "0xa9059cbb");
+    }
+
+    #[test]
+    fn node_supplied_transaction_fields_are_kept() {
+        let mut tx = tron_transaction();
+        tx["nonce"] = json!("0x7");
+        tx["gas"] = json!("0x5208");
+        tx["gasPrice"] = json!("0x1a4");
+        let tx = enhanced_transaction(tx);
+
+        assert_eq!((tx["nonce"].clone(), tx["gas"].clone(), tx["gasPrice"].clone()), (json!("0x7"), json!("0x5208"), json!("0x1a4")));
+    }
+
+    #[test]
+    fn unknown_transactions_stay_null() {
+        let body = r#"{"jsonrpc":"2.0","id":1,"result":null}"#;
+        assert_eq!(enhance_transaction_response(body, &PendingTxStore::default(), 1), body);
+    }
+
+    /// The start of a signed EIP-1559 transaction: only the type byte matters for the store.
+    const TYPED_RAW_TX: &str = "0x02f86f82";
+
+    #[test]
+    fn legacy_transactions_get_type_0_and_y_parity_from_v() {
+        for (v, y_parity) in [("0x1b", "0x0"), ("0x1c", "0x1"), ("0x25", "0x0"), ("0x56cca7db", "0x0"), ("0x56cca7dc", "0x1")] {
+            let mut tx = tron_transaction();
+            tx["v"] = json!(v);
+            let tx = enhanced_transaction(tx);
+            assert_eq!(tx["type"], "0x0", "v {}", v);
+            assert_eq!(tx["yParity"], y_parity, "v {}", v);
+        }
+
+        // Without a real v there's no parity to report beyond the zeroed signature
+        let tx = enhanced_transaction(tron_transaction());
+        assert_eq!((tx["type"].clone(), tx["yParity"].clone()), (json!("0x0"), json!("0x0")));
+    }
+
+    #[test]
+    fn typed_broadcasts_report_their_recorded_type() {
+        let pending_txs = PendingTxStore::default();
+        pending_txs.record(TX_HASH, TYPED_RAW_TX);
+
+        let mut tx = tron_transaction();
+        tx["v"] = json!("0x1");
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "result": tx }).to_string();
+        let tx: Value = serde_json::from_str(&enhance_transaction_response(&body, &pending_txs, 1)).unwrap();
+        assert_eq!((tx["result"]["type"].clone(), tx["result"]["yParity"].clone()), (json!("0x2"), json!("0x1")));
+
+        let mut receipt = json!({ "transactionHash": TX_HASH, "status": "0x1", "logs": [] });
+        assert!(ensure_tx_type(receipt.as_object_mut().unwrap(), "transactionHash", &pending_txs));
+        assert_eq!(receipt["type"], "0x2");
+    }
+
+    #[test]
+    fn receipts_keep_a_type_the_node_reported() {
+        let mut receipt = json!({ "transactionHash": TX_HASH, "type": "0x1" });
+        assert!(!ensure_tx_type(receipt.as_object_mut().unwrap(), "transactionHash", &PendingTxStore::default()));
+        assert_eq!(receipt["type"], "0x1");
+
+        let mut receipt = json!({ "transactionHash": TX_HASH });
+        assert!(ensure_tx_type(receipt.as_object_mut().unwrap(), "transactionHash", &PendingTxStore::default()));
+        assert_eq!(receipt["type"], "0x0");
+    }
+
+    #[test]
+    fn receipt_logs_get_their_missing_fields() {
+        let block_hash = format!("0x{}", "b1".repeat(32));
+        let mut receipt = json!({
+            "transactionHash": TX_HASH,
+            "transactionIndex": "0x2",
+            "blockNumber": "0x10",
+            "blockHash": block_hash,
+            "logs": [
+                // Nothing but the event
+                { "address": "0x41a614f803b6fd780986a42c78ec9c7f77e6ded13c", "topics": [], "data": "0x" },
+                // Its own logIndex and removed, padded blockNumber
+                { "address": "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c", "topics": [], "data": "0x", "logIndex": "0x07", "removed": false, "blockNumber": "0x010" },
+                // Everything but logIndex
+                { "address": "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c", "topics": [], "data": "0x", "transactionIndex": "0x2", "blockNumber": "0x10", "blockHash": block_hash, "transactionHash": TX_HASH, "removed": false },
+            ],
+        });
+        assert!(fill_receipt_logs(receipt.as_object_mut().unwrap()));
+
+        let logs = receipt["logs"].as_array().unwrap();
+        for log in logs {
+            assert_eq!(log["address"], "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c");
+            assert_eq!(log["removed"], false);
+            assert_eq!(log["transactionIndex"], "0x2");
+            assert_eq!(log["blockNumber"], "0x10");
+            assert_eq!(log["blockHash"], block_hash);
+            assert_eq!(log["transactionHash"], TX_HASH);
+        }
+        let log_indexes: Vec<_> = logs.iter().map(|log| log["logIndex"].clone()).collect();
+        assert_eq!(log_indexes, ["0x0", "0x7", "0x2"]);
+    }
+
+    #[test]
+    fn complete_receipt_logs_are_left_alone() {
+        let mut receipt = json!({
+            "transactionIndex": "0x0",
+            "blockNumber": "0x10",
+            "logs": [{ "address": "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c", "logIndex": "0x0", "transactionIndex": "0x0", "blockNumber": "0x10", "removed": false }],
+        });
+        assert!(!fill_receipt_logs(receipt.as_object_mut().unwrap()));
+    }
+
+    /// A node answering receipts and block numbers with bodies compressed as `encoding`.
+    async fn compressing_node(encoding: &'static str) -> MockUpstream {
+        MockUpstream::start(move |hit| {
+            rpc_reply(hit, |method, _| match method {
+                "eth_getTransactionReceipt" => Ok(json!({
+                    "transactionHash": TX_HASH,
+                    "from": "0x41d8da6bf26964af9d7eed9e03e53415d37aa96045",
+                    "status": "0x1",
+                    "logs": [],
+                })),
+                _ => Ok(json!("0x10")),
+            })
+            .encoded(encoding)
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn compressed_upstream_responses_are_decoded_and_enhanced() {
+        for encoding in ["gzip", "deflate"] {
+            let upstream = compressing_node(encoding).await;
+            let proxy = TestProxy::start(&upstream, &[]).await;
+
+            let response = proxy.post(&json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_getTransactionReceipt", "params": [TX_HASH] })).await;
+            assert!(response.headers().get("content-encoding").is_none(), "{}", encoding);
+            let receipt: Value = response.json().await.unwrap();
+            assert_eq!(receipt["result"]["from"], "0xd8da6bf26964af9d7eed9e03e53415d37aa96045", "{}", encoding);
+            assert_eq!(receipt["result"]["type"], "0x0", "{}", encoding);
+
+            let response = proxy.post(&json!({ "jsonrpc": "2.0", "id": 2, "method": "eth_blockNumber", "params": [] })).await;
+            assert!(response.headers().get("content-encoding").is_none(), "{}", encoding);
+            assert_eq!(response.json::<Value>().await.unwrap()["result"], "0x10", "{}", encoding);
+        }
+    }
+
+    #[tokio::test]
+    async fn calls_without_a_jsonrpc_member_are_still_normalized() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x"))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let call = json!({
+            "id": "call-1",
+            "method": "eth_call",
+            "params": [{ "from": "0xd8da6bf26964af9d7eed9e03e53415d37aa96045", "to": "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c", "input": "a9059cbb", "gas": 21000, "chainId": "0x2b6653dc" }, "latest"],
+        });
+        let response: Value = proxy.post(&call).await.json().await.unwrap();
+        assert_eq!((response["id"].clone(), response["result"].clone()), (json!("call-1"), json!("0x")));
+
+        let forwarded = upstream.hits()[0].json();
+        assert_eq!(forwarded["jsonrpc"], "2.0");
+        assert_eq!(
+            forwarded["params"][0],
+            json!({ "from": "0x41d8da6bf26964af9d7eed9e03e53415d37aa96045", "to": "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c", "data": "0xa9059cbb", "gas": "0x5208" })
+        );
+    }
+
+    #[tokio::test]
+    async fn hop_by_hop_headers_are_not_propagated() {
+        let upstream = MockUpstream::start(|hit| {
+            let mut reply = rpc_reply(hit, |_, _| Ok(json!("0x10")));
+            reply.headers.push(("proxy-connection".to_string(), "keep-alive".to_string()));
+            reply.headers.push(("keep-alive".to_string(), "timeout=5".to_string()));
+            reply.headers.push(("x-node".to_string(), "fullnode-1".to_string()));
+            reply
+        })
+        .await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber", "params": [] }).to_string();
+        for path in ["/", "/wallet/getnowblock"] {
+            let response = proxy
+                .client
+                .post(format!("{}{}", proxy.url, path))
+                .header("content-type", "application/json")
+                .header("keep-alive", "timeout=30")
+                .header("proxy-connection", "keep-alive")
+                .body(request.clone())
+                .send()
+                .await
+                .unwrap();
+
+            assert!(response.headers().get("transfer-encoding").is_none(), "{}", path);
+            assert!(response.headers().get("keep-alive").is_none(), "{}", path);
+            assert!(response.headers().get("proxy-connection").is_none(), "{}", path);
+            assert_eq!(response.headers()["x-node"], "fullnode-1", "{}", path);
+            assert_eq!(response.json::<Value>().await.unwrap()["result"], "0x10", "{}", path);
+        }
+
+        for hit in upstream.hits() {
+            assert!(hit.headers.get("keep-alive").is_none(), "{}", hit.path);
+            assert!(hit.headers.get("proxy-connection").is_none(), "{}", hit.path);
+        }
+    }
+
+    async fn call_owners(extra_args: &[&str]) -> Vec<Value> {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x"))).await;
+        let proxy = TestProxy::start(&upstream, extra_args).await;
+        let to = "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c";
+        for call in [
+            json!({ "from": "0x95222290dd7278aa3ddd389cc1e1d165cc4bafe5", "to": to }),
+            json!({ "to": to }),
+            json!({ "from": ZERO_ADDRESS, "to": to }),
+        ] {
+            proxy.rpc("eth_call", json!([call, "latest"])).await;
+        }
+
+        upstream.hits().iter().map(|hit| hit.json()["params"][0]["from"].clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn call_owner_is_tron_form_and_defaults_when_absent_or_zero() {
+        let owners = call_owners(&["--default-owner", "TR7NHqjeKQxGTCi8q8ZY4pL8otSzgjLj6t"]).await;
+        assert_eq!(
+            owners,
+            vec![
+                json!("0x4195222290dd7278aa3ddd389cc1e1d165cc4bafe5"),
+                json!("0x41a614f803b6fd780986a42c78ec9c7f77e6ded13c"),
+                json!("0x41a614f803b6fd780986a42c78ec9c7f77e6ded13c"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn call_owner_is_left_alone_without_a_default() {
+        let owners = call_owners(&[]).await;
+        assert_eq!(owners, vec![json!("0x4195222290dd7278aa3ddd389cc1e1d165cc4bafe5"), Value::Null, json!(ZERO_ADDRESS)]);
+    }
+
+    fn overridden_call() -> Value {
+        json!([
+            { "to": "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c", "data": "0x70a08231" },
+            "latest",
+            { "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c": { "balance": "0x1" } }
+        ])
+    }
+
+    #[tokio::test]
+    async fn state_overrides_are_stripped_by_default() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x01"))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        assert_eq!(proxy.rpc("eth_call", overridden_call()).await["result"], "0x01");
+        assert_eq!(proxy.rpc("eth_call", json!([overridden_call()[0], "latest", {}])).await["result"], "0x01");
+
+        for hit in upstream.hits() {
+            let params = hit.json()["params"].clone();
+            assert_eq!(params.as_array().unwrap().len(), 2, "{}", params);
+            assert_eq!(params[1], "latest");
+        }
+    }
+
+    #[tokio::test]
+    async fn state_overrides_are_rejected_when_asked() {
+        for args in [&["--state-overrides", "reject"][..], &["--reject-state-overrides"][..]] {
+            let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x01"))).await;
+            let proxy = TestProxy::start(&upstream, args).await;
+
+            let response = proxy.rpc("eth_call", overridden_call()).await;
+            assert_eq!(response["error"]["code"], -32602, "{:?}", args);
+            assert_eq!(response["error"]["message"], "eth_call state overrides are not supported on TRON");
+            assert!(upstream.hits().is_empty(), "{:?}", args);
+
+            // An empty override set changes nothing, so it's still dropped
+            assert_eq!(proxy.rpc("eth_call", json!([overridden_call()[0], "latest", {}])).await["result"], "0x01");
+        }
+    }
+
+    #[tokio::test]
+    async fn transactions_by_block_and_index_are_enhanced() {
+        let upstream = MockUpstream::rpc(|method, params| match method {
+            "eth_getTransactionByBlockNumberAndIndex" | "eth_getTransactionByBlockHashAndIndex" if params[1] == "0x3" => {
+                Ok(tron_transaction())
+            }
+            _ => Ok(Value::Null),
+        })
+        .await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+        let block_hash = tron_transaction()["blockHash"].clone();
+
+        for (method, block) in [
+            ("eth_getTransactionByBlockNumberAndIndex", json!("0x3a1f5e2")),
+            ("eth_getTransactionByBlockHashAndIndex", block_hash),
+        ] {
+            let tx = proxy.rpc(method, json!([block, "0x3"])).await["result"].clone();
+            assert_eq!(tx["from"], "0xd8da6bf26964af9d7eed9e03e53415d37aa96045", "{}", method);
+            assert_eq!(tx["to"], "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c", "{}", method);
+            assert_eq!((tx["nonce"].clone(), tx["v"].clone()), (json!("0x0"), json!("0x0")), "{}", method);
+            assert_eq!(tx["transactionIndex"], "0x3", "{}", method);
+
+            // Past the end of the block
+            let response = proxy.rpc(method, json!([block, "0x9"])).await;
+            assert_eq!(response["result"], Value::Null, "{}", method);
+            assert!(response.get("error").is_none(), "{}", method);
+        }
+    }
+
+    #[tokio::test]
+    async fn addresses_are_not_taken_as_block_hashes() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(tron_transaction())).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        for address in ["TR7NHqjeKQxGTCi8q8ZY4pL8otSzgjLj6t", "0x41a614f803b6fd780986a42c78ec9c7f77e6ded13c"] {
+            let response = proxy.rpc("eth_getTransactionByBlockHashAndIndex", json!([address, "0x0"])).await;
+            assert_eq!(response["error"]["code"], -32602, "{}", address);
+            assert_eq!(response["error"]["message"], format!("expected a 32-byte block hash, got address {}", address));
+        }
+        assert!(upstream.hits().is_empty());
+    }
+
+    #[test]
+    fn full_block_transactions_are_enhanced() {
+        let mut block = json!({ "transactions": [tron_transaction()] });
+        let block = block.as_object_mut().unwrap();
+
+        assert!(normalize_block_transactions(block, true, &PendingTxStore::default(), 1));
+        assert_eq!(block["transactions"][0], enhanced_transaction(tron_transaction()));
+        assert_eq!(block["transactions"][0]["from"], "0xd8da6bf26964af9d7eed9e03e53415d37aa96045");
+    }
+
+    #[test]
+    fn hashes_only_blocks_hold_prefixed_32_byte_hashes() {
+        let unprefixed = TX_HASH[2..].to_ascii_uppercase();
+        let mut block = json!({ "transactions": [TX_HASH, unprefixed, tron_transaction(), "0x1234", 7] });
+        let block = block.as_object_mut().unwrap();
+
+        assert!(normalize_block_transactions(block, false, &PendingTxStore::default(), 1));
+        assert_eq!(block["transactions"], json!([TX_HASH, TX_HASH, TX_HASH]));
+
+        // Already normalized
+        assert!(!normalize_block_transactions(block, false, &PendingTxStore::default(), 1));
+    }
+
+    #[tokio::test]
+    async fn the_same_raw_transaction_is_broadcast_once() {
+        let upstream = MockUpstream::rpc(|method, params| match (method, params[0].as_str()) {
+            ("eth_sendRawTransaction", Some(TYPED_RAW_TX)) => Ok(json!(TX_HASH)),
+            ("eth_sendRawTransaction", _) => Err(json!({ "code": -32000, "message": "broadcast failed" })),
+            _ => Ok(Value::Null),
+        })
+        .await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+        let broadcasts = || upstream.methods().iter().filter(|method| *method == "eth_sendRawTransaction").count();
+
+        for _ in 0..2 {
+            assert_eq!(proxy.rpc("eth_sendRawTransaction", json!([TYPED_RAW_TX])).await["result"], TX_HASH);
+        }
+        assert_eq!(broadcasts(), 1);
+
+        // Only successful broadcasts are remembered
+        for _ in 0..2 {
+            assert_eq!(proxy.rpc("eth_sendRawTransaction", json!(["0x02f86f83"])).await["error"]["message"], "broadcast failed");
+        }
+        assert_eq!(broadcasts(), 3);
+    }
+
+    fn normalized_data(data: &str) -> Result<Value, OverrideError> {
+        let mut obj = json!({ "data": data }).as_object().unwrap().clone();
+        normalize_call_data(&mut obj)?;
+        Ok(obj["data"].clone())
+    }
+
+    #[test]
+    fn call_data_is_0x_prefixed_hex() {
+        assert_eq!(normalized_data("0x70a08231").unwrap(), "0x70a08231");
+        assert_eq!(normalized_data("0X70a08231").unwrap(), "0x70a08231");
+        assert_eq!(normalized_data("70a08231").unwrap(), "0x70a08231");
+        assert_eq!(normalized_data(" 70a08231\n").unwrap(), "0x70a08231");
+        assert_eq!(normalized_data("0x").unwrap(), "0x");
+    }
+
+    #[test]
+    fn malformed_call_data_is_rejected() {
+        for data in ["0x70a0823", "0xzz", "balanceOf(address)", "0x 70"] {
+            let (code, message) = normalized_data(data).unwrap_err();
+            assert_eq!(code, -32602, "{}", data);
+            assert_eq!(message, format!("invalid call data '{}': expected hex-encoded bytes", data));
+        }
+    }
+
+    #[tokio::test]
+    async fn unprefixed_call_data_reaches_the_node_prefixed() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x"))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+        let to = "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c";
+
+        for method in ["eth_call", "eth_estimateGas"] {
+            proxy.rpc(method, json!([{ "to": to, "data": "70a08231" }, "latest"])).await;
+            let response = proxy.rpc(method, json!([{ "to": to, "data": "0xnothex" }, "latest"])).await;
+            assert_eq!(response["error"]["code"], -32602, "{}", method);
+        }
+
+        let sent: Vec<Value> = upstream.hits().iter().map(|hit| hit.json()["params"][0]["data"].clone()).collect();
+        assert_eq!(sent, vec![json!("0x70a08231"), json!("0x70a08231")]);
+    }
+
+    #[tokio::test]
+    async fn request_ids_round_trip_exactly() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x10"))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+        let big_id = "123456789012345678901234567890";
+
+        // Forwarded, answered locally, and rejected locally
+        for (method, params) in [("eth_blockNumber", json!([])), ("web3_sha3", json!(["0x"])), ("web3_sha3", json!([7]))] {
+            for id in [json!(1), json!("1"), Value::Null, serde_json::from_str(big_id).unwrap()] {
+                let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+                let text = proxy.post(&request).await.text().await.unwrap();
+                let response: Value = serde_json::from_str(&text).unwrap();
+                assert_eq!(response["id"], id, "{} {}", method, text);
+                assert!(response.as_object().unwrap().contains_key("id"), "{} {}", method, text);
+                if id == json!("1") {
+                    assert!(text.contains(r#""id":"1""#), "{}", text);
+                }
+            }
+        }
+
+        let response = proxy.post_text("/", &format!(r#"{{"jsonrpc":"2.0","id":{},"method":"eth_blockNumber","params":[]}}"#, big_id)).await;
+        assert!(response.text().await.unwrap().contains(&format!(r#""id":{}"#, big_id)));
+    }
+
+    async fn token_node(balance: &'static str) -> MockUpstream {
+        MockUpstream::rpc(move |method, _| match method {
+            "eth_call" => Ok(json!(balance)),
+            "eth_getBalance" => Ok(json!("0x5")),
+            _ => Err(json!({ "code": -32601, "message": "method not found" })),
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn balance_token_answers_get_balance_with_balance_of() {
+        let one_token = "0x0000000000000000000000000000000000000000000000000de0b6b3a7640000";
+        let upstream = token_node(one_token).await;
+        let proxy = TestProxy::start(&upstream, &["--balance-token", "TR7NHqjeKQxGTCi8q8ZY4pL8otSzgjLj6t"]).await;
+
+        let account = crate::address::to_base58_address("0x95222290dd7278aa3ddd389cc1e1d165cc4bafe5").unwrap();
+        let response = proxy.rpc("eth_getBalance", json!([account, "latest"])).await;
+        assert_eq!(response["result"], "0xde0b6b3a7640000");
+
+        let hits = upstream.hits();
+        assert_eq!(hits.len(), 1);
+        let call = hits[0].json();
+        assert_eq!(call["method"], "eth_call");
+        assert_eq!(call["params"][1], "latest");
+        let data = call["params"][0]["data"].as_str().unwrap().to_string();
+        assert_eq!(data, format!("{}{:0>64}", BALANCE_OF_SELECTOR, "95222290dd7278aa3ddd389cc1e1d165cc4bafe5"));
+    }
+
+    #[tokio::test]
+    async fn balance_token_results_are_quantities() {
+        let upstream = token_node("0x0000000000000000000000000000000000000000000000000000000000000000").await;
+        let proxy = TestProxy::start(&upstream, &["--balance-token", "TR7NHqjeKQxGTCi8q8ZY4pL8otSzgjLj6t"]).await;
+        assert_eq!(proxy.rpc("eth_getBalance", json!(["0x95222290dd7278aa3ddd389cc1e1d165cc4bafe5"])).await["result"], "0x0");
+
+        let upstream = token_node("0x").await;
+        let proxy = TestProxy::start(&upstream, &["--balance-token", "TR7NHqjeKQxGTCi8q8ZY4pL8otSzgjLj6t"]).await;
+        let response = proxy.rpc("eth_getBalance", json!(["0x95222290dd7278aa3ddd389cc1e1d165cc4bafe5"])).await;
+        assert_eq!(response["error"]["code"], -32000);
+    }
+
+    #[tokio::test]
+    async fn get_balance_is_native_without_a_balance_token() {
+        let upstream = token_node("0x").await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+        assert_eq!(proxy.rpc("eth_getBalance", json!(["0x95222290dd7278aa3ddd389cc1e1d165cc4bafe5", "latest"])).await["result"], "0x5");
+        assert_eq!(upstream.methods(), vec!["eth_getBalance"]);
+    }
+
+    #[tokio::test]
+    async fn slow_upstreams_time_out_with_a_json_rpc_error() {
+        let upstream = MockUpstream::rpc_with_delay(Duration::from_millis(300), |method, _| match method {
+            "eth_sendRawTransaction" => Ok(json!(TX_HASH)),
+            _ => Ok(json!("0x10")),
+        })
+        .await;
+        let proxy = TestProxy::start(&upstream, &["--upstream-timeout-ms", "100", "--broadcast-timeout-ms", "2000"]).await;
+
+        let response = proxy.post(&json!({ "jsonrpc": "2.0", "id": "slow", "method": "eth_blockNumber", "params": [] })).await;
+        let response: Value = response.json().await.unwrap();
+        assert_eq!(response["id"], "slow");
+        assert_eq!(response["error"]["code"], -32603);
+        assert!(response["error"]["message"].as_str().unwrap().starts_with("upstream timed out after 100ms"), "{}", response);
+        assert_eq!(proxy.state.stats.snapshot()["upstream_timeouts"], json!({ "eth_blockNumber": 1 }));
+
+        // Broadcasts get the longer timeout
+        assert_eq!(proxy.rpc("eth_sendRawTransaction", json!([TYPED_RAW_TX])).await["result"], TX_HASH);
+    }
+
+    #[tokio::test]
+    async fn broadcasts_time_out_after_the_broadcast_timeout() {
+        let upstream = MockUpstream::rpc_with_delay(Duration::from_millis(300), |_, _| Ok(json!(TX_HASH))).await;
+        let proxy = TestProxy::start(&upstream, &["--upstream-timeout-ms", "2000", "--broadcast-timeout-ms", "100"]).await;
+
+        let response = proxy.rpc("eth_sendRawTransaction", json!([TYPED_RAW_TX])).await;
+        assert_eq!(response["error"]["code"], -32603);
+        assert!(response["error"]["message"].as_str().unwrap().starts_with("upstream timed out after 100ms"), "{}", response);
+        assert_eq!(proxy.state.stats.snapshot()["upstream_timeouts"], json!({ "eth_sendRawTransaction": 1 }));
+    }
+
+    #[tokio::test]
+    async fn block_transaction_counts_are_emulated_when_the_node_lacks_them() {
+        const BLOCK_HASH: &str = "0x0000000000000010a2c4e6f8a0b2c4d6e8f0a2c4e6f8a0b2c4d6e8f0a2c4e6f8";
+        let upstream = MockUpstream::rpc(|method, params| match (method, params[0].as_str()) {
+            ("eth_getBlockByNumber", Some("0x10")) | ("eth_getBlockByHash", Some(BLOCK_HASH)) => {
+                assert_eq!(params[1], false, "{}", method);
+                Ok(json!({ "number": "0x10", "hash": BLOCK_HASH, "transactions": [TX_HASH, TX_HASH, TX_HASH] }))
+            }
+            ("eth_getBlockByNumber", _) | ("eth_getBlockByHash", _) => Ok(Value::Null),
+            _ => Err(json!({ "code": -32601, "message": "method not found" })),
+        })
+        .await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        assert_eq!(proxy.rpc("eth_getBlockTransactionCountByNumber", json!(["0x10"])).await["result"], "0x3");
+        assert_eq!(proxy.rpc("eth_getBlockTransactionCountByHash", json!([BLOCK_HASH])).await["result"], "0x3");
+
+        let missing_hash = format!("0x{}", "ff".repeat(32));
+        for (method, block) in [("eth_getBlockTransactionCountByNumber", json!("0x20")), ("eth_getBlockTransactionCountByHash", json!(missing_hash))] {
+            let response = proxy.rpc(method, json!([block])).await;
+            assert_eq!(response["result"], Value::Null, "{}", method);
+            assert!(response.get("error").is_none(), "{} {}", method, response);
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_are_rejected_with_413() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x10"))).await;
+        let proxy = TestProxy::start(&upstream, &["--max-request-bytes", "1024"]).await;
+        let oversized = json!({ "jsonrpc": "2.0", "id": 7, "method": "eth_sendRawTransaction", "params": [format!("0x{}", "ab".repeat(1024))] });
+
+        for path in ["/", "/wallet/broadcasthex"] {
+            let response = proxy.post_text(path, &oversized.to_string()).await;
+            assert_eq!(response.status().as_u16(), 413, "{}", path);
+            let body: Value = response.json().await.unwrap();
+            assert_eq!(body["error"]["code"], -32600, "{}", path);
+            assert!(body["error"]["message"].as_str().unwrap().contains("request body exceeds 1024 bytes"), "{}", body);
+        }
+        assert!(upstream.hits().is_empty());
+
+        assert_eq!(proxy.rpc("eth_blockNumber", json!([])).await["result"], "0x10");
+    }
+
+    #[tokio::test]
+    async fn large_deployments_fit_under_the_default_limit() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!(TX_HASH))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        // Well past the 24KiB EIP-170 limit on deployed code
+        let raw_tx = format!("{}{}", TYPED_RAW_TX, "ab".repeat(128 * 1024));
+        assert_eq!(proxy.rpc("eth_sendRawTransaction", json!([raw_tx])).await["result"], TX_HASH);
+    }
+
+    #[tokio::test]
+    async fn gzip_accepting_clients_never_get_a_stale_content_encoding() {
+        let upstream = MockUpstream::start(|hit| {
+            rpc_reply(hit, |_, _| Ok(json!({ "number": "0x10", "stateRoot": "0x", "transactions": vec![&TX_HASH[2..]; 32] })))
+                .encoded("gzip")
+        })
+        .await;
+        let client = reqwest::Client::builder().no_gzip().build().unwrap();
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_getBlockByNumber", "params": ["0x10", false] }).to_string();
+
+        for compress in [false, true] {
+            let args: &[&str] = if compress { &["--compress-responses"] } else { &[] };
+            let proxy = TestProxy::start(&upstream, args).await;
+            let response = client
+                .post(&proxy.url)
+                .header("content-type", "application/json")
+                .header("accept-encoding", "gzip")
+                .body(request.clone())
+                .send()
+                .await
+                .unwrap();
+
+            let encoding = response.headers().get("content-encoding").map(|value| value.to_str().unwrap().to_string());
+            let bytes = response.bytes().await.unwrap();
+            let text = if compress {
+                assert_eq!(encoding.as_deref(), Some("gzip"));
+                let mut text = String::new();
+                std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(&bytes[..]), &mut text).unwrap();
+                text
+            } else {
+                assert_eq!(encoding, None);
+                String::from_utf8(bytes.to_vec()).unwrap()
+            };
+
+            let block: Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(block["result"]["transactions"], json!(vec![TX_HASH; 32]), "compress: {}", compress);
+            assert_eq!(block["result"]["stateRoot"].as_str().unwrap().len(), 66, "compress: {}", compress);
+        }
+    }
+
+    #[tokio::test]
+    async fn call_params_are_accepted_as_arrays_bare_objects_and_named_members() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x5208"))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+        let call = json!({ "to": "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c", "input": "0xa9059cbb", "chainId": "0x2b6653dc" });
+        let mut bare = call.clone();
+        bare["blockNumber"] = json!("0x10");
+
+        for method in ["eth_call", "eth_estimateGas"] {
+            for params in [
+                json!([call, "0x10"]),
+                bare.clone(),
+                json!({ "transaction": call, "block": "0x10" }),
+                json!({ "tx": call, "blockTag": "0x10" }),
+            ] {
+                assert_eq!(proxy.rpc(method, params.clone()).await["result"], "0x5208", "{} {}", method, params);
+            }
+        }
+
+        // TRON's eth_estimateGas takes the call alone
+        let translated = json!({ "to": "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c", "data": "0xa9059cbb" });
+        let sent: Vec<Value> = upstream.hits().iter().map(|hit| hit.json()["params"].clone()).collect();
+        assert_eq!(sent[..4], vec![json!([translated, "0x10"]); 4]);
+        assert_eq!(sent[4..], vec![json!([translated]); 4]);
+    }
+
+    #[tokio::test]
+    async fn bare_eth_call_objects_default_to_the_latest_block() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x"))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        proxy.rpc("eth_call", json!({ "to": "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c", "data": "0x" })).await;
+        proxy.rpc("eth_estimateGas", json!({ "to": "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c", "data": "0x" })).await;
+
+        let params: Vec<Value> = upstream.hits().iter().map(|hit| hit.json()["params"].clone()).collect();
+        assert_eq!(params[0][1], "latest");
+        assert_eq!(params[1], json!([{ "to": "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c", "data": "0x" }]));
+    }
+
+    #[tokio::test]
+    async fn enhanced_block_responses_are_labelled_json() {
+        let upstream = MockUpstream::start(|hit| {
+            if hit.path == "/wallet/getnodeinfo" {
+                let mut reply = MockReply::json(json!({}));
+                reply.headers = vec![("content-type".to_string(), "text/html".to_string())];
+                reply.body = b"<html>node info</html>".to_vec();
+                return reply;
+            }
+            let mut reply = rpc_reply(hit, |_, _| Ok(json!({ "number": "0x10", "stateRoot": "0x", "transactions": [] })));
+            reply.headers = vec![("content-type".to_string(), "text/plain".to_string())];
+            reply
+        })
+        .await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let response = proxy.post(&json!({ "jsonrpc": "2.0", "id": 1, "method": "eth_getBlockByNumber", "params": ["0x10", false] })).await;
+        assert_eq!(response.headers()["content-type"], JSON_CONTENT_TYPE);
+        assert_eq!(response.json::<Value>().await.unwrap()["result"]["stateRoot"].as_str().unwrap().len(), 66);
+
+        // Answered locally
+        let response = proxy.post(&json!({ "jsonrpc": "2.0", "id": 1, "method": "web3_sha3", "params": ["0x"] })).await;
+        assert_eq!(response.headers()["content-type"], JSON_CONTENT_TYPE);
+
+        // Non-JSON passthrough keeps the upstream's type
+        let response = proxy.client.get(format!("{}/wallet/getnodeinfo", proxy.url)).send().await.unwrap();
+        assert_eq!(response.headers()["content-type"], "text/html");
+        assert_eq!(response.text().await.unwrap(), "<html>node info</html>");
+    }
+
+    #[tokio::test]
+    async fn every_call_method_gets_every_call_object_fix() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(Value::Null)).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+        let to = "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c";
+
+        let quirks = [
+            (json!({ "to": to, "input": "0xa9059cbb" }), json!({ "to": to, "data": "0xa9059cbb" })),
+            (json!({ "to": to, "input": "0x01", "data": "0xa9059cbb" }), json!({ "to": to, "data": "0xa9059cbb" })),
+            (json!({ "to": to, "chainId": "0x2b6653dc" }), json!({ "to": to })),
+            (json!({ "to": to, "accessList": [{ "address": to, "storageKeys": [] }] }), json!({ "to": to })),
+            (json!({ "to": to, "type": "0x2" }), json!({ "to": to })),
+            (json!({ "to": to, "maxFeePerGas": "0x1a4", "maxPriorityFeePerGas": "0x1" }), json!({ "to": to, "gasPrice": "0x1a4" })),
+            (
+                json!({ "to": to, "gasPrice": "0xd2", "maxFeePerGas": "0x1a4", "maxPriorityFeePerGas": "0x1" }),
+                json!({ "to": to, "gasPrice": "0xd2" }),
+            ),
+        ];
+
+        for method in ["eth_call", "eth_estimateGas", "eth_sendTransaction"] {
+            for (call, expected) in &quirks {
+                let mut request = JsonRpcRequest {
+                    jsonrpc: "2.0".to_string(),
+                    method: method.to_string(),
+                    params: Some(json!([call, "latest"])),
+                    id: Some(json!(1)),
+                };
+                translate_call_request(&proxy.state, &mut request).unwrap();
+                assert_eq!(request.params.unwrap()[0], *expected, "{} {}", method, call);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn a_stalled_node_info_request_times_out() {
+        let upstream = MockUpstream::start(|_| {
+            MockReply::json(json!({ "block": "Num:100,ID:00", "peerInfoList": [{ "remainNum": 5 }] })).delay(Duration::from_secs(5))
+        })
+        .await;
+        let proxy = TestProxy::start(&upstream, &["--report-syncing", "--upstream-timeout-ms", "100"]).await;
+
+        let started = Instant::now();
+        assert_eq!(proxy.rpc("eth_syncing", json!([])).await["result"], false);
+        assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+        assert_eq!(upstream.hits()[0].path, "/wallet/getnodeinfo");
+    }
 }
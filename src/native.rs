@@ -0,0 +1,196 @@
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::overrides::{self, OverrideError};
+use crate::destination::join_destination;
+use crate::{upstream_timeout, AppState, WALLET_PATH_PREFIXES};
+
+/// Node HTTP API endpoints that only read chain state, the ones bridged under `--read-only`.
+/// Everything else, including endpoints that just build unsigned transactions, is rejected.
+const READ_ONLY_PATHS: &[&str] = &[
+    "estimateenergy",
+    "getaccount",
+    "getaccountbalance",
+    "getaccountnet",
+    "getaccountresource",
+    "getassetissuebyaccount",
+    "getassetissuebyid",
+    "getassetissuebyname",
+    "getassetissuelist",
+    "getavailableunfreezecount",
+    "getbandwidthprices",
+    "getblock",
+    "getblockbalance",
+    "getblockbyid",
+    "getblockbylatestnum",
+    "getblockbylimitnext",
+    "getblockbynum",
+    "getbrokerage",
+    "getburntrx",
+    "getcandelegatedmaxsize",
+    "getcanwithdrawunfreezeamount",
+    "getchainparameters",
+    "getcontract",
+    "getcontractinfo",
+    "getdelegatedresource",
+    "getdelegatedresourceaccountindex",
+    "getdelegatedresourceaccountindexv2",
+    "getdelegatedresourcev2",
+    "getenergyprices",
+    "getexchangebyid",
+    "getnextmaintenancetime",
+    "getnodeinfo",
+    "getnowblock",
+    "getpaginatedassetissuelist",
+    "getpaginatedexchangelist",
+    "getpaginatedproposallist",
+    "getproposalbyid",
+    "getreward",
+    "gettransactionbyid",
+    "gettransactioncountbyblocknum",
+    "gettransactioninfobyblocknum",
+    "gettransactioninfobyid",
+    "listexchanges",
+    "listnodes",
+    "listproposals",
+    "listwitnesses",
+    "triggerconstantcontract",
+    "validateaddress",
+];
+
+/// Returns the node HTTP API path a method is bridged to: an explicit `--native-method`
+/// entry, or for methods with the `--native-prefix`, `/wallet/` plus the rest of the name
+/// lowercased (`tron_getNowBlock` -> `/wallet/getnowblock`). Methods in the override registry
+/// keep their local answer unless mapped explicitly.
+pub fn native_path(state: &AppState, method: &str) -> Option<String> {
+    if let Some(path) = state.native_methods.get(method) {
+        return Some(path.clone());
+    }
+    if overrides::find(state, method).is_some() {
+        return None;
+    }
+
+    let prefix = state.native_prefix.as_deref()?;
+    let name = method.strip_prefix(prefix).filter(|name| !name.is_empty())?;
+    Some(format!("/wallet/{}", name.to_ascii_lowercase()))
+}
+
+/// Whether a node HTTP API path only reads chain state: one of `READ_ONLY_PATHS` under
+/// `/wallet/`, `/walletsolidity/` or `/walletpbft/`.
+pub fn is_read_only_path(path: &str) -> bool {
+    let path = path.split('?').next().unwrap_or_default().trim_matches('/').to_ascii_lowercase();
+    path.split_once('/')
+        .is_some_and(|(prefix, endpoint)| WALLET_PATH_PREFIXES.contains(&prefix) && READ_ONLY_PATHS.contains(&endpoint))
+}
+
+/// POSTs a request's params to the node's HTTP API. A single object parameter (or an object
+/// `params`) is sent as the body, no parameters as `{}`. TRON reports failures as an
+/// `Error` field in a 200 response, which becomes a JSON-RPC error.
+pub async fn call_native(state: &AppState, method: &str, path: &str, params: Option<&Value>) -> Result<Value, OverrideError> {
+    let body = match params {
+        None | Some(Value::Null) => json!({}),
+        Some(Value::Array(params)) if params.is_empty() => json!({}),
+        Some(Value::Array(params)) if params.len() == 1 && params[0].is_object() => params[0].clone(),
+        Some(Value::Object(params)) => Value::Object(params.clone()),
+        Some(_) => return Err((-32602, format!("{} expects a single object parameter", method))),
+    };
+
+    let url = join_destination(&state.wallet_dest, path).map_err(|e| (-32603, format!("invalid node API path {}: {}", path, e)))?;
+    debug!("Bridging {} to {}", method, url);
+
+    let response = state
+        .client
+        .post(url.clone())
+        .json(&body)
+        .timeout(upstream_timeout(state, method))
+        .send()
+        .await
+        .map_err(|e| (-32603, format!("{} failed: {}", url, e)))?;
+    let status = response.status();
+    let result = response
+        .json::<Value>()
+        .await
+        .map_err(|e| (-32603, format!("{} returned {} with an invalid body: {}", url, status, e)))?;
+
+    if let Some(error) = result.get("Error") {
+        let message = error.as_str().map_or_else(|| error.to_string(), str::to_string);
+        return Err((-32000, message));
+    }
+    if !status.is_success() {
+        return Err((-32603, format!("{} returned {}", url, status)));
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::is_read_only_path;
+    use crate::test_support::{MockReply, MockUpstream, TestProxy};
+
+    /// Sends a request from a client that gives up after 100ms and closes its connection.
+    async fn abandon(proxy: &TestProxy, method: &str) {
+        let client = reqwest::Client::builder().timeout(Duration::from_millis(100)).build().unwrap();
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": [{}] });
+        assert!(client.post(&proxy.url).json(&request).send().await.is_err());
+    }
+
+    #[test]
+    fn only_allowlisted_wallet_reads_are_read_only() {
+        assert!(is_read_only_path("/wallet/getnowblock"));
+        assert!(is_read_only_path("/walletsolidity/getaccount"));
+        assert!(is_read_only_path("/walletpbft/GetBlockByNum/"));
+
+        assert!(!is_read_only_path("/wallet/broadcasttransaction"));
+        assert!(!is_read_only_path("/wallet/easytransfer"));
+        assert!(!is_read_only_path("/wallet/easytransferbyprivate"));
+        assert!(!is_read_only_path("/wallet/createtransaction"));
+        assert!(!is_read_only_path("/wallet/freezebalancev2"));
+        assert!(!is_read_only_path("/other/getnowblock"));
+        assert!(!is_read_only_path("/wallet/getnowblock/extra"));
+    }
+
+    #[tokio::test]
+    async fn read_only_rejects_native_writes_before_the_upstream() {
+        let upstream = MockUpstream::start(|_| MockReply::json(json!({ "blockID": "00" }))).await;
+        let proxy = TestProxy::start(&upstream, &["--read-only", "--native-prefix", "tron_"]).await;
+
+        for method in ["tron_easyTransfer", "tron_easyTransferByPrivate", "tron_createTransaction", "tron_freezeBalanceV2", "tron_broadcastTransaction"] {
+            let response = proxy.rpc(method, json!([{}])).await;
+            assert_eq!(response["error"]["code"], -32000, "{}", method);
+        }
+        assert!(upstream.hits().is_empty());
+
+        let response = proxy.rpc("tron_getNowBlock", json!([])).await;
+        assert_eq!(response["result"], json!({ "blockID": "00" }));
+        assert_eq!(upstream.hits().len(), 1);
+        assert_eq!(upstream.hits()[0].path, "/wallet/getnowblock");
+    }
+
+    #[tokio::test]
+    async fn native_writes_outlive_a_disconnected_client() {
+        let upstream = MockUpstream::start(|_| MockReply::json(json!({ "result": true })).delay(Duration::from_millis(300))).await;
+        let proxy = TestProxy::start(&upstream, &["--native-prefix", "tron_"]).await;
+
+        abandon(&proxy, "tron_broadcastTransaction").await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(upstream.completed(), ["/wallet/broadcasttransaction"]);
+    }
+
+    #[tokio::test]
+    async fn native_reads_are_cancelled_with_a_disconnected_client() {
+        let upstream = MockUpstream::start(|_| MockReply::json(json!({ "blockID": "00" })).delay(Duration::from_millis(300))).await;
+        let proxy = TestProxy::start(&upstream, &["--native-prefix", "tron_"]).await;
+
+        abandon(&proxy, "tron_getNowBlock").await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(upstream.hits().len(), 1);
+        assert!(upstream.completed().is_empty());
+    }
+}
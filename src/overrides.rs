@@ -0,0 +1,394 @@
+use futures::future::{BoxFuture, FutureExt};
+use serde_json::{json, Map, Value};
+use sha3::{Digest, Keccak256};
+
+use crate::address::normalize_address;
+use crate::{confirmation, filters, native, parse_quantity, trace, upstream_call, AppState};
+
+/// A JSON-RPC error returned by an override: code and message.
+pub type OverrideError = (i64, String);
+
+/// Answers a request locally from its params, without forwarding it as-is.
+pub type OverrideHandler =
+    for<'a> fn(&'a AppState, Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>>;
+
+/// Methods the proxy answers itself. Each can be turned off with `--disable-override`,
+/// in which case the request is forwarded to the upstream as usual.
+pub const METHOD_OVERRIDES: &[(&str, OverrideHandler)] = &[
+    ("eth_coinbase", eth_coinbase),
+    ("eth_protocolVersion", eth_protocol_version),
+    ("eth_getProof", eth_get_proof),
+    ("eth_feeHistory", eth_fee_history),
+    ("debug_traceTransaction", debug_trace_transaction),
+    ("txpool_status", txpool_status),
+    ("txpool_content", txpool_content),
+    ("eth_getUncleCountByBlockHash", uncle_count),
+    ("eth_getUncleCountByBlockNumber", uncle_count),
+    ("eth_getUncleByBlockHashAndIndex", uncle_by_index),
+    ("eth_getUncleByBlockNumberAndIndex", uncle_by_index),
+    ("eth_newFilter", new_filter),
+    ("eth_newBlockFilter", new_block_filter),
+    ("eth_newPendingTransactionFilter", new_pending_transaction_filter),
+    ("eth_getFilterChanges", get_filter_changes),
+    ("eth_getFilterLogs", get_filter_logs),
+    ("eth_uninstallFilter", uninstall_filter),
+    ("tron_getAccountResource", tron_get_account_resource),
+    ("tron_waitForConfirmation", tron_wait_for_confirmation),
+];
+
+/// Methods TRON can't serve at all, with the reason given to clients. They are rejected with a
+/// `-32601` error before anything is forwarded, unless `--disable-override` lets them through.
+pub const UNSUPPORTED_METHODS: &[(&str, &str)] = &[
+    (
+        "eth_getProof",
+        "TRON does not produce Merkle-Patricia proofs. Use a mode that doesn't verify state \
+         proofs (e.g. forge's --no-storage-caching), or start the proxy with --fake-proofs",
+    ),
+    (
+        "debug_traceCall",
+        "TRON can't replay calls under a tracer. Use eth_call, or debug_traceTransaction with \
+         the callTracer once the transaction is mined",
+    ),
+    (
+        "debug_traceBlockByNumber",
+        "TRON can't replay blocks. Trace individual transactions with debug_traceTransaction \
+         and the callTracer",
+    ),
+    (
+        "debug_traceBlockByHash",
+        "TRON can't replay blocks. Trace individual transactions with debug_traceTransaction \
+         and the callTracer",
+    ),
+    ("eth_getWork", "TRON is delegated proof-of-stake and has no proof-of-work mining"),
+    ("eth_submitWork", "TRON is delegated proof-of-stake and has no proof-of-work mining"),
+    ("eth_submitHashrate", "TRON is delegated proof-of-stake and has no proof-of-work mining"),
+];
+
+/// Root of an empty Merkle-Patricia trie
+const EMPTY_TRIE_ROOT: &str = "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421";
+
+/// Returns the override for `method`, unless it isn't registered or has been disabled.
+pub fn find(state: &AppState, method: &str) -> Option<OverrideHandler> {
+    if state.disabled_overrides.contains(method) {
+        return None;
+    }
+
+    METHOD_OVERRIDES
+        .iter()
+        .find(|(name, _)| *name == method)
+        .map(|(_, handler)| *handler)
+}
+
+pub fn is_registered(method: &str) -> bool {
+    METHOD_OVERRIDES.iter().any(|(name, _)| *name == method)
+        || UNSUPPORTED_METHODS.iter().any(|(name, _)| *name == method)
+}
+
+/// The error for a method in `UNSUPPORTED_METHODS`, unless it has been disabled (or, for
+/// `eth_getProof`, is answered with `--fake-proofs`).
+pub fn unsupported(state: &AppState, method: &str) -> Option<OverrideError> {
+    if state.disabled_overrides.contains(method) || (method == "eth_getProof" && state.fake_proofs) {
+        return None;
+    }
+
+    UNSUPPORTED_METHODS
+        .iter()
+        .find(|(name, _)| *name == method)
+        .map(|(name, reason)| (-32601, format!("{} is not supported: {}", name, reason)))
+}
+
+/// The `--signer-key` address; without one there is no etherbase, so answer with geth's error.
+fn eth_coinbase<'a>(state: &'a AppState, _params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    async move {
+        match &state.signer {
+            Some(signer) => Ok(json!(signer.address)),
+            None => Err((-32000, "etherbase must be explicitly specified".to_string())),
+        }
+    }
+    .boxed()
+}
+
+fn eth_protocol_version<'a>(_state: &'a AppState, _params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    async { Ok(json!("0x41")) }.boxed()
+}
+
+/// Most blocks `eth_feeHistory` reports on, as in geth
+const FEE_HISTORY_MAX_BLOCKS: u64 = 1024;
+
+/// TRON has no base fee market or tips: every block's "base fee" is the energy fee from the
+/// chain parameter cache (or `eth_gasPrice` before it's filled), blocks report no usage and
+/// every reward percentile is 0.
+fn eth_fee_history<'a>(state: &'a AppState, params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    async move {
+        let param = |index: usize| params.and_then(|params| params.get(index));
+        let block_count = param(0)
+            .and_then(parse_quantity)
+            .ok_or_else(|| (-32602, "eth_feeHistory expects a block count".to_string()))?;
+        let newest_block = match param(1).and_then(Value::as_str) {
+            Some("earliest") => 0,
+            Some("latest" | "pending" | "safe" | "finalized") | None => filters::latest_block(state).await?,
+            Some(_) => param(1)
+                .and_then(parse_quantity)
+                .ok_or_else(|| (-32602, format!("invalid newest block {}", param(1).unwrap_or(&Value::Null))))?,
+        };
+        let percentiles = param(2).and_then(Value::as_array).map_or(0, Vec::len);
+        let blocks_through_newest = newest_block
+            .checked_add(1)
+            .ok_or_else(|| (-32602, format!("newest block 0x{:x} is out of range", newest_block)))?;
+
+        let block_count = block_count.min(FEE_HISTORY_MAX_BLOCKS).min(blocks_through_newest);
+        if block_count == 0 {
+            return Ok(json!({ "oldestBlock": "0x0", "baseFeePerGas": [], "gasUsedRatio": [] }));
+        }
+
+        let base_fee = match state.chain_params.get() {
+            Some(parameters) => json!(format!("0x{:x}", parameters.energy_fee)),
+            None => upstream_call(state, "eth_gasPrice", json!([]))
+                .await
+                .map_err(|e| (-32603, format!("failed to fetch gas price: {}", e)))?,
+        };
+
+        let block_count = block_count as usize;
+        let mut history = json!({
+            "oldestBlock": format!("0x{:x}", blocks_through_newest - block_count as u64),
+            // One more than the block count: the base fee of the block after the newest
+            "baseFeePerGas": vec![base_fee; block_count + 1],
+            "gasUsedRatio": vec![0.0; block_count],
+        });
+        if percentiles > 0 {
+            history["reward"] = json!(vec![vec!["0x0"; percentiles]; block_count]);
+        }
+        Ok(history)
+    }
+    .boxed()
+}
+
+/// TRON has no uncles. The block isn't looked up, so unknown blocks also report none.
+fn uncle_count<'a>(_state: &'a AppState, _params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    async { Ok(json!("0x0")) }.boxed()
+}
+
+fn uncle_by_index<'a>(_state: &'a AppState, _params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    async { Ok(Value::Null) }.boxed()
+}
+
+/// TRON can't produce Merkle-Patricia proofs. Without `--fake-proofs` this is rejected from
+/// `UNSUPPORTED_METHODS`; with it, the account fields are filled from the upstream and every
+/// proof is empty.
+fn eth_get_proof<'a>(state: &'a AppState, params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    async move {
+        if let Some(error) = unsupported(state, "eth_getProof") {
+            return Err(error);
+        }
+
+        let params = params.and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+        let [address, storage_keys, block, ..] = params else {
+            return Err((-32602, format!("eth_getProof expects 3 parameters, got {}", params.len())));
+        };
+        let storage_keys = storage_keys
+            .as_array()
+            .ok_or_else(|| (-32602, "eth_getProof expects an array of storage keys".to_string()))?;
+
+        let upstream_error = |e: anyhow::Error| (-32603, format!("failed to build proof: {}", e));
+
+        let balance = upstream_call(state, "eth_getBalance", json!([address, block]))
+            .await
+            .map_err(upstream_error)?;
+        let code = upstream_call(state, "eth_getCode", json!([address, block]))
+            .await
+            .map_err(upstream_error)?;
+        let code = code
+            .as_str()
+            .map(|code| code.strip_prefix("0x").unwrap_or(code))
+            .and_then(|code| hex::decode(code).ok())
+            .unwrap_or_default();
+
+        let mut storage_proof = Vec::with_capacity(storage_keys.len());
+        for key in storage_keys {
+            let value = upstream_call(state, "eth_getStorageAt", json!([address, key, block]))
+                .await
+                .map_err(upstream_error)?;
+            storage_proof.push(json!({ "key": key, "value": value, "proof": [] }));
+        }
+
+        Ok(json!({
+            "address": address,
+            "accountProof": [],
+            "balance": balance,
+            "codeHash": format!("0x{}", hex::encode(Keccak256::digest(&code))),
+            // TRON has no account nonces
+            "nonce": "0x0",
+            "storageHash": EMPTY_TRIE_ROOT,
+            "storageProof": storage_proof,
+        }))
+    }
+    .boxed()
+}
+
+fn debug_trace_transaction<'a>(state: &'a AppState, params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    trace::trace_transaction(state, params).boxed()
+}
+
+fn tron_wait_for_confirmation<'a>(state: &'a AppState, params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    confirmation::wait_for_confirmation(state, params).boxed()
+}
+
+fn new_filter<'a>(state: &'a AppState, params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    filters::new_filter(state, params).boxed()
+}
+
+fn new_block_filter<'a>(state: &'a AppState, params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    filters::new_block_filter(state, params).boxed()
+}
+
+fn new_pending_transaction_filter<'a>(state: &'a AppState, params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    filters::new_pending_transaction_filter(state, params).boxed()
+}
+
+fn get_filter_changes<'a>(state: &'a AppState, params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    filters::get_filter_changes(state, params).boxed()
+}
+
+fn get_filter_logs<'a>(state: &'a AppState, params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    filters::get_filter_logs(state, params).boxed()
+}
+
+fn uninstall_filter<'a>(state: &'a AppState, params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    filters::uninstall_filter(state, params).boxed()
+}
+
+/// Transactions broadcast through the proxy and not yet seen in a block, as geth-style
+/// pending transaction objects. Transactions broadcast elsewhere are invisible to the proxy.
+fn pending_transactions(state: &AppState) -> Vec<(String, u64, Value)> {
+    state
+        .pending_txs
+        .unconfirmed()
+        .into_iter()
+        .filter_map(|(hash, pending_tx)| {
+            let tx = pending_tx.transaction?;
+            let tx_object = json!({
+                "hash": hash,
+                "from": tx.from,
+                "nonce": format!("0x{:x}", tx.nonce),
+                "to": tx.to,
+                "value": tx.value,
+                "gas": tx.gas,
+                "gasPrice": tx.gas_price,
+                "input": tx.input,
+                "type": format!("0x{:x}", tx.tx_type),
+                "blockHash": null,
+                "blockNumber": null,
+                "transactionIndex": null,
+            });
+            Some((tx.from, tx.nonce, tx_object))
+        })
+        .collect()
+}
+
+fn txpool_status<'a>(state: &'a AppState, _params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    async move {
+        let pending = pending_transactions(state).len();
+        Ok(json!({ "pending": format!("0x{:x}", pending), "queued": "0x0" }))
+    }
+    .boxed()
+}
+
+fn txpool_content<'a>(state: &'a AppState, _params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    async move {
+        let mut pending = Map::new();
+        for (from, nonce, tx) in pending_transactions(state) {
+            let by_nonce = pending.entry(from).or_insert_with(|| json!({}));
+            by_nonce[nonce.to_string()] = tx;
+        }
+        Ok(json!({ "pending": pending, "queued": {} }))
+    }
+    .boxed()
+}
+
+/// An account's energy and bandwidth from `wallet/getaccountresource`. The node leaves out
+/// fields that are zero, so missing fields (or an account that doesn't exist) count as 0.
+fn tron_get_account_resource<'a>(state: &'a AppState, params: Option<&'a Value>) -> BoxFuture<'a, Result<Value, OverrideError>> {
+    async move {
+        let address = params
+            .and_then(Value::as_array)
+            .and_then(|params| params.first())
+            .and_then(Value::as_str)
+            .ok_or_else(|| (-32602, "tron_getAccountResource expects an address parameter".to_string()))?;
+        let address = normalize_address(&state.address_cache, address)
+            .ok_or_else(|| (-32602, format!("invalid address '{}'", address)))?;
+
+        let body = json!({ "address": format!("41{}", &address[2..]), "visible": false });
+        let resources = native::call_native(state, "tron_getAccountResource", "/wallet/getaccountresource", Some(&body)).await?;
+        let field = |name: &str| resources.get(name).and_then(Value::as_u64).unwrap_or(0);
+
+        let (energy_limit, energy_used) = (field("EnergyLimit"), field("EnergyUsed"));
+        let (free_net_limit, free_net_used) = (field("freeNetLimit"), field("freeNetUsed"));
+        let (net_limit, net_used) = (field("NetLimit"), field("NetUsed"));
+
+        Ok(json!({
+            "address": address,
+            "energy": {
+                "limit": energy_limit,
+                "used": energy_used,
+                "available": energy_limit.saturating_sub(energy_used),
+            },
+            "bandwidth": {
+                "freeLimit": free_net_limit,
+                "freeUsed": free_net_used,
+                "limit": net_limit,
+                "used": net_used,
+                "available": free_net_limit.saturating_sub(free_net_used) + net_limit.saturating_sub(net_used),
+            },
+        }))
+    }
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::test_support::{MockUpstream, TestProxy};
+
+    /// The EIP-155 example transaction: nonce 9, 1 ether to 0x3535..35, signed on chain 1
+    const RAW_TX: &str = "0xf86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+    const RAW_TX_SENDER: &str = "0x9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f";
+    const RAW_TX_HASH: &str = "0x33469b22e9f636356c4160a87eb19df52b7412e8eac32a4a55ffe88ea8350788";
+
+    #[tokio::test]
+    async fn broadcasts_show_up_in_the_txpool() {
+        let upstream = MockUpstream::rpc(|method, _| match method {
+            "eth_sendRawTransaction" => Ok(json!(RAW_TX_HASH)),
+            _ => Ok(Value::Null),
+        })
+        .await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        assert_eq!(proxy.rpc("txpool_status", json!([])).await["result"], json!({ "pending": "0x0", "queued": "0x0" }));
+        assert_eq!(proxy.rpc("eth_sendRawTransaction", json!([RAW_TX])).await["result"], RAW_TX_HASH);
+
+        assert_eq!(proxy.rpc("txpool_status", json!([])).await["result"], json!({ "pending": "0x1", "queued": "0x0" }));
+        let content = proxy.rpc("txpool_content", json!([])).await["result"].clone();
+        let tx = &content["pending"][RAW_TX_SENDER]["9"];
+        assert_eq!(tx["hash"], RAW_TX_HASH);
+        assert_eq!(tx["nonce"], "0x9");
+        assert_eq!(tx["to"], "0x3535353535353535353535353535353535353535");
+        assert_eq!(tx["value"], "0xde0b6b3a7640000");
+        assert_eq!(tx["blockNumber"], Value::Null);
+        assert_eq!(content["queued"], json!({}));
+    }
+
+    #[tokio::test]
+    async fn fee_history_rejects_a_newest_block_at_the_end_of_the_range() {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0xd2"))).await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let history = proxy.rpc("eth_feeHistory", json!(["0x4", "0xfffffffffffffffe", []])).await;
+        assert_eq!(history["result"]["oldestBlock"], "0xfffffffffffffffb");
+        assert_eq!(history["result"]["baseFeePerGas"], json!(vec!["0xd2"; 5]));
+
+        let response = proxy.rpc("eth_feeHistory", json!(["0x4", "0xffffffffffffffff", []])).await;
+        assert_eq!(response["error"]["code"], -32602);
+        assert_eq!(response["error"]["message"], "newest block 0xffffffffffffffff is out of range");
+    }
+}
@@ -0,0 +1,114 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use tracing::{debug, info};
+
+/// Path rewrite rules loaded from `--path-rewrites`, applied to the path of forwarded
+/// requests before it's joined with the destination, for gateways that mount the node
+/// under a prefix of their own.
+pub struct PathRewrites {
+    rules: Vec<PathRewrite>,
+}
+
+/// One entry of the `--path-rewrites` file: `{"match", "replace"}`. `match` is a path prefix,
+/// matched on whole segments, that `replace` is substituted for.
+#[derive(Deserialize)]
+struct PathRewrite {
+    #[serde(rename = "match")]
+    prefix: String,
+    replace: String,
+}
+
+impl PathRewrites {
+    /// Reads a JSON array of rules.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let rules: Vec<PathRewrite> =
+            serde_json::from_str(&contents).with_context(|| format!("invalid path rewrites in {}", path.display()))?;
+        for rule in &rules {
+            if !rule.prefix.starts_with('/') {
+                bail!("path rewrite match '{}' in {} must start with /", rule.prefix, path.display());
+            }
+            info!("Rewriting request paths under {} to {}", rule.prefix, rule.replace);
+        }
+        Ok(PathRewrites { rules })
+    }
+
+    /// Rewrites a request path with the first rule whose prefix it's under; paths no rule
+    /// matches are returned as they are.
+    pub fn rewrite<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        let rewritten = self.rules.iter().find_map(|rule| {
+            let rest = path.strip_prefix(rule.prefix.as_str())?;
+            // `/api` covers `/api` and `/api/...`, but not `/apix`
+            let on_boundary = rest.is_empty() || rest.starts_with('/') || rule.prefix.ends_with('/');
+            on_boundary.then(|| format!("{}{}", rule.replace, rest))
+        });
+
+        match rewritten {
+            Some(rewritten) => {
+                debug!("Rewrote request path {} to {}", path, rewritten);
+                Cow::Owned(rewritten)
+            }
+            None => Cow::Borrowed(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrites(rules: &[(&str, &str)]) -> PathRewrites {
+        PathRewrites {
+            rules: rules
+                .iter()
+                .map(|(prefix, replace)| PathRewrite { prefix: prefix.to_string(), replace: replace.to_string() })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn strips_a_prefix() {
+        let rewrites = rewrites(&[("/tron", "")]);
+        assert_eq!(rewrites.rewrite("/tron/wallet/getnowblock"), "/wallet/getnowblock");
+        assert_eq!(rewrites.rewrite("/tron"), "");
+    }
+
+    #[test]
+    fn adds_a_prefix() {
+        let rewrites = rewrites(&[("/wallet", "/v1/wallet"), ("/", "/v1/")]);
+        assert_eq!(rewrites.rewrite("/wallet/getnowblock"), "/v1/wallet/getnowblock");
+        assert_eq!(rewrites.rewrite("/walletsolidity/getnowblock"), "/v1/walletsolidity/getnowblock");
+    }
+
+    #[test]
+    fn paths_no_rule_matches_are_left_alone() {
+        let rewrites = rewrites(&[("/wallet", "/v1/wallet"), ("/api", "/gateway")]);
+        for path in ["/walletsolidity/getnowblock", "/apix/getnowblock", "/jsonrpc"] {
+            assert!(matches!(rewrites.rewrite(path), Cow::Borrowed(unchanged) if unchanged == path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rewrites = rewrites(&[("/wallet/getnowblock", "/cached/now"), ("/wallet", "/v1/wallet")]);
+        assert_eq!(rewrites.rewrite("/wallet/getnowblock"), "/cached/now");
+        assert_eq!(rewrites.rewrite("/wallet/getblock"), "/v1/wallet/getblock");
+    }
+
+    #[test]
+    fn load_rejects_relative_prefixes() {
+        let dir = std::env::temp_dir().join(format!("path-rewrites-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let valid = dir.join("valid.json");
+        let relative = dir.join("relative.json");
+        std::fs::write(&valid, r#"[{"match": "/tron", "replace": ""}]"#).unwrap();
+        std::fs::write(&relative, r#"[{"match": "tron", "replace": ""}]"#).unwrap();
+
+        assert_eq!(PathRewrites::load(&valid).unwrap().rewrite("/tron/jsonrpc"), "/jsonrpc");
+        assert!(PathRewrites::load(&relative).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use crate::raw_tx::{self, RawTransaction};
+
+/// How long a broadcast transaction is remembered after it was sent.
+const PENDING_TX_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How long a repeated broadcast of the same signed payload is answered from the store
+/// instead of being sent again.
+const DUPLICATE_BROADCAST_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// A transaction the proxy forwarded through `eth_sendRawTransaction`.
+#[derive(Debug, Clone)]
+pub struct PendingTx {
+    /// EIP-2718 transaction type (0 for legacy)
+    pub tx_type: u8,
+    /// The decoded transaction, when the raw payload could be decoded
+    pub transaction: Option<RawTransaction>,
+    /// Whether a receipt or mined transaction for it has passed through the proxy
+    pub confirmed: bool,
+    /// The signed payload, lowercased
+    pub raw_tx: String,
+    pub broadcast_at: Instant,
+}
+
+/// Transactions broadcast through this proxy, keyed by lowercase transaction hash.
+#[derive(Default)]
+pub struct PendingTxStore {
+    txs: Mutex<HashMap<String, PendingTx>>,
+}
+
+impl PendingTxStore {
+    /// Records a successful broadcast of `raw_tx`, which the upstream reported as `hash`.
+    pub fn record(&self, hash: &str, raw_tx: &str) {
+        let transaction = raw_tx::decode(raw_tx);
+        let Some(tx_type) = transaction.as_ref().map(|tx| tx.tx_type).or_else(|| raw_transaction_type(raw_tx)) else {
+            return;
+        };
+
+        let mut txs = self.txs.lock().unwrap();
+        txs.retain(|_, tx| tx.broadcast_at.elapsed() < PENDING_TX_TTL);
+
+        debug!("Recording broadcast transaction {} (type {})", hash, tx_type);
+        txs.insert(
+            hash.to_ascii_lowercase(),
+            PendingTx {
+                tx_type,
+                transaction,
+                confirmed: false,
+                raw_tx: raw_tx.to_ascii_lowercase(),
+                broadcast_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Returns the hash of a recent successful broadcast of exactly `raw_tx`, so a retried
+    /// submission can be answered without broadcasting it twice.
+    pub fn recent_broadcast(&self, raw_tx: &str) -> Option<String> {
+        let raw_tx = raw_tx.to_ascii_lowercase();
+        self.txs
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, tx)| tx.raw_tx == raw_tx && tx.broadcast_at.elapsed() < DUPLICATE_BROADCAST_TTL)
+            .map(|(hash, _)| hash.clone())
+    }
+
+    pub fn get(&self, hash: &str) -> Option<PendingTx> {
+        self.txs.lock().unwrap().get(&hash.to_ascii_lowercase()).cloned()
+    }
+
+    /// Marks a broadcast transaction as included in a block.
+    pub fn mark_confirmed(&self, hash: &str) {
+        if let Some(tx) = self.txs.lock().unwrap().get_mut(&hash.to_ascii_lowercase()) && !tx.confirmed {
+            debug!("Broadcast transaction {} is confirmed", hash);
+            tx.confirmed = true;
+        }
+    }
+
+    /// Returns the hashes of transactions broadcast at or after `since`, oldest first.
+    pub fn broadcast_since(&self, since: Instant) -> Vec<String> {
+        let txs = self.txs.lock().unwrap();
+        let mut recent: Vec<(&String, Instant)> = txs
+            .iter()
+            .filter(|(_, tx)| tx.broadcast_at >= since)
+            .map(|(hash, tx)| (hash, tx.broadcast_at))
+            .collect();
+        recent.sort_by_key(|(_, broadcast_at)| *broadcast_at);
+        recent.into_iter().map(|(hash, _)| hash.clone()).collect()
+    }
+
+    /// Returns the broadcast transactions not yet seen in a block, with their hashes.
+    pub fn unconfirmed(&self) -> Vec<(String, PendingTx)> {
+        self.txs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, tx)| !tx.confirmed && tx.broadcast_at.elapsed() < PENDING_TX_TTL)
+            .map(|(hash, tx)| (hash.clone(), tx.clone()))
+            .collect()
+    }
+}
+
+/// Returns the EIP-2718 type of a raw signed transaction, or `None` if it isn't valid hex.
+///
+/// Typed transactions start with a type byte in `0x00..=0x7f`; legacy transactions are
+/// RLP lists, whose first byte is always `0xc0` or above.
+pub fn raw_transaction_type(raw_tx: &str) -> Option<u8> {
+    let raw_hex = raw_tx.strip_prefix("0x").unwrap_or(raw_tx);
+    let first_byte = u8::from_str_radix(raw_hex.get(0..2)?, 16).ok()?;
+
+    if first_byte <= 0x7f {
+        Some(first_byte)
+    } else {
+        Some(0)
+    }
+}
@@ -0,0 +1,223 @@
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use sha3::{Digest, Keccak256};
+
+/// The fields of a signed Ethereum transaction the proxy cares about. Quantities are kept
+/// as `0x`-prefixed hex so values wider than 64 bits survive.
+#[derive(Debug, Clone)]
+pub struct RawTransaction {
+    /// EIP-2718 transaction type (0 for legacy)
+    pub tx_type: u8,
+    /// Sender recovered from the signature
+    pub from: String,
+    pub nonce: u64,
+    /// `None` for contract creation
+    pub to: Option<String>,
+    pub value: String,
+    pub gas: String,
+    /// Legacy/EIP-2930 gas price, or the EIP-1559 max fee per gas
+    pub gas_price: String,
+    pub input: String,
+}
+
+/// An RLP item: either a byte string or a list, with the raw encoding it was read from.
+struct RlpItem<'a> {
+    raw: &'a [u8],
+    payload: &'a [u8],
+    is_list: bool,
+}
+
+/// Decodes a raw signed transaction (legacy, EIP-2930 or EIP-1559) and recovers its sender.
+pub fn decode(raw_tx: &str) -> Option<RawTransaction> {
+    let bytes = hex::decode(raw_tx.strip_prefix("0x").unwrap_or(raw_tx)).ok()?;
+    let (&first_byte, rest) = bytes.split_first()?;
+
+    // Typed transactions are `type || rlp(fields)`; legacy transactions are a bare RLP list
+    let (tx_type, encoded) = if first_byte <= 0x7f { (first_byte, rest) } else { (0, bytes.as_slice()) };
+    let list = rlp_item(encoded).filter(|(item, rest)| item.is_list && rest.is_empty())?.0;
+    let fields = rlp_list(list.payload)?;
+
+    // Positions of nonce, gas price (or max fee), gas, to, value and input, and the number
+    // of fields covered by the signature
+    let (nonce, gas_price, gas, to, value, input, signed_fields) = match tx_type {
+        0 if fields.len() == 9 => (0, 1, 2, 3, 4, 5, 6),
+        1 if fields.len() == 11 => (1, 2, 3, 4, 5, 6, 8),
+        2 if fields.len() == 12 => (1, 3, 4, 5, 6, 7, 9),
+        _ => return None,
+    };
+
+    let [v, r, s] = &fields[signed_fields..] else {
+        return None;
+    };
+    let v = be_u64(v.payload)?;
+
+    let mut signing_payload: Vec<u8> = fields[..signed_fields].iter().flat_map(|field| field.raw.to_vec()).collect();
+    let recovery_id = if tx_type != 0 {
+        v
+    } else if v >= 35 {
+        // EIP-155: the chain id is signed over as `chainId, 0, 0`
+        let chain_id = (v - 35) / 2;
+        signing_payload.extend(rlp_encode_u64(chain_id));
+        signing_payload.extend([0x80, 0x80]);
+        (v - 35) % 2
+    } else {
+        v.checked_sub(27)?
+    };
+
+    let mut signing_message = Vec::new();
+    if tx_type != 0 {
+        signing_message.push(tx_type);
+    }
+    signing_message.extend(rlp_list_header(signing_payload.len()));
+    signing_message.extend(signing_payload);
+
+    let from = recover_sender(&Keccak256::digest(&signing_message), r.payload, s.payload, recovery_id)?;
+
+    Some(RawTransaction {
+        tx_type,
+        from,
+        nonce: be_u64(fields[nonce].payload)?,
+        to: (!fields[to].payload.is_empty()).then(|| format!("0x{}", hex::encode(fields[to].payload))),
+        value: quantity(fields[value].payload),
+        gas: quantity(fields[gas].payload),
+        gas_price: quantity(fields[gas_price].payload),
+        input: format!("0x{}", hex::encode(fields[input].payload)),
+    })
+}
+
+fn recover_sender(message_hash: &[u8], r: &[u8], s: &[u8], recovery_id: u64) -> Option<String> {
+    if r.len() > 32 || s.len() > 32 {
+        return None;
+    }
+
+    let mut signature = [0u8; 64];
+    signature[32 - r.len()..32].copy_from_slice(r);
+    signature[64 - s.len()..].copy_from_slice(s);
+
+    let signature = Signature::from_slice(&signature).ok()?;
+    let recovery_id = RecoveryId::from_byte(u8::try_from(recovery_id).ok()?)?;
+    let key = VerifyingKey::recover_from_prehash(message_hash, &signature, recovery_id).ok()?;
+
+    // The address is the last 20 bytes of the hash of the uncompressed key, minus its 0x04 tag
+    let public_key = key.to_encoded_point(false);
+    let key_hash = Keccak256::digest(&public_key.as_bytes()[1..]);
+    Some(format!("0x{}", hex::encode(&key_hash[12..])))
+}
+
+/// Reads one RLP item from the front of `data`, returning it and the remaining bytes.
+fn rlp_item(data: &[u8]) -> Option<(RlpItem<'_>, &[u8])> {
+    let prefix = *data.first()?;
+
+    let (is_list, header_len, payload_len) = match prefix {
+        0x00..=0x7f => (false, 0, 1),
+        0x80..=0xb7 => (false, 1, (prefix - 0x80) as usize),
+        0xb8..=0xbf => {
+            let len_of_len = (prefix - 0xb7) as usize;
+            (false, 1 + len_of_len, be_usize(data.get(1..1 + len_of_len)?)?)
+        }
+        0xc0..=0xf7 => (true, 1, (prefix - 0xc0) as usize),
+        0xf8..=0xff => {
+            let len_of_len = (prefix - 0xf7) as usize;
+            (true, 1 + len_of_len, be_usize(data.get(1..1 + len_of_len)?)?)
+        }
+    };
+
+    let item_len = header_len.checked_add(payload_len)?;
+    let raw = data.get(..item_len)?;
+    Some((
+        RlpItem {
+            raw,
+            payload: &raw[header_len..],
+            is_list,
+        },
+        &data[item_len..],
+    ))
+}
+
+fn rlp_list(mut payload: &[u8]) -> Option<Vec<RlpItem<'_>>> {
+    let mut items = Vec::new();
+    while !payload.is_empty() {
+        let (item, rest) = rlp_item(payload)?;
+        items.push(item);
+        payload = rest;
+    }
+    Some(items)
+}
+
+/// RLP-encodes a byte string.
+pub fn rlp_encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    match bytes {
+        [byte] if *byte <= 0x7f => vec![*byte],
+        _ if bytes.len() <= 55 => {
+            let mut encoded = vec![0x80 + bytes.len() as u8];
+            encoded.extend(bytes);
+            encoded
+        }
+        _ => {
+            let len_bytes = minimal_be_bytes(bytes.len() as u64);
+            let mut encoded = vec![0xb7 + len_bytes.len() as u8];
+            encoded.extend(len_bytes);
+            encoded.extend(bytes);
+            encoded
+        }
+    }
+}
+
+/// RLP-encodes a list of already encoded items.
+pub fn rlp_encode_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload: Vec<u8> = items.concat();
+    let mut encoded = rlp_list_header(payload.len());
+    encoded.extend(payload);
+    encoded
+}
+
+fn rlp_list_header(payload_len: usize) -> Vec<u8> {
+    if payload_len <= 55 {
+        return vec![0xc0 + payload_len as u8];
+    }
+
+    let len_bytes = minimal_be_bytes(payload_len as u64);
+    let mut header = vec![0xf7 + len_bytes.len() as u8];
+    header.extend(len_bytes);
+    header
+}
+
+pub fn rlp_encode_u64(value: u64) -> Vec<u8> {
+    match value {
+        0 => vec![0x80],
+        1..=0x7f => vec![value as u8],
+        _ => {
+            let bytes = minimal_be_bytes(value);
+            let mut encoded = vec![0x80 + bytes.len() as u8];
+            encoded.extend(bytes);
+            encoded
+        }
+    }
+}
+
+fn minimal_be_bytes(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let first_nonzero = bytes.iter().position(|byte| *byte != 0).unwrap_or(bytes.len());
+    bytes[first_nonzero..].to_vec()
+}
+
+fn be_u64(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() > 8 {
+        return None;
+    }
+    Some(bytes.iter().fold(0, |value, byte| (value << 8) | u64::from(*byte)))
+}
+
+fn be_usize(bytes: &[u8]) -> Option<usize> {
+    be_u64(bytes).and_then(|value| usize::try_from(value).ok())
+}
+
+/// Formats a big-endian integer as a minimal `0x`-prefixed hex quantity.
+fn quantity(bytes: &[u8]) -> String {
+    let digits = hex::encode(bytes);
+    let digits = digits.trim_start_matches('0');
+    if digits.is_empty() {
+        "0x0".to_string()
+    } else {
+        format!("0x{}", digits)
+    }
+}
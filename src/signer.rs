@@ -0,0 +1,186 @@
+use k256::ecdsa::SigningKey;
+use serde_json::{json, Map, Value};
+use sha3::{Digest, Keccak256};
+
+use crate::address::normalize_address;
+use crate::raw_tx::{rlp_encode_bytes, rlp_encode_list, rlp_encode_u64};
+use crate::{chain_id, upstream_call, AppState};
+
+/// A local key used to sign `eth_sendTransaction` requests (`--signer-key`).
+pub struct Signer {
+    key: SigningKey,
+    /// Lowercase `0x` EVM address of the key
+    pub address: String,
+}
+
+/// Fields of a transaction to sign, as big-endian integers without leading zeros.
+pub struct UnsignedTx {
+    pub nonce: Vec<u8>,
+    pub gas_price: Vec<u8>,
+    pub gas: Vec<u8>,
+    /// Empty for contract creation
+    pub to: Vec<u8>,
+    pub value: Vec<u8>,
+    pub input: Vec<u8>,
+    pub chain_id: u64,
+}
+
+// Keep the private key out of debug output
+impl std::fmt::Debug for Signer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Signer").field("address", &self.address).finish_non_exhaustive()
+    }
+}
+
+impl Signer {
+    pub fn from_hex(private_key: &str) -> Result<Self, String> {
+        let key_bytes = hex::decode(private_key.trim().strip_prefix("0x").unwrap_or(private_key.trim()))
+            .map_err(|e| format!("invalid private key hex: {}", e))?;
+        let key = SigningKey::from_slice(&key_bytes).map_err(|_| "invalid secp256k1 private key".to_string())?;
+
+        let public_key = key.verifying_key().to_encoded_point(false);
+        let key_hash = Keccak256::digest(&public_key.as_bytes()[1..]);
+        let address = format!("0x{}", hex::encode(&key_hash[12..]));
+
+        Ok(Signer { key, address })
+    }
+
+    /// Signs `tx` as an EIP-155 legacy transaction and returns the raw `0x` hex payload.
+    pub fn sign(&self, tx: &UnsignedTx) -> Result<String, String> {
+        let mut fields = vec![
+            rlp_encode_bytes(&tx.nonce),
+            rlp_encode_bytes(&tx.gas_price),
+            rlp_encode_bytes(&tx.gas),
+            rlp_encode_bytes(&tx.to),
+            rlp_encode_bytes(&tx.value),
+            rlp_encode_bytes(&tx.input),
+        ];
+
+        let mut signing_fields = fields.clone();
+        signing_fields.extend([rlp_encode_u64(tx.chain_id), rlp_encode_u64(0), rlp_encode_u64(0)]);
+        let message_hash = Keccak256::digest(rlp_encode_list(&signing_fields));
+
+        let (signature, recovery_id) = self
+            .key
+            .sign_prehash_recoverable(&message_hash)
+            .map_err(|e| format!("failed to sign transaction: {}", e))?;
+        let signature = signature.to_bytes();
+
+        let v = tx
+            .chain_id
+            .checked_mul(2)
+            .and_then(|v| v.checked_add(35 + u64::from(recovery_id.to_byte())))
+            .ok_or_else(|| format!("chain id {} is too large for EIP-155", tx.chain_id))?;
+        fields.push(rlp_encode_u64(v));
+        fields.push(rlp_encode_bytes(trim_leading_zeros(&signature[..32])));
+        fields.push(rlp_encode_bytes(trim_leading_zeros(&signature[32..])));
+
+        Ok(format!("0x{}", hex::encode(rlp_encode_list(&fields))))
+    }
+}
+
+fn trim_leading_zeros(bytes: &[u8]) -> &[u8] {
+    let first_nonzero = bytes.iter().position(|byte| *byte != 0).unwrap_or(bytes.len());
+    &bytes[first_nonzero..]
+}
+
+/// Reads a quantity field of a transaction object as a minimal big-endian integer.
+/// Absent fields yield `None`; malformed ones an error.
+pub fn quantity_field(tx: &Map<String, Value>, field: &str) -> Result<Option<Vec<u8>>, String> {
+    let Some(value) = tx.get(field).filter(|value| !value.is_null()) else {
+        return Ok(None);
+    };
+
+    let bytes = match value {
+        Value::Number(number) => number
+            .as_u64()
+            .map(|number| number.to_be_bytes().to_vec())
+            .ok_or_else(|| format!("invalid {} {}", field, number))?,
+        Value::String(text) => {
+            let digits = text
+                .strip_prefix("0x")
+                .ok_or_else(|| format!("{} must be 0x-prefixed hex, got '{}'", field, text))?;
+            let digits = if !digits.len().is_multiple_of(2) { format!("0{}", digits) } else { digits.to_string() };
+            hex::decode(&digits).map_err(|e| format!("invalid {} '{}': {}", field, text, e))?
+        }
+        _ => return Err(format!("invalid {} {}", field, value)),
+    };
+
+    Ok(Some(trim_leading_zeros(&bytes).to_vec()))
+}
+
+/// Reads a hex data field (`to`, `data`/`input`) of a transaction object.
+pub fn data_field(tx: &Map<String, Value>, field: &str) -> Result<Option<Vec<u8>>, String> {
+    match tx.get(field) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(text)) => {
+            let digits = text.strip_prefix("0x").unwrap_or(text);
+            hex::decode(digits)
+                .map(Some)
+                .map_err(|e| format!("invalid {} '{}': {}", field, text, e))
+        }
+        Some(value) => Err(format!("invalid {} {}", field, value)),
+    }
+}
+
+/// Turns an `eth_sendTransaction` request into a raw transaction signed by `signer`, filling
+/// gas from the upstream and gas price from the chain parameters when the request leaves
+/// them out. TRON has no account nonces, so the nonce defaults to 0.
+pub async fn sign_transaction_request(state: &AppState, signer: &Signer, params: Option<&Value>) -> Result<String, String> {
+    let Some(tx) = params.and_then(|params| params.get(0)).and_then(Value::as_object) else {
+        return Err("eth_sendTransaction expects a transaction object".to_string());
+    };
+
+    if let Some(from) = tx.get("from").and_then(Value::as_str)
+        && normalize_address(&state.address_cache, from).as_deref() != Some(signer.address.as_str())
+    {
+        return Err(format!("unknown account {}: the proxy can only sign for {}", from, signer.address));
+    }
+
+    let gas_price = match quantity_field(tx, "gasPrice")?.or(quantity_field(tx, "maxFeePerGas")?) {
+        Some(gas_price) => gas_price,
+        None => match state.chain_params.get() {
+            Some(parameters) => trim_leading_zeros(&parameters.energy_fee.to_be_bytes()).to_vec(),
+            None => upstream_quantity(state, "eth_gasPrice", json!([])).await?,
+        },
+    };
+
+    let gas = match quantity_field(tx, "gas")? {
+        Some(gas) => gas,
+        None => upstream_quantity(state, "eth_estimateGas", json!([tx])).await?,
+    };
+
+    let input = match data_field(tx, "data")? {
+        Some(data) => data,
+        None => data_field(tx, "input")?.unwrap_or_default(),
+    };
+
+    let to = match tx.get("to").and_then(Value::as_str) {
+        Some(to) => {
+            let to = normalize_address(&state.address_cache, to).ok_or_else(|| format!("invalid to address '{}'", to))?;
+            hex::decode(&to[2..]).map_err(|e| e.to_string())?
+        }
+        None => Vec::new(),
+    };
+
+    let chain_id = chain_id(state).await.map_err(|e| format!("failed to fetch chain id: {}", e))?;
+
+    signer.sign(&UnsignedTx {
+        nonce: quantity_field(tx, "nonce")?.unwrap_or_default(),
+        gas_price,
+        gas,
+        to,
+        value: quantity_field(tx, "value")?.unwrap_or_default(),
+        input,
+        chain_id,
+    })
+}
+
+async fn upstream_quantity(state: &AppState, method: &str, params: Value) -> Result<Vec<u8>, String> {
+    let result = upstream_call(state, method, params)
+        .await
+        .map_err(|e| format!("failed to fill transaction: {}", e))?;
+    let mut wrapper = Map::new();
+    wrapper.insert(method.to_string(), result);
+    quantity_field(&wrapper, method)?.ok_or_else(|| format!("{} returned no value", method))
+}
@@ -0,0 +1,375 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::overrides;
+
+/// Upper bounds, in seconds, of the latency histogram buckets
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Label under which methods outside `KNOWN_METHODS` are counted, so clients can't create
+/// series at will
+const OTHER_METHOD: &str = "other";
+
+/// Methods counted under their own name: the Ethereum JSON-RPC API, the cheatcodes Foundry and
+/// Hardhat send, and the HTTP methods of requests forwarded as-is. The proxy's own overrides
+/// are counted by name too.
+const KNOWN_METHODS: &[&str] = &[
+    "eth_accounts",
+    "eth_blobBaseFee",
+    "eth_blockNumber",
+    "eth_call",
+    "eth_chainId",
+    "eth_createAccessList",
+    "eth_estimateGas",
+    "eth_feeHistory",
+    "eth_gasPrice",
+    "eth_getBalance",
+    "eth_getBlockByHash",
+    "eth_getBlockByNumber",
+    "eth_getBlockReceipts",
+    "eth_getBlockTransactionCountByHash",
+    "eth_getBlockTransactionCountByNumber",
+    "eth_getCode",
+    "eth_getLogs",
+    "eth_getStorageAt",
+    "eth_getTransactionByBlockHashAndIndex",
+    "eth_getTransactionByBlockNumberAndIndex",
+    "eth_getTransactionByHash",
+    "eth_getTransactionCount",
+    "eth_getTransactionReceipt",
+    "eth_hashrate",
+    "eth_maxPriorityFeePerGas",
+    "eth_mining",
+    "eth_sendRawTransaction",
+    "eth_sendTransaction",
+    "eth_sign",
+    "eth_signTransaction",
+    "eth_signTypedData_v4",
+    "eth_subscribe",
+    "eth_syncing",
+    "eth_unsubscribe",
+    "net_listening",
+    "net_peerCount",
+    "net_version",
+    "web3_clientVersion",
+    "web3_sha3",
+    "anvil_impersonateAccount",
+    "anvil_mine",
+    "anvil_reset",
+    "anvil_setBalance",
+    "anvil_setCode",
+    "anvil_setNonce",
+    "anvil_setStorageAt",
+    "anvil_stopImpersonatingAccount",
+    "evm_increaseTime",
+    "evm_mine",
+    "evm_revert",
+    "evm_setNextBlockTimestamp",
+    "evm_snapshot",
+    "hardhat_impersonateAccount",
+    "hardhat_mine",
+    "hardhat_reset",
+    "hardhat_setBalance",
+    "hardhat_setCode",
+    "hardhat_setNonce",
+    "hardhat_setStorageAt",
+    "hardhat_stopImpersonatingAccount",
+    "GET",
+    "POST",
+];
+
+/// The `method` label for a method: its name if known, `other` otherwise.
+fn method_label(method: &str) -> &str {
+    if KNOWN_METHODS.contains(&method) || overrides::is_registered(method) {
+        method
+    } else {
+        OTHER_METHOD
+    }
+}
+
+/// Escapes a Prometheus label value: backslash, double quote and newline.
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// A Prometheus-style cumulative histogram over `LATENCY_BUCKETS`.
+#[derive(Default, Clone)]
+struct Histogram {
+    bucket_counts: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bound, bucket_count) in LATENCY_BUCKETS.iter().zip(self.bucket_counts.iter_mut()) {
+            if seconds <= *bound {
+                *bucket_count += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Request outcomes for one upstream URL, behind the `proxy_upstream_*` gauges.
+#[derive(Default, Clone)]
+struct UpstreamHealth {
+    /// `default` or the --upstream name
+    name: String,
+    requests: u64,
+    failures: u64,
+    consecutive_failures: u64,
+    last_success: Option<SystemTime>,
+}
+
+/// Counters exposed on the `/stats` and `/metrics` endpoints.
+#[derive(Default)]
+pub struct Stats {
+    /// Requests answered by the proxy without contacting the upstream, per method
+    local_answers: Mutex<HashMap<String, u64>>,
+    /// Rejected anvil_/hardhat_/evm_ cheatcode calls, per method
+    cheatcode_calls: Mutex<HashMap<String, u64>>,
+    /// Requests that shared an identical in-flight upstream request, per method
+    coalesced_requests: Mutex<HashMap<String, u64>>,
+    /// Forwarded requests the upstream didn't answer in time, per method
+    upstream_timeouts: Mutex<HashMap<String, u64>>,
+    /// Time spent waiting on the upstream, per method
+    upstream_latency: Mutex<HashMap<String, Histogram>>,
+    /// Time spent rewriting upstream responses, per method
+    enhancement_latency: Mutex<HashMap<String, Histogram>>,
+    /// JSON-RPC request outcomes, per upstream URL
+    upstream_health: Mutex<HashMap<String, UpstreamHealth>>,
+}
+
+impl Stats {
+    pub fn record_local_answer(&self, method: &str) {
+        *self.local_answers.lock().unwrap().entry(method_label(method).to_string()).or_default() += 1;
+    }
+
+    pub fn record_cheatcode_call(&self, method: &str) {
+        *self.cheatcode_calls.lock().unwrap().entry(method_label(method).to_string()).or_default() += 1;
+    }
+
+    pub fn record_coalesced_request(&self, method: &str) {
+        *self.coalesced_requests.lock().unwrap().entry(method_label(method).to_string()).or_default() += 1;
+    }
+
+    pub fn record_upstream_timeout(&self, method: &str) {
+        *self.upstream_timeouts.lock().unwrap().entry(method_label(method).to_string()).or_default() += 1;
+    }
+
+    pub fn record_upstream_latency(&self, method: &str, elapsed: Duration) {
+        observe(&self.upstream_latency, method, elapsed);
+    }
+
+    pub fn record_enhancement_latency(&self, method: &str, elapsed: Duration) {
+        observe(&self.enhancement_latency, method, elapsed);
+    }
+
+    /// Lists an upstream before it has been used, so its gauges exist from startup.
+    pub fn register_upstream(&self, name: &str, url: &str) {
+        self.upstream_health.lock().unwrap().entry(url.to_string()).or_insert_with(|| UpstreamHealth {
+            name: name.to_string(),
+            ..UpstreamHealth::default()
+        });
+    }
+
+    pub fn record_upstream_result(&self, name: &str, url: &str, success: bool) {
+        let mut upstream_health = self.upstream_health.lock().unwrap();
+        let health = upstream_health.entry(url.to_string()).or_default();
+        health.name = name.to_string();
+        health.requests += 1;
+        if success {
+            health.consecutive_failures = 0;
+            health.last_success = Some(SystemTime::now());
+        } else {
+            health.failures += 1;
+            health.consecutive_failures += 1;
+        }
+    }
+
+    pub fn snapshot(&self) -> Value {
+        json!({
+            "local_answers": *self.local_answers.lock().unwrap(),
+            "cheatcode_calls": *self.cheatcode_calls.lock().unwrap(),
+            "coalesced_requests": *self.coalesced_requests.lock().unwrap(),
+            "upstream_timeouts": *self.upstream_timeouts.lock().unwrap(),
+            "upstream_requests": self.upstream_requests(),
+        })
+    }
+
+    /// JSON-RPC requests sent to each upstream, by name; replicas count towards `default`.
+    fn upstream_requests(&self) -> HashMap<String, u64> {
+        let mut requests = HashMap::new();
+        for health in self.upstream_health.lock().unwrap().values() {
+            *requests.entry(health.name.clone()).or_default() += health.requests;
+        }
+        requests
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        let local_answers: BTreeMap<_, _> = self.local_answers.lock().unwrap().clone().into_iter().collect();
+        out.push_str("# HELP proxy_local_answers_total Requests answered without contacting the upstream.\n");
+        out.push_str("# TYPE proxy_local_answers_total counter\n");
+        for (method, count) in local_answers {
+            let _ = writeln!(out, "proxy_local_answers_total{{method=\"{}\"}} {}", escape_label(&method), count);
+        }
+
+        let cheatcode_calls: BTreeMap<_, _> = self.cheatcode_calls.lock().unwrap().clone().into_iter().collect();
+        out.push_str("# HELP proxy_cheatcode_calls_total Rejected anvil_/hardhat_/evm_ method calls.\n");
+        out.push_str("# TYPE proxy_cheatcode_calls_total counter\n");
+        for (method, count) in cheatcode_calls {
+            let _ = writeln!(out, "proxy_cheatcode_calls_total{{method=\"{}\"}} {}", escape_label(&method), count);
+        }
+
+        let coalesced_requests: BTreeMap<_, _> = self.coalesced_requests.lock().unwrap().clone().into_iter().collect();
+        out.push_str("# HELP proxy_coalesced_requests_total Requests answered by an identical in-flight upstream request.\n");
+        out.push_str("# TYPE proxy_coalesced_requests_total counter\n");
+        for (method, count) in coalesced_requests {
+            let _ = writeln!(out, "proxy_coalesced_requests_total{{method=\"{}\"}} {}", escape_label(&method), count);
+        }
+
+        let upstream_timeouts: BTreeMap<_, _> = self.upstream_timeouts.lock().unwrap().clone().into_iter().collect();
+        out.push_str("# HELP proxy_upstream_timeouts_total Forwarded requests the upstream didn't answer in time.\n");
+        out.push_str("# TYPE proxy_upstream_timeouts_total counter\n");
+        for (method, count) in upstream_timeouts {
+            let _ = writeln!(out, "proxy_upstream_timeouts_total{{method=\"{}\"}} {}", escape_label(&method), count);
+        }
+
+        self.render_upstream_health(&mut out);
+
+        render_histograms(
+            &mut out,
+            "proxy_upstream_duration_seconds",
+            "Time spent waiting on the upstream per JSON-RPC method.",
+            &self.upstream_latency,
+        );
+        render_histograms(
+            &mut out,
+            "proxy_enhancement_duration_seconds",
+            "Time spent rewriting upstream responses per JSON-RPC method.",
+            &self.enhancement_latency,
+        );
+
+        out
+    }
+
+    /// Per-upstream gauges and counters, labelled by upstream name and URL. An upstream is up
+    /// until a request to it fails, and again after the next success.
+    fn render_upstream_health(&self, out: &mut String) {
+        let upstream_health: BTreeMap<_, _> = self.upstream_health.lock().unwrap().clone().into_iter().collect();
+        let labels = |url: &str, health: &UpstreamHealth| format!("upstream=\"{}\",url=\"{}\"", escape_label(&health.name), escape_label(url));
+
+        out.push_str("# HELP proxy_upstream_up Whether the last JSON-RPC request to the upstream succeeded (1 before any request).\n");
+        out.push_str("# TYPE proxy_upstream_up gauge\n");
+        for (url, health) in &upstream_health {
+            let _ = writeln!(out, "proxy_upstream_up{{{}}} {}", labels(url, health), u8::from(health.consecutive_failures == 0));
+        }
+
+        out.push_str("# HELP proxy_upstream_consecutive_failures Failed JSON-RPC requests to the upstream since its last success.\n");
+        out.push_str("# TYPE proxy_upstream_consecutive_failures gauge\n");
+        for (url, health) in &upstream_health {
+            let _ = writeln!(out, "proxy_upstream_consecutive_failures{{{}}} {}", labels(url, health), health.consecutive_failures);
+        }
+
+        out.push_str("# HELP proxy_upstream_last_success_timestamp_seconds Unix time of the upstream's last successful JSON-RPC request.\n");
+        out.push_str("# TYPE proxy_upstream_last_success_timestamp_seconds gauge\n");
+        for (url, health) in &upstream_health {
+            if let Some(last_success) = health.last_success {
+                let seconds = last_success.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64();
+                let _ = writeln!(out, "proxy_upstream_last_success_timestamp_seconds{{{}}} {:.3}", labels(url, health), seconds);
+            }
+        }
+
+        out.push_str("# HELP proxy_upstream_requests_total JSON-RPC requests sent to the upstream.\n");
+        out.push_str("# TYPE proxy_upstream_requests_total counter\n");
+        for (url, health) in &upstream_health {
+            let _ = writeln!(out, "proxy_upstream_requests_total{{{}}} {}", labels(url, health), health.requests);
+        }
+
+        out.push_str("# HELP proxy_upstream_failures_total JSON-RPC requests to the upstream that failed (transport error, timeout, 5xx, 429, 401 or 403).\n");
+        out.push_str("# TYPE proxy_upstream_failures_total counter\n");
+        for (url, health) in &upstream_health {
+            let _ = writeln!(out, "proxy_upstream_failures_total{{{}}} {}", labels(url, health), health.failures);
+        }
+    }
+}
+
+fn observe(histograms: &Mutex<HashMap<String, Histogram>>, method: &str, elapsed: Duration) {
+    histograms
+        .lock()
+        .unwrap()
+        .entry(method_label(method).to_string())
+        .or_default()
+        .observe(elapsed.as_secs_f64());
+}
+
+fn render_histograms(out: &mut String, name: &str, help: &str, histograms: &Mutex<HashMap<String, Histogram>>) {
+    let histograms: BTreeMap<_, _> = histograms.lock().unwrap().clone().into_iter().collect();
+
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    for (method, histogram) in histograms {
+        let method = escape_label(&method);
+        for (bound, bucket_count) in LATENCY_BUCKETS.iter().zip(histogram.bucket_counts) {
+            let _ = writeln!(out, "{}_bucket{{method=\"{}\",le=\"{}\"}} {}", name, method, bound, bucket_count);
+        }
+        let _ = writeln!(out, "{}_bucket{{method=\"{}\",le=\"+Inf\"}} {}", name, method, histogram.count);
+        let _ = writeln!(out, "{}_sum{{method=\"{}\"}} {}", name, method, histogram.sum);
+        let _ = writeln!(out, "{}_count{{method=\"{}\"}} {}", name, method, histogram.count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_methods_are_counted_as_other() {
+        let stats = Stats::default();
+        stats.record_local_answer("eth_chainId");
+        stats.record_local_answer("made_up_1");
+        stats.record_local_answer("made_up_2");
+        stats.record_cheatcode_call("anvil_whatever");
+        stats.record_upstream_latency("made_up_3", Duration::from_millis(1));
+
+        let metrics = stats.render_prometheus();
+        assert!(metrics.contains("proxy_local_answers_total{method=\"eth_chainId\"} 1\n"));
+        assert!(metrics.contains("proxy_local_answers_total{method=\"other\"} 2\n"));
+        assert!(metrics.contains("proxy_cheatcode_calls_total{method=\"other\"} 1\n"));
+        assert!(metrics.contains("proxy_upstream_duration_seconds_count{method=\"other\"} 1\n"));
+        assert!(!metrics.contains("made_up"));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape_label(r#"a\b"c"#), r#"a\\b\"c"#);
+        assert_eq!(escape_label("a\nb"), "a\\nb");
+
+        let stats = Stats::default();
+        stats.record_coalesced_request("eth_call\"} 1\nfake_metric{x=\"");
+        stats.record_upstream_timeout("eth_call\\");
+        let metrics = stats.render_prometheus();
+        assert!(!metrics.contains("fake_metric"));
+        assert!(metrics.contains("proxy_coalesced_requests_total{method=\"other\"} 1\n"));
+        assert!(metrics.contains("proxy_upstream_timeouts_total{method=\"other\"} 1\n"));
+    }
+
+    #[test]
+    fn upstream_health_labels_are_escaped() {
+        let stats = Stats::default();
+        stats.register_upstream("a\"b", "http://node/\"} 1\nfake_metric{x=\"");
+
+        let metrics = stats.render_prometheus();
+        assert!(!metrics.contains("\nfake_metric"));
+        assert!(metrics.contains(r#"proxy_upstream_up{upstream="a\"b",url="http://node/\"} 1\nfake_metric{x=\""} 1"#));
+    }
+}
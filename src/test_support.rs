@@ -0,0 +1,247 @@
+//! A mock upstream node and an in-process proxy in front of it, for tests that exercise
+//! requests end to end.
+
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{HeaderMap, Method, StatusCode, Uri},
+    response::Response,
+    Router,
+};
+use clap::Parser;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use serde_json::{json, Value};
+
+use crate::{build_router, build_state, AppState, Args};
+
+/// A request the mock upstream received.
+#[derive(Clone, Debug)]
+pub struct Hit {
+    pub method: Method,
+    pub path: String,
+    /// Query string, without the `?`
+    pub query: Option<String>,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl Hit {
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.body).unwrap_or(Value::Null)
+    }
+}
+
+/// What the mock upstream answers a request with.
+pub struct MockReply {
+    pub status: StatusCode,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub delay: Duration,
+}
+
+impl MockReply {
+    pub fn json(body: Value) -> Self {
+        MockReply {
+            status: StatusCode::OK,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: body.to_string().into_bytes(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Compresses the body with `gzip` or `deflate` and labels it with that Content-Encoding.
+    pub fn encoded(mut self, encoding: &str) -> Self {
+        self.body = match encoding {
+            "gzip" => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&self.body).unwrap();
+                encoder.finish().unwrap()
+            }
+            "deflate" => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&self.body).unwrap();
+                encoder.finish().unwrap()
+            }
+            _ => panic!("unsupported encoding {}", encoding),
+        };
+        self.headers.push(("content-encoding".to_string(), encoding.to_string()));
+        self
+    }
+}
+
+type Responder = Arc<dyn Fn(&Hit) -> MockReply + Send + Sync>;
+
+#[derive(Clone)]
+struct MockState {
+    responder: Responder,
+    hits: Arc<Mutex<Vec<Hit>>>,
+    completed: Arc<Mutex<Vec<String>>>,
+}
+
+/// An HTTP server on a local port answering every path with a test-supplied responder.
+pub struct MockUpstream {
+    /// Root URL of the server, without a trailing slash
+    pub url: String,
+    hits: Arc<Mutex<Vec<Hit>>>,
+    completed: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockUpstream {
+    pub async fn start(responder: impl Fn(&Hit) -> MockReply + Send + Sync + 'static) -> Self {
+        let state = MockState { responder: Arc::new(responder), hits: Arc::default(), completed: Arc::default() };
+        let (hits, completed) = (state.hits.clone(), state.completed.clone());
+        let app = Router::new().fallback(mock_handler).with_state(state);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        MockUpstream { url: format!("http://{}", addr), hits, completed }
+    }
+
+    /// A JSON-RPC node answering each request, batched or not, right away.
+    pub async fn rpc(handler: impl Fn(&str, &Value) -> Result<Value, Value> + Send + Sync + 'static) -> Self {
+        Self::rpc_with_delay(Duration::ZERO, handler).await
+    }
+
+    /// A JSON-RPC node answering each request, batched or not, after `delay`, with the
+    /// handler's result (`Ok`) or error object (`Err`) for its method and params.
+    pub async fn rpc_with_delay(
+        delay: Duration,
+        handler: impl Fn(&str, &Value) -> Result<Value, Value> + Send + Sync + 'static,
+    ) -> Self {
+        Self::start(move |hit| rpc_reply(hit, &handler).delay(delay)).await
+    }
+
+    pub fn hits(&self) -> Vec<Hit> {
+        self.hits.lock().unwrap().clone()
+    }
+
+    /// Paths of the requests answered in full. A request whose client went away during its
+    /// reply's delay is missing.
+    pub fn completed(&self) -> Vec<String> {
+        self.completed.lock().unwrap().clone()
+    }
+
+    /// JSON-RPC methods received, in order, with batches flattened.
+    pub fn methods(&self) -> Vec<String> {
+        self.hits()
+            .iter()
+            .flat_map(|hit| match hit.json() {
+                Value::Array(requests) => requests,
+                request => vec![request],
+            })
+            .filter_map(|request| request["method"].as_str().map(str::to_string))
+            .collect()
+    }
+}
+
+/// Answers a JSON-RPC request, batched or not, with the handler's result (`Ok`) or error
+/// object (`Err`) for its method and params.
+pub fn rpc_reply(hit: &Hit, handler: impl Fn(&str, &Value) -> Result<Value, Value>) -> MockReply {
+    let answer = |request: &Value| {
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        let method = request["method"].as_str().unwrap_or_default();
+        match handler(method, &params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+            Err(error) => json!({ "jsonrpc": "2.0", "id": request["id"], "error": error }),
+        }
+    };
+    let body = match hit.json() {
+        Value::Array(requests) => Value::Array(requests.iter().map(answer).collect()),
+        request => answer(&request),
+    };
+    MockReply::json(body)
+}
+
+async fn mock_handler(
+    State(state): State<MockState>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let hit = Hit {
+        method,
+        path: uri.path().to_string(),
+        query: uri.query().map(str::to_string),
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    };
+    state.hits.lock().unwrap().push(hit.clone());
+
+    let reply = (state.responder)(&hit);
+    if !reply.delay.is_zero() {
+        tokio::time::sleep(reply.delay).await;
+    }
+    state.completed.lock().unwrap().push(hit.path);
+
+    let mut builder = Response::builder().status(reply.status);
+    for (name, value) in &reply.headers {
+        builder = builder.header(name, value);
+    }
+    builder.body(Body::from(reply.body)).unwrap()
+}
+
+/// The proxy, serving on a local port with `--dest` pointing at a mock upstream.
+pub struct TestProxy {
+    /// Root URL of the proxy, without a trailing slash
+    pub url: String,
+    pub state: AppState,
+    pub client: reqwest::Client,
+}
+
+impl TestProxy {
+    /// Starts the proxy with `--dest <upstream>/jsonrpc` and the given extra arguments. The
+    /// startup probes are skipped.
+    pub async fn start(upstream: &MockUpstream, extra_args: &[&str]) -> Self {
+        let dest = format!("{}/jsonrpc", upstream.url);
+        let args = ["tron-foundry-proxy", "--port", "0", "--dest", &dest].into_iter().chain(extra_args.iter().copied());
+        let args = Args::try_parse_from(args).unwrap();
+        let state = build_state(&args).unwrap();
+        let app = build_router(state.clone(), &args);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap()
+        });
+
+        TestProxy { url: format!("http://{}", addr), state, client: reqwest::Client::new() }
+    }
+
+    /// Sends one JSON-RPC request with id 1 and returns the parsed response.
+    pub async fn rpc(&self, method: &str, params: Value) -> Value {
+        self.post(&json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }))
+            .await
+            .json()
+            .await
+            .unwrap()
+    }
+
+    /// POSTs a JSON body to `/` and returns the raw response.
+    pub async fn post(&self, body: &Value) -> reqwest::Response {
+        self.post_text("/", &body.to_string()).await
+    }
+
+    pub async fn post_text(&self, path: &str, body: &str) -> reqwest::Response {
+        self.client
+            .post(format!("{}{}", self.url, path))
+            .header("content-type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .unwrap()
+    }
+}
@@ -0,0 +1,310 @@
+use serde_json::{json, Map, Value};
+
+use crate::address::canonical_evm_address;
+use crate::overrides::OverrideError;
+use crate::{fetch_transaction_info, upstream_call, AppState};
+
+/// Selector of Solidity's `Error(string)` revert payload
+const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// Builds a callTracer-shaped trace for `debug_traceTransaction`.
+///
+/// TRON can't replay transactions, so the trace is assembled from the transaction, its
+/// receipt and `wallet/gettransactioninfobyid`: the top-level call, with TRON's internal
+/// transactions as nested calls. TRON doesn't record call depth, so every internal
+/// transaction is a direct child of the top-level call.
+pub async fn trace_transaction(state: &AppState, params: Option<&Value>) -> Result<Value, OverrideError> {
+    let params = params.and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    let Some(tx_hash) = params.first().and_then(Value::as_str) else {
+        return Err((-32602, "debug_traceTransaction expects a transaction hash".to_string()));
+    };
+
+    let tracer_config = params.get(1).and_then(Value::as_object);
+    let tracer = tracer_config.and_then(|config| config.get("tracer")).and_then(Value::as_str);
+    if tracer != Some("callTracer") {
+        return Err((
+            -32601,
+            format!(
+                "{} is not supported: TRON can't replay transactions opcode by opcode, so only \
+                 {{\"tracer\": \"callTracer\"}} is available, built from TRON transaction info",
+                tracer.unwrap_or("struct-log tracing")
+            ),
+        ));
+    }
+    let only_top_call = tracer_config
+        .and_then(|config| config.get("tracerConfig"))
+        .and_then(|config| config.get("onlyTopCall"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+
+    let upstream_error = |e: anyhow::Error| (-32603, format!("failed to build trace: {}", e));
+
+    let tx = upstream_call(state, "eth_getTransactionByHash", json!([tx_hash]))
+        .await
+        .map_err(upstream_error)?;
+    let Some(tx) = tx.as_object() else {
+        return Err((-32000, format!("transaction {} not found", tx_hash)));
+    };
+    let receipt = upstream_call(state, "eth_getTransactionReceipt", json!([tx_hash]))
+        .await
+        .map_err(upstream_error)?;
+    let Some(info) = fetch_transaction_info(state, tx_hash).await else {
+        return Err((-32000, format!("transaction {} is not confirmed yet", tx_hash)));
+    };
+
+    let to = tx.get("to").and_then(Value::as_str).and_then(canonical_evm_address);
+    let mut call = Map::new();
+    call.insert("type".to_string(), json!(if to.is_some() { "CALL" } else { "CREATE" }));
+    call.insert("from".to_string(), address_value(tx.get("from")));
+    call.insert(
+        "to".to_string(),
+        json!(to.or_else(|| receipt.get("contractAddress").and_then(Value::as_str).and_then(canonical_evm_address))),
+    );
+    call.insert("value".to_string(), tx.get("value").cloned().unwrap_or(json!("0x0")));
+    call.insert("gas".to_string(), tx.get("gas").cloned().unwrap_or(json!("0x0")));
+    call.insert("gasUsed".to_string(), receipt.get("gasUsed").cloned().unwrap_or(json!("0x0")));
+    call.insert("input".to_string(), tx.get("input").cloned().unwrap_or(json!("0x")));
+
+    let output = info
+        .get("contractResult")
+        .and_then(|results| results.get(0))
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    call.insert("output".to_string(), json!(format!("0x{}", output)));
+
+    let contract_result = info
+        .pointer("/receipt/result")
+        .and_then(Value::as_str)
+        .unwrap_or("SUCCESS");
+    if contract_result != "SUCCESS" {
+        let error = if contract_result == "REVERT" {
+            "execution reverted".to_string()
+        } else {
+            contract_result.to_ascii_lowercase()
+        };
+        call.insert("error".to_string(), json!(error));
+
+        if let Some(reason) = hex::decode(output).ok().as_deref().and_then(decode_revert_reason) {
+            call.insert("revertReason".to_string(), json!(reason));
+        }
+    }
+
+    if !only_top_call {
+        let internal_calls: Vec<Value> = info
+            .get("internal_transactions")
+            .and_then(Value::as_array)
+            .map(|internal_txs| internal_txs.iter().map(internal_call).collect())
+            .unwrap_or_default();
+        if !internal_calls.is_empty() {
+            call.insert("calls".to_string(), json!(internal_calls));
+        }
+    }
+
+    Ok(Value::Object(call))
+}
+
+/// Maps one of TRON's internal transactions to a nested call frame.
+fn internal_call(internal_tx: &Value) -> Value {
+    // The note is the hex-encoded kind of the internal transaction: call, create or suicide
+    let note = internal_tx
+        .get("note")
+        .and_then(Value::as_str)
+        .and_then(|note| hex::decode(note).ok())
+        .and_then(|note| String::from_utf8(note).ok())
+        .unwrap_or_default();
+    let call_type = match note.as_str() {
+        "create" => "CREATE",
+        "suicide" => "SELFDESTRUCT",
+        _ => "CALL",
+    };
+
+    let value = internal_tx
+        .pointer("/callValueInfo/0/callValue")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+
+    let mut call = json!({
+        "type": call_type,
+        "from": address_value(internal_tx.get("caller_address")),
+        "to": address_value(internal_tx.get("transferTo_address")),
+        "value": format!("0x{:x}", value),
+        "gas": "0x0",
+        "gasUsed": "0x0",
+        "input": "0x",
+    });
+    if internal_tx.get("rejected").and_then(Value::as_bool) == Some(true) {
+        call["error"] = json!("rejected");
+    }
+
+    call
+}
+
+/// Converts an address in EVM or TRON hex form, with or without `0x`, to an EVM address.
+fn address_value(address: Option<&Value>) -> Value {
+    let Some(address) = address.and_then(Value::as_str) else {
+        return Value::Null;
+    };
+
+    let prefixed = if address.starts_with("0x") {
+        address.to_string()
+    } else {
+        format!("0x{}", address)
+    };
+    json!(canonical_evm_address(&prefixed))
+}
+
+/// Decodes the message of a Solidity `Error(string)` revert payload.
+pub fn decode_revert_reason(output: &[u8]) -> Option<String> {
+    let payload = output.strip_prefix(&ERROR_STRING_SELECTOR)?;
+    let length_word = payload.get(32..64)?;
+    // Anything that doesn't fit in the last 8 bytes of the length word isn't a real message
+    if length_word[..24].iter().any(|byte| *byte != 0) {
+        return None;
+    }
+
+    let length = u64::from_be_bytes(length_word[24..].try_into().ok()?) as usize;
+    let message = payload.get(64..64usize.checked_add(length)?)?;
+    String::from_utf8(message.to_vec()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::test_support::{rpc_reply, MockReply, MockUpstream, TestProxy};
+
+    const CALL_HASH: &str = "0x1111111111111111111111111111111111111111111111111111111111111111";
+    const CREATE_HASH: &str = "0x2222222222222222222222222222222222222222222222222222222222222222";
+    const REVERT_HASH: &str = "0x3333333333333333333333333333333333333333333333333333333333333333";
+    const SENDER: &str = "0xd8da6bf26964af9d7eed9e03e53415d37aa96045";
+    const CONTRACT: &str = "0xa614f803b6fd780986a42c78ec9c7f77e6ded13c";
+
+    /// `Error("insufficient balance")`, ABI-encoded
+    fn revert_output() -> String {
+        let message = hex::encode("insufficient balance");
+        format!("08c379a0{:064x}{:064x}{:0<64}", 32, message.len() / 2, message)
+    }
+
+    /// A node knowing a call into CONTRACT that makes one internal call, a deployment and a
+    /// call that reverted.
+    async fn trace_node() -> MockUpstream {
+        MockUpstream::start(|hit| {
+            if hit.path == "/wallet/gettransactioninfobyid" {
+                let id = hit.json()["value"].as_str().unwrap_or_default().to_string();
+                let info = match format!("0x{}", id).as_str() {
+                    CALL_HASH => json!({
+                        "id": id,
+                        "contractResult": [format!("{:064x}", 1)],
+                        "receipt": { "result": "SUCCESS" },
+                        "internal_transactions": [{
+                            "caller_address": format!("41{}", &CONTRACT[2..]),
+                            "transferTo_address": format!("41{}", &SENDER[2..]),
+                            "callValueInfo": [{ "callValue": 5 }],
+                            "note": hex::encode("call"),
+                        }],
+                    }),
+                    CREATE_HASH => json!({ "id": id, "contractResult": [""], "receipt": { "result": "SUCCESS" } }),
+                    REVERT_HASH => json!({ "id": id, "contractResult": [revert_output()], "receipt": { "result": "REVERT" } }),
+                    _ => json!({}),
+                };
+                return MockReply::json(info);
+            }
+            rpc_reply(hit, |method, params| {
+                let hash = params[0].as_str().unwrap_or_default();
+                let to = if hash == CREATE_HASH { Value::Null } else { json!(format!("0x41{}", &CONTRACT[2..])) };
+                match method {
+                    "eth_getTransactionByHash" => Ok(json!({
+                        "hash": hash,
+                        "from": format!("0x41{}", &SENDER[2..]),
+                        "to": to,
+                        "value": "0x0",
+                        "gas": "0x3e8",
+                        "input": "0xa9059cbb",
+                    })),
+                    "eth_getTransactionReceipt" if hash == CREATE_HASH => {
+                        Ok(json!({ "gasUsed": "0x64", "contractAddress": format!("0x41{}", &CONTRACT[2..]) }))
+                    }
+                    "eth_getTransactionReceipt" => Ok(json!({ "gasUsed": "0x64", "contractAddress": null })),
+                    _ => Err(json!({ "code": -32601, "message": "method not found" })),
+                }
+            })
+        })
+        .await
+    }
+
+    fn call_tracer(only_top_call: bool) -> Value {
+        json!({ "tracer": "callTracer", "tracerConfig": { "onlyTopCall": only_top_call } })
+    }
+
+    #[tokio::test]
+    async fn calls_are_traced_with_internal_transactions() {
+        let upstream = trace_node().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let trace = proxy.rpc("debug_traceTransaction", json!([CALL_HASH, call_tracer(false)])).await["result"].clone();
+        assert_eq!(
+            trace,
+            json!({
+                "type": "CALL",
+                "from": SENDER,
+                "to": CONTRACT,
+                "value": "0x0",
+                "gas": "0x3e8",
+                "gasUsed": "0x64",
+                "input": "0xa9059cbb",
+                "output": format!("0x{:064x}", 1),
+                "calls": [{
+                    "type": "CALL",
+                    "from": CONTRACT,
+                    "to": SENDER,
+                    "value": "0x5",
+                    "gas": "0x0",
+                    "gasUsed": "0x0",
+                    "input": "0x",
+                }],
+            })
+        );
+
+        let top_call = proxy.rpc("debug_traceTransaction", json!([CALL_HASH, call_tracer(true)])).await["result"].clone();
+        let mut expected = trace;
+        expected.as_object_mut().unwrap().remove("calls");
+        assert_eq!(top_call, expected);
+    }
+
+    #[tokio::test]
+    async fn deployments_are_traced_as_creates() {
+        let upstream = trace_node().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let trace = proxy.rpc("debug_traceTransaction", json!([CREATE_HASH, call_tracer(false)])).await["result"].clone();
+        assert_eq!(trace["type"], "CREATE");
+        assert_eq!(trace["to"], CONTRACT);
+        assert_eq!(trace["output"], "0x");
+        assert!(trace.get("calls").is_none() && trace.get("error").is_none(), "{}", trace);
+    }
+
+    #[tokio::test]
+    async fn reverts_carry_the_decoded_reason() {
+        let upstream = trace_node().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let trace = proxy.rpc("debug_traceTransaction", json!([REVERT_HASH, call_tracer(false)])).await["result"].clone();
+        assert_eq!(trace["error"], "execution reverted");
+        assert_eq!(trace["revertReason"], "insufficient balance");
+        assert_eq!(trace["output"], format!("0x{}", revert_output()));
+    }
+
+    #[tokio::test]
+    async fn only_the_call_tracer_is_supported() {
+        let upstream = trace_node().await;
+        let proxy = TestProxy::start(&upstream, &[]).await;
+
+        let response = proxy.rpc("debug_traceTransaction", json!([CALL_HASH, { "tracer": "prestateTracer" }])).await;
+        assert_eq!(response["error"]["code"], -32601);
+        assert!(response["error"]["message"].as_str().unwrap().starts_with("prestateTracer is not supported"), "{}", response);
+
+        let response = proxy.rpc("debug_traceTransaction", json!([CALL_HASH])).await;
+        assert!(response["error"]["message"].as_str().unwrap().starts_with("struct-log tracing is not supported"), "{}", response);
+        assert!(upstream.hits().is_empty());
+    }
+}
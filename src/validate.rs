@@ -0,0 +1,377 @@
+use serde_json::{Map, Value};
+use tracing::warn;
+
+use crate::{JsonRpcResponse, ResponseValidation};
+
+/// The shape a field's value must have.
+#[derive(Clone, Copy)]
+enum Kind {
+    /// `0x`-prefixed hex number without leading zeros
+    Quantity,
+    /// 32-byte hex
+    Hash,
+    /// 20-byte hex
+    Address,
+    /// 8-byte hex (block nonces)
+    Nonce,
+    /// 256-byte hex (logs blooms)
+    Bloom,
+    /// Any even-length hex
+    Data,
+    Bool,
+    /// Array of 32-byte hex
+    Hashes,
+}
+
+/// Whether a field must be present, and whether it may be `null`.
+#[derive(Clone, Copy)]
+enum Presence {
+    Required,
+    /// Present, possibly `null` (e.g. `to` on deployments, `blockHash` on pending transactions)
+    Nullable,
+    /// Checked only when present and not `null`
+    Optional,
+}
+
+use Kind::*;
+use Presence::*;
+
+/// Block header fields alloy requires (or checks when present)
+const BLOCK_FIELDS: &[(&str, Kind, Presence)] = &[
+    ("hash", Hash, Required),
+    ("parentHash", Hash, Required),
+    ("sha3Uncles", Hash, Required),
+    ("miner", Address, Required),
+    ("stateRoot", Hash, Required),
+    ("transactionsRoot", Hash, Required),
+    ("receiptsRoot", Hash, Required),
+    ("logsBloom", Bloom, Required),
+    ("difficulty", Quantity, Required),
+    ("number", Quantity, Required),
+    ("gasLimit", Quantity, Required),
+    ("gasUsed", Quantity, Required),
+    ("timestamp", Quantity, Required),
+    ("extraData", Data, Required),
+    ("mixHash", Hash, Optional),
+    ("nonce", Nonce, Optional),
+    ("baseFeePerGas", Quantity, Optional),
+    ("totalDifficulty", Quantity, Optional),
+    ("size", Quantity, Optional),
+    ("uncles", Hashes, Optional),
+];
+
+const TRANSACTION_FIELDS: &[(&str, Kind, Presence)] = &[
+    ("hash", Hash, Required),
+    ("nonce", Quantity, Required),
+    ("from", Address, Required),
+    ("to", Address, Nullable),
+    ("value", Quantity, Required),
+    ("gas", Quantity, Required),
+    ("input", Data, Required),
+    ("r", Quantity, Required),
+    ("s", Quantity, Required),
+    ("v", Quantity, Optional),
+    ("yParity", Quantity, Optional),
+    ("type", Quantity, Optional),
+    ("gasPrice", Quantity, Optional),
+    ("maxFeePerGas", Quantity, Optional),
+    ("maxPriorityFeePerGas", Quantity, Optional),
+    ("chainId", Quantity, Optional),
+    ("blockHash", Hash, Nullable),
+    ("blockNumber", Quantity, Nullable),
+    ("transactionIndex", Quantity, Nullable),
+];
+
+const RECEIPT_FIELDS: &[(&str, Kind, Presence)] = &[
+    ("transactionHash", Hash, Required),
+    ("transactionIndex", Quantity, Required),
+    ("blockHash", Hash, Required),
+    ("blockNumber", Quantity, Required),
+    ("from", Address, Required),
+    ("to", Address, Nullable),
+    ("cumulativeGasUsed", Quantity, Required),
+    ("gasUsed", Quantity, Required),
+    ("effectiveGasPrice", Quantity, Required),
+    ("contractAddress", Address, Nullable),
+    ("logsBloom", Bloom, Required),
+    ("status", Quantity, Optional),
+    ("type", Quantity, Optional),
+];
+
+const LOG_FIELDS: &[(&str, Kind, Presence)] = &[
+    ("address", Address, Required),
+    ("topics", Hashes, Required),
+    ("data", Data, Required),
+    ("blockHash", Hash, Optional),
+    ("blockNumber", Quantity, Optional),
+    ("transactionHash", Hash, Optional),
+    ("transactionIndex", Quantity, Optional),
+    ("logIndex", Quantity, Optional),
+    ("removed", Bool, Optional),
+];
+
+/// Checks an enhanced response against the shape clients deserialize it into, for the methods
+/// whose results are blocks, transactions, receipts or logs. Under `--validate-responses` a
+/// mismatch is logged with every offending field; in strict mode the response is replaced by
+/// a `-32603` error naming them. Error responses and other methods are left alone.
+pub fn validate_response(mode: ResponseValidation, rpc_method: &str, response_body: String) -> String {
+    let Ok(response) = serde_json::from_str::<JsonRpcResponse>(&response_body) else {
+        return response_body;
+    };
+    let Some(result) = response.result.as_ref().filter(|result| !result.is_null()) else {
+        return response_body;
+    };
+
+    let mut problems = Vec::new();
+    match rpc_method {
+        "eth_getBlockByNumber" | "eth_getBlockByHash" => check_block(result, "block", &mut problems),
+        "eth_getTransactionByHash"
+        | "eth_getTransactionByBlockNumberAndIndex"
+        | "eth_getTransactionByBlockHashAndIndex" => check_object(result, TRANSACTION_FIELDS, "transaction", &mut problems),
+        "eth_getTransactionReceipt" => check_receipt(result, "receipt", &mut problems),
+        "eth_getBlockReceipts" => check_each(result, "receipts", &mut problems, check_receipt),
+        "eth_getLogs" | "eth_getFilterLogs" => {
+            check_each(result, "logs", &mut problems, |log, path, problems| check_object(log, LOG_FIELDS, path, problems))
+        }
+        _ => return response_body,
+    }
+
+    if problems.is_empty() {
+        return response_body;
+    }
+
+    let summary = problems.join("; ");
+    warn!("{} response failed validation: {}", rpc_method, summary);
+    if mode != ResponseValidation::Strict {
+        return response_body;
+    }
+
+    let message = format!("invalid {} response: {}", rpc_method, summary);
+    let error = JsonRpcResponse::failure(response.id, -32603, message);
+    serde_json::to_string(&error).unwrap_or(response_body)
+}
+
+fn check_block(block: &Value, path: &str, problems: &mut Vec<String>) {
+    check_object(block, BLOCK_FIELDS, path, problems);
+
+    let Some(transactions) = block.get("transactions") else {
+        problems.push(format!("{}.transactions is missing", path));
+        return;
+    };
+    check_each(transactions, &format!("{}.transactions", path), problems, |tx, path, problems| {
+        // Either hashes or full transaction objects, depending on the request
+        if tx.is_object() {
+            check_object(tx, TRANSACTION_FIELDS, path, problems);
+        } else if let Err(problem) = check_kind(tx, Hash) {
+            problems.push(format!("{} {}", path, problem));
+        }
+    });
+}
+
+fn check_receipt(receipt: &Value, path: &str, problems: &mut Vec<String>) {
+    check_object(receipt, RECEIPT_FIELDS, path, problems);
+
+    match receipt.get("logs") {
+        Some(logs) => check_each(logs, &format!("{}.logs", path), problems, |log, path, problems| {
+            check_object(log, LOG_FIELDS, path, problems)
+        }),
+        None => problems.push(format!("{}.logs is missing", path)),
+    }
+}
+
+/// Checks every element of an array with `check`, or reports that it isn't one.
+fn check_each(value: &Value, path: &str, problems: &mut Vec<String>, check: impl Fn(&Value, &str, &mut Vec<String>)) {
+    let Some(elements) = value.as_array() else {
+        problems.push(format!("{} is not an array", path));
+        return;
+    };
+    for (index, element) in elements.iter().enumerate() {
+        check(element, &format!("{}[{}]", path, index), problems);
+    }
+}
+
+fn check_object(value: &Value, fields: &[(&str, Kind, Presence)], path: &str, problems: &mut Vec<String>) {
+    let Some(object) = value.as_object() else {
+        problems.push(format!("{} is not an object", path));
+        return;
+    };
+
+    for &(field, kind, presence) in fields {
+        if let Err(problem) = check_field(object, field, kind, presence) {
+            problems.push(format!("{}.{} {}", path, field, problem));
+        }
+    }
+}
+
+fn check_field(object: &Map<String, Value>, field: &str, kind: Kind, presence: Presence) -> Result<(), String> {
+    match (object.get(field), presence) {
+        (None, Required | Nullable) => Err("is missing".to_string()),
+        (None, Optional) => Ok(()),
+        (Some(Value::Null), Required) => Err("is null".to_string()),
+        (Some(Value::Null), Nullable | Optional) => Ok(()),
+        (Some(value), _) => check_kind(value, kind),
+    }
+}
+
+fn check_kind(value: &Value, kind: Kind) -> Result<(), String> {
+    let valid = match kind {
+        Bool => value.is_boolean(),
+        Hashes => {
+            return match value.as_array() {
+                Some(hashes) => hashes.iter().try_for_each(|hash| check_kind(hash, Hash)),
+                None => Err(format!("is not an array of hashes (got {})", value)),
+            };
+        }
+        _ => value.as_str().and_then(|s| s.strip_prefix("0x")).is_some_and(|hex| is_valid_hex(hex, kind)),
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("is not {} (got {})", describe(kind), truncated(value)))
+    }
+}
+
+fn is_valid_hex(hex: &str, kind: Kind) -> bool {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return false;
+    }
+    match kind {
+        Quantity => !hex.is_empty() && (hex == "0" || !hex.starts_with('0')),
+        Hash => hex.len() == 64,
+        Address => hex.len() == 40,
+        Nonce => hex.len() == 16,
+        Bloom => hex.len() == 512,
+        Data => hex.len().is_multiple_of(2),
+        Bool | Hashes => false,
+    }
+}
+
+fn describe(kind: Kind) -> &'static str {
+    match kind {
+        Quantity => "a hex quantity",
+        Hash => "a 32-byte hex hash",
+        Address => "a 20-byte hex address",
+        Nonce => "an 8-byte hex nonce",
+        Bloom => "a 256-byte hex bloom",
+        Data => "hex data",
+        Bool => "a boolean",
+        Hashes => "an array of hashes",
+    }
+}
+
+/// The offending value for the message, cut short so a huge `input` doesn't flood the log.
+fn truncated(value: &Value) -> String {
+    let value = value.to_string();
+    match value.char_indices().nth(80) {
+        Some((end, _)) => format!("{}...", &value[..end]),
+        None => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const HASH: &str = "0x3f6c1e4f2a9a1e8d1c8a1f0b6c2e9d7f5a4b3c2d1e0f9a8b7c6d5e4f3a2b1c0d";
+    const ADDRESS: &str = "0xd8da6bf26964af9d7eed9e03e53415d37aa96045";
+
+    fn bloom() -> String {
+        format!("0x{}", "0".repeat(512))
+    }
+
+    fn transaction() -> Value {
+        json!({
+            "hash": HASH, "nonce": "0x0", "from": ADDRESS, "to": ADDRESS, "value": "0x0", "gas": "0x5208",
+            "gasPrice": "0xd2", "input": "0x", "r": "0x1", "s": "0x1", "v": "0x1b", "type": "0x0",
+            "blockHash": HASH, "blockNumber": "0x10", "transactionIndex": "0x0",
+        })
+    }
+
+    fn log() -> Value {
+        json!({
+            "address": ADDRESS, "topics": [HASH], "data": "0x", "blockHash": HASH, "blockNumber": "0x10",
+            "transactionHash": HASH, "transactionIndex": "0x0", "logIndex": "0x0", "removed": false,
+        })
+    }
+
+    fn receipt() -> Value {
+        json!({
+            "transactionHash": HASH, "transactionIndex": "0x0", "blockHash": HASH, "blockNumber": "0x10",
+            "from": ADDRESS, "to": ADDRESS, "cumulativeGasUsed": "0x5208", "gasUsed": "0x5208",
+            "effectiveGasPrice": "0xd2", "contractAddress": null, "logsBloom": bloom(), "status": "0x1",
+            "type": "0x0", "logs": [log()],
+        })
+    }
+
+    fn block() -> Value {
+        json!({
+            "hash": HASH, "parentHash": HASH, "sha3Uncles": HASH, "miner": ADDRESS, "stateRoot": HASH,
+            "transactionsRoot": HASH, "receiptsRoot": HASH, "logsBloom": bloom(), "difficulty": "0x0",
+            "number": "0x10", "gasLimit": "0x0", "gasUsed": "0x0", "timestamp": "0x6553f100", "extraData": "0x",
+            "nonce": "0x0000000000000000", "uncles": [], "transactions": [transaction()],
+        })
+    }
+
+    fn validate(mode: ResponseValidation, method: &str, result: Value) -> Value {
+        let body = json!({ "jsonrpc": "2.0", "id": 7, "result": result }).to_string();
+        serde_json::from_str(&validate_response(mode, method, body)).unwrap()
+    }
+
+    /// The message strict mode fails the request with, or `None` if the result passed.
+    fn strict_error(method: &str, result: Value) -> Option<String> {
+        let response = validate(ResponseValidation::Strict, method, result);
+        response["error"]["message"].as_str().map(str::to_string)
+    }
+
+    #[test]
+    fn well_formed_results_pass() {
+        assert_eq!(strict_error("eth_getBlockByNumber", block()), None);
+        assert_eq!(strict_error("eth_getTransactionByHash", transaction()), None);
+        assert_eq!(strict_error("eth_getTransactionReceipt", receipt()), None);
+        assert_eq!(strict_error("eth_getLogs", json!([log()])), None);
+    }
+
+    #[test]
+    fn block_missing_its_hash_is_caught() {
+        let mut block = block();
+        block.as_object_mut().unwrap().remove("hash");
+        let message = strict_error("eth_getBlockByHash", block).unwrap();
+        assert_eq!(message, "invalid eth_getBlockByHash response: block.hash is missing");
+    }
+
+    #[test]
+    fn transaction_with_a_short_from_is_caught() {
+        let mut tx = transaction();
+        tx["from"] = json!("0x41d8da6b");
+        let message = strict_error("eth_getTransactionByHash", tx).unwrap();
+        assert!(message.contains("transaction.from is not a 20-byte hex address"), "{}", message);
+    }
+
+    #[test]
+    fn receipt_with_a_padded_quantity_is_caught() {
+        let mut receipt = receipt();
+        receipt["gasUsed"] = json!("0x05208");
+        let message = strict_error("eth_getTransactionReceipt", receipt).unwrap();
+        assert!(message.contains("receipt.gasUsed is not a hex quantity"), "{}", message);
+    }
+
+    #[test]
+    fn log_with_null_topics_is_caught() {
+        let mut log = log();
+        log["topics"] = Value::Null;
+        let message = strict_error("eth_getLogs", json!([self::log(), log])).unwrap();
+        assert_eq!(message, "invalid eth_getLogs response: logs[1].topics is null");
+    }
+
+    #[test]
+    fn warn_mode_keeps_the_response() {
+        let mut block = block();
+        block.as_object_mut().unwrap().remove("hash");
+        let response = validate(ResponseValidation::Warn, "eth_getBlockByNumber", block.clone());
+        assert_eq!(response["result"], block);
+        assert_eq!(response["id"], 7);
+    }
+}
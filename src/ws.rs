@@ -0,0 +1,658 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Query, State,
+    },
+    http::{header, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Map, Value};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest, protocol::frame::coding::CloseCode};
+use tracing::{debug, info, warn};
+
+use crate::block_tags::rewrite_block_tags;
+use crate::filters::{latest_block, MAX_BLOCK_FILTER_WINDOW};
+use crate::logs::{get_logs, normalize_filter_addresses};
+use crate::{
+    block_net_usage, check_method_policy, enhance_block_response, enhance_response, handle_get_request, handle_post_text, positional_call_params,
+    select_upstream, translate_call_request, upstream_call, AppState, JsonRpcRequest, JsonRpcResponse,
+};
+
+/// Serves `GET /` when `--ws` or `--ws-dest` is set: WebSocket upgrades become JSON-RPC
+/// sockets (tunnelled to `--ws-dest` if there is one), anything else is forwarded like a
+/// plain GET.
+pub async fn handle_root_get(
+    State(state): State<AppState>,
+    ws: Option<WebSocketUpgrade>,
+    ConnectInfo(remote_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    query: Query<Vec<(String, String)>>,
+) -> Response {
+    match ws {
+        Some(ws) => {
+            let upstream_state = match select_upstream(state.clone(), &headers) {
+                Ok(upstream_state) => upstream_state,
+                Err(e) => return e.into_response(),
+            };
+            match upstream_state.ws_dest.clone() {
+                Some(dest) => {
+                    info!("WebSocket connection from {}, tunnelling to {}", remote_addr, dest);
+                    // The same Authorization an HTTP request would carry upstream
+                    let authorization = headers
+                        .get(header::AUTHORIZATION)
+                        .filter(|_| !upstream_state.override_auth)
+                        .or(upstream_state.upstream_auth.as_ref())
+                        .cloned();
+                    ws.on_upgrade(move |socket| tunnel_socket(upstream_state, socket, remote_addr, dest, authorization))
+                }
+                None => {
+                    info!("WebSocket connection from {}", remote_addr);
+                    ws.on_upgrade(move |socket| serve_socket(state, socket, remote_addr, headers))
+                }
+            }
+        }
+        None => handle_get_request(State(state), ConnectInfo(remote_addr), headers, query).await.into_response(),
+    }
+}
+
+/// A socket's live subscriptions, keyed by subscription id. Each one is a background poller
+/// that pushes notifications until it's aborted.
+#[derive(Default)]
+struct Subscriptions {
+    pollers: HashMap<String, JoinHandle<()>>,
+    next_id: u64,
+}
+
+impl Subscriptions {
+    fn add(&mut self, poller: impl FnOnce(String) -> JoinHandle<()>) -> String {
+        self.next_id += 1;
+        let id = format!("0x{:x}", self.next_id);
+        self.pollers.insert(id.clone(), poller(id.clone()));
+        id
+    }
+
+    fn remove(&mut self, id: &str) -> bool {
+        match self.pollers.entry(id.to_string()) {
+            Entry::Occupied(entry) => {
+                entry.remove().abort();
+                true
+            }
+            Entry::Vacant(_) => false,
+        }
+    }
+}
+
+impl Drop for Subscriptions {
+    fn drop(&mut self) {
+        for (id, poller) in self.pollers.drain() {
+            debug!("Stopping subscription {}", id);
+            poller.abort();
+        }
+    }
+}
+
+/// Answers JSON-RPC messages on a socket until it closes. Subscriptions are handled here;
+/// everything else goes through `handle_post_text` like an HTTP request.
+async fn serve_socket(state: AppState, socket: WebSocket, remote_addr: SocketAddr, headers: HeaderMap) {
+    let (mut sink, mut stream) = socket.split();
+
+    // Responses and notifications are queued so pollers and in-flight requests can write
+    // without holding the socket
+    let (outgoing, mut queued) = mpsc::unbounded_channel::<String>();
+    let writer = tokio::spawn(async move {
+        while let Some(text) = queued.recv().await {
+            if sink.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut subscriptions = Subscriptions::default();
+
+    while let Some(message) = stream.next().await {
+        let text = match message {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Binary(bytes)) => match String::from_utf8(bytes) {
+                Ok(text) => text,
+                Err(_) => continue,
+            },
+            Ok(Message::Close(_)) | Err(_) => break,
+            // Pings are answered by axum
+            Ok(_) => continue,
+        };
+
+        let request = serde_json::from_str::<JsonRpcRequest>(&text).ok();
+        match request.as_ref().map(|request| request.method.as_str()) {
+            Some("eth_subscribe") => {
+                let request = request.unwrap();
+                let response = subscribe(&state, &mut subscriptions, &outgoing, request);
+                let _ = outgoing.send(to_text(&response));
+            }
+            Some("eth_unsubscribe") => {
+                let request = request.unwrap();
+                let id = request.params.as_ref().and_then(|params| params.get(0)).and_then(Value::as_str);
+                let removed = id.is_some_and(|id| subscriptions.remove(id));
+                if removed {
+                    info!("Subscription {} from {} cancelled", id.unwrap_or_default(), remote_addr);
+                }
+                let _ = outgoing.send(to_text(&JsonRpcResponse::success(request.id, json!(removed))));
+            }
+            _ => {
+                // Answered concurrently, so a slow call doesn't hold up the socket
+                let state = state.clone();
+                let headers = headers.clone();
+                let outgoing = outgoing.clone();
+                tokio::spawn(async move {
+                    let body = match handle_post_text(State(state), ConnectInfo(remote_addr), headers, text).await {
+                        Ok(response) => response.into_body(),
+                        Err(e) => e.to_json().to_string(),
+                    };
+                    // Notifications are answered with an empty body, which isn't sent
+                    if !body.is_empty() {
+                        let _ = outgoing.send(body);
+                    }
+                });
+            }
+        }
+    }
+
+    drop(subscriptions);
+    drop(outgoing);
+    let _ = writer.await;
+    info!("WebSocket connection from {} closed", remote_addr);
+}
+
+/// Relays a socket to the upstream's own WebSocket endpoint. Requests are translated like
+/// HTTP ones on the way up and responses enhanced on the way down; binary frames and pings
+/// pass through untouched. When either side closes or fails, both are closed. The upstream
+/// handshake carries `authorization`, if any.
+async fn tunnel_socket(
+    state: AppState,
+    mut socket: WebSocket,
+    remote_addr: SocketAddr,
+    dest: String,
+    authorization: Option<HeaderValue>,
+) {
+    let connected = match dest.as_str().into_client_request() {
+        Ok(mut request) => {
+            if let Some(authorization) = authorization {
+                request.headers_mut().insert(header::AUTHORIZATION, authorization);
+            }
+            tokio_tungstenite::connect_async(request).await
+        }
+        Err(e) => Err(e),
+    };
+    let upstream = match connected {
+        Ok((upstream, _)) => upstream,
+        Err(e) => {
+            warn!("Failed to connect to WebSocket upstream {}: {}", dest, e);
+            let _ = socket
+                .send(Message::Close(Some(CloseFrame {
+                    code: close_code::ERROR,
+                    reason: "upstream unavailable".into(),
+                })))
+                .await;
+            return;
+        }
+    };
+
+    let (mut client_sink, mut client_stream) = socket.split();
+    let (mut upstream_sink, mut upstream_stream) = upstream.split();
+
+    // Both directions write to the client: responses from upstream, and rejections of
+    // requests that never leave the proxy
+    let (to_client, mut queued) = mpsc::unbounded_channel::<Message>();
+    let writer = tokio::spawn(async move {
+        while let Some(message) = queued.recv().await {
+            if client_sink.send(message).await.is_err() {
+                break;
+            }
+        }
+        let _ = client_sink.close().await;
+    });
+
+    // Requests awaiting a response, by JSON id, with their method and body
+    let in_flight = Mutex::new(HashMap::<String, (String, String)>::new());
+
+    let upstream_bound = async {
+        while let Some(Ok(message)) = client_stream.next().await {
+            let message = match message {
+                Message::Text(text) => match translate_frame(&state, remote_addr, text, &in_flight).await {
+                    Ok(text) => tungstenite::Message::Text(text),
+                    Err(rejection) => {
+                        let _ = to_client.send(Message::Text(to_text(&rejection)));
+                        continue;
+                    }
+                },
+                Message::Binary(bytes) => tungstenite::Message::Binary(bytes),
+                Message::Ping(payload) => tungstenite::Message::Ping(payload),
+                Message::Pong(payload) => tungstenite::Message::Pong(payload),
+                Message::Close(frame) => {
+                    let _ = upstream_sink
+                        .send(tungstenite::Message::Close(frame.map(|frame| tungstenite::protocol::CloseFrame {
+                            code: CloseCode::from(frame.code),
+                            reason: frame.reason,
+                        })))
+                        .await;
+                    break;
+                }
+            };
+            if upstream_sink.send(message).await.is_err() {
+                break;
+            }
+        }
+    };
+
+    let client_bound = async {
+        while let Some(message) = upstream_stream.next().await {
+            let message = match message {
+                Ok(tungstenite::Message::Text(text)) => Message::Text(enhance_frame(&state, text, &in_flight).await),
+                Ok(tungstenite::Message::Binary(bytes)) => Message::Binary(bytes),
+                Ok(tungstenite::Message::Ping(payload)) => Message::Ping(payload),
+                Ok(tungstenite::Message::Pong(payload)) => Message::Pong(payload),
+                Ok(tungstenite::Message::Close(frame)) => {
+                    let _ = to_client.send(Message::Close(frame.map(|frame| CloseFrame {
+                        code: frame.code.into(),
+                        reason: frame.reason,
+                    })));
+                    break;
+                }
+                Ok(tungstenite::Message::Frame(_)) => continue,
+                Err(e) => {
+                    warn!("WebSocket upstream {} failed: {}", dest, e);
+                    let _ = to_client.send(Message::Close(Some(CloseFrame {
+                        code: close_code::ERROR,
+                        reason: "upstream connection failed".into(),
+                    })));
+                    break;
+                }
+            };
+            if to_client.send(message).is_err() {
+                break;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = upstream_bound => {}
+        _ = client_bound => {}
+    }
+
+    let _ = upstream_sink.close().await;
+    drop(to_client);
+    let _ = writer.await;
+    info!("WebSocket tunnel from {} closed", remote_addr);
+}
+
+/// Runs a client frame through the request translation. Frames that aren't a single
+/// JSON-RPC request pass through as they are; rejected requests yield the error response.
+async fn translate_frame(
+    state: &AppState,
+    remote_addr: SocketAddr,
+    text: String,
+    in_flight: &Mutex<HashMap<String, (String, String)>>,
+) -> Result<String, JsonRpcResponse> {
+    let Ok(mut request) = serde_json::from_str::<JsonRpcRequest>(&text) else {
+        return Ok(text);
+    };
+
+    positional_call_params(&mut request);
+    if let Some(params) = &mut request.params {
+        rewrite_block_tags(state, &request.method, params).await;
+    }
+    if let Err((code, message)) =
+        check_method_policy(state, &request.method, remote_addr).and_then(|()| translate_call_request(state, &mut request))
+    {
+        return Err(JsonRpcResponse::failure(request.id, code, message));
+    }
+
+    let mut translated = serde_json::to_value(&request).unwrap_or_default();
+    match &request.id {
+        Some(id) => {
+            in_flight.lock().unwrap().insert(id.to_string(), (request.method.clone(), translated.to_string()));
+        }
+        // Keep notifications id-less so the upstream doesn't answer them
+        None => {
+            if let Some(translated) = translated.as_object_mut() {
+                translated.remove("id");
+            }
+        }
+    }
+
+    Ok(translated.to_string())
+}
+
+/// Enhances an upstream frame that answers a request sent through the tunnel.
+/// Subscription notifications and anything unrecognized pass through unchanged.
+async fn enhance_frame(state: &AppState, text: String, in_flight: &Mutex<HashMap<String, (String, String)>>) -> String {
+    let id = serde_json::from_str::<Value>(&text).ok().and_then(|response| response.get("id").cloned());
+    let request = id.and_then(|id| in_flight.lock().unwrap().remove(&id.to_string()));
+
+    match request {
+        Some((method, request_body)) => enhance_response(state, &method, &request_body, text).await,
+        None => text,
+    }
+}
+
+fn subscribe(
+    state: &AppState,
+    subscriptions: &mut Subscriptions,
+    outgoing: &UnboundedSender<String>,
+    request: JsonRpcRequest,
+) -> JsonRpcResponse {
+    let kind = request.params.as_ref().and_then(|params| params.get(0)).and_then(Value::as_str);
+
+    match kind {
+        Some("newHeads") => {
+            let id = subscriptions.add(|id| {
+                tokio::spawn(poll_new_heads(state.clone(), id, outgoing.clone()))
+            });
+            info!("Subscription {} to newHeads started", id);
+            JsonRpcResponse::success(request.id, json!(id))
+        }
+        Some("logs") => {
+            let mut criteria = match request.params.as_ref().and_then(|params| params.get(1)) {
+                None | Some(Value::Null) => Map::new(),
+                Some(Value::Object(criteria)) => criteria.clone(),
+                Some(_) => return JsonRpcResponse::failure(request.id, -32602, "expected a logs filter object"),
+            };
+            normalize_filter_addresses(&state.address_cache, &mut criteria);
+            if criteria.contains_key("fromBlock") || criteria.contains_key("toBlock") || criteria.contains_key("blockHash") {
+                return JsonRpcResponse::failure(request.id, -32602, "logs subscriptions only take address and topics");
+            }
+
+            let id = subscriptions.add(|id| {
+                tokio::spawn(poll_logs(state.clone(), id, criteria, outgoing.clone()))
+            });
+            info!("Subscription {} to logs started", id);
+            JsonRpcResponse::success(request.id, json!(id))
+        }
+        Some(kind) => JsonRpcResponse::failure(request.id, -32602, format!("unsupported subscription type {}", kind)),
+        None => JsonRpcResponse::failure(request.id, -32602, "expected a subscription type"),
+    }
+}
+
+/// Pushes the header of every new block, polling the latest block every
+/// `--ws-poll-interval`. A poller that falls behind skips ahead like a block filter.
+async fn poll_new_heads(state: AppState, subscription: String, outgoing: UnboundedSender<String>) {
+    let mut interval = tokio::time::interval(state.ws_poll_interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut next_block = None;
+
+    loop {
+        interval.tick().await;
+
+        let latest = match latest_block(&state).await {
+            Ok(latest) => latest,
+            Err((_, message)) => {
+                warn!("newHeads subscription {}: {}", subscription, message);
+                continue;
+            }
+        };
+
+        let first = next_block.unwrap_or(latest).max((latest + 1).saturating_sub(MAX_BLOCK_FILTER_WINDOW));
+        for number in first..=latest {
+            let header = match fetch_header(&state, number).await {
+                Ok(Some(header)) => header,
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("newHeads subscription {} failed to fetch block {}: {}", subscription, number, e);
+                    break;
+                }
+            };
+
+            if outgoing.send(notification(&subscription, header)).is_err() {
+                return;
+            }
+            next_block = Some(number + 1);
+        }
+    }
+}
+
+/// Pushes every new log matching `criteria` (address and topics), polling for new blocks
+/// every `--ws-poll-interval` and querying them like an `eth_getFilterChanges` poll.
+/// Logs from before the subscription aren't reported.
+async fn poll_logs(state: AppState, subscription: String, criteria: Map<String, Value>, outgoing: UnboundedSender<String>) {
+    let mut interval = tokio::time::interval(state.ws_poll_interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut next_block = None;
+
+    loop {
+        interval.tick().await;
+
+        let latest = match latest_block(&state).await {
+            Ok(latest) => latest,
+            Err((_, message)) => {
+                warn!("logs subscription {}: {}", subscription, message);
+                continue;
+            }
+        };
+        let Some(first) = next_block else {
+            next_block = Some(latest + 1);
+            continue;
+        };
+        if first > latest {
+            continue;
+        }
+        let first = first.max((latest + 1).saturating_sub(MAX_BLOCK_FILTER_WINDOW));
+
+        let mut range = criteria.clone();
+        range.insert("fromBlock".to_string(), json!(format!("0x{:x}", first)));
+        range.insert("toBlock".to_string(), json!(format!("0x{:x}", latest)));
+
+        let logs = match get_logs(&state, range).await {
+            Ok(logs) => logs,
+            Err((_, message)) => {
+                warn!("logs subscription {} failed to fetch blocks {}..={}: {}", subscription, first, latest, message);
+                continue;
+            }
+        };
+
+        for log in logs.as_array().into_iter().flatten() {
+            if outgoing.send(notification(&subscription, log.clone())).is_err() {
+                return;
+            }
+        }
+        next_block = Some(latest + 1);
+    }
+}
+
+/// Fetches a block and reduces it to its header, enhanced like an `eth_getBlockByNumber`
+/// response.
+async fn fetch_header(state: &AppState, number: u64) -> anyhow::Result<Option<Value>> {
+    let block = upstream_call(state, "eth_getBlockByNumber", json!([format!("0x{:x}", number), false])).await?;
+    if block.is_null() {
+        return Ok(None);
+    }
+
+    let body = serde_json::to_string(&JsonRpcResponse::success(Some(json!(1)), block))?;
+    let net_usage = block_net_usage(state, Some(number)).await;
+    let enhanced = enhance_block_response(&body, "eth_getBlockByNumber", false, &state.pending_txs, state.gas_ratios, net_usage);
+    let mut header = serde_json::from_str::<JsonRpcResponse>(&enhanced)?.result.unwrap_or(Value::Null);
+
+    if let Some(header) = header.as_object_mut() {
+        header.remove("transactions");
+        header.remove("uncles");
+    }
+    Ok(Some(header))
+}
+
+fn notification(subscription: &str, result: Value) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "eth_subscription",
+        "params": { "subscription": subscription, "result": result },
+    })
+    .to_string()
+}
+
+fn to_text(response: &JsonRpcResponse) -> String {
+    serde_json::to_string(response).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::oneshot;
+    use tokio_tungstenite::tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response as HandshakeResponse};
+
+    use super::*;
+    use crate::test_support::{MockUpstream, TestProxy};
+
+    type ClientSocket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+    /// Reports the Authorization header of a WebSocket handshake.
+    struct CaptureAuthorization(oneshot::Sender<Option<String>>);
+
+    impl Callback for CaptureAuthorization {
+        fn on_request(self, request: &Request, response: HandshakeResponse) -> Result<HandshakeResponse, ErrorResponse> {
+            let authorization = request.headers().get(header::AUTHORIZATION).map(|value| value.to_str().unwrap().to_string());
+            let _ = self.0.send(authorization);
+            Ok(response)
+        }
+    }
+
+    /// A WebSocket server accepting one connection, reporting the Authorization header of its
+    /// handshake.
+    async fn ws_upstream() -> (String, oneshot::Receiver<Option<String>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (report, reported) = oneshot::channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let _socket = tokio_tungstenite::accept_hdr_async(stream, CaptureAuthorization(report)).await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        (url, reported)
+    }
+
+    async fn tunnel_authorization(proxy_args: &[&str], client_authorization: Option<&str>) -> Option<String> {
+        let upstream = MockUpstream::rpc(|_, _| Ok(json!("0x1"))).await;
+        let (ws_dest, reported) = ws_upstream().await;
+        let args: Vec<&str> = ["--ws-dest", ws_dest.as_str()].into_iter().chain(proxy_args.iter().copied()).collect();
+        let proxy = TestProxy::start(&upstream, &args).await;
+
+        let mut request = proxy.url.replace("http://", "ws://").into_client_request().unwrap();
+        if let Some(authorization) = client_authorization {
+            request.headers_mut().insert(header::AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+        }
+        let (_client, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+        reported.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn tunnel_sends_upstream_auth() {
+        let authorization = tunnel_authorization(&["--upstream-auth", "bearer:secret"], None).await;
+        assert_eq!(authorization.as_deref(), Some("Bearer secret"));
+    }
+
+    #[tokio::test]
+    async fn tunnel_forwards_client_auth_unless_overridden() {
+        let forwarded = tunnel_authorization(&["--upstream-auth", "bearer:secret"], Some("Bearer mine")).await;
+        let overridden = tunnel_authorization(&["--upstream-auth", "bearer:secret", "--override-auth"], Some("Bearer mine")).await;
+        let none = tunnel_authorization(&[], None).await;
+
+        assert_eq!(forwarded.as_deref(), Some("Bearer mine"));
+        assert_eq!(overridden.as_deref(), Some("Bearer secret"));
+        assert_eq!(none, None);
+    }
+
+    /// A node at block 0x10, answering every eth_getBlockByNumber with a block of that number.
+    async fn block_node() -> MockUpstream {
+        MockUpstream::rpc(|method, params| match method {
+            "eth_blockNumber" => Ok(json!("0x10")),
+            "eth_getBlockByNumber" => Ok(json!({
+                "number": params[0],
+                "hash": format!("0x{:064x}", 0x10),
+                "parentHash": format!("0x{:064x}", 0x0f),
+                "gasUsed": "0x0",
+                "transactions": [],
+                "uncles": [],
+            })),
+            _ => Err(json!({ "code": -32601, "message": "method not found" })),
+        })
+        .await
+    }
+
+    async fn connect(proxy: &TestProxy) -> ClientSocket {
+        let (socket, _) = tokio_tungstenite::connect_async(proxy.url.replace("http://", "ws://")).await.unwrap();
+        socket
+    }
+
+    async fn send(socket: &mut ClientSocket, id: u64, method: &str, params: Value) {
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        socket.send(tungstenite::Message::Text(request.to_string())).await.unwrap();
+    }
+
+    /// The next JSON message on the socket, failing the test if none comes within 5 seconds.
+    async fn receive(socket: &mut ClientSocket) -> Value {
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next()).await.unwrap().unwrap().unwrap();
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn requests_over_websocket_are_answered() {
+        let upstream = block_node().await;
+        let proxy = TestProxy::start(&upstream, &["--ws"]).await;
+        let mut socket = connect(&proxy).await;
+
+        send(&mut socket, 1, "eth_blockNumber", json!([])).await;
+        assert_eq!(receive(&mut socket).await, json!({ "jsonrpc": "2.0", "id": 1, "result": "0x10" }));
+
+        send(&mut socket, 2, "eth_getBlockByNumber", json!(["0x10", false])).await;
+        let block = receive(&mut socket).await;
+        assert_eq!((block["id"].clone(), block["result"]["number"].clone()), (json!(2), json!("0x10")));
+    }
+
+    #[tokio::test]
+    async fn new_heads_are_pushed_until_unsubscribed() {
+        let upstream = block_node().await;
+        let proxy = TestProxy::start(&upstream, &["--ws", "--ws-poll-interval", "1"]).await;
+        let mut socket = connect(&proxy).await;
+
+        send(&mut socket, 1, "eth_subscribe", json!(["newHeads"])).await;
+        let subscription = receive(&mut socket).await["result"].clone();
+        assert!(subscription.is_string());
+
+        let notification = receive(&mut socket).await;
+        assert_eq!(notification["method"], "eth_subscription");
+        assert_eq!(notification["params"]["subscription"], subscription);
+        let header = &notification["params"]["result"];
+        assert_eq!(header["number"], "0x10");
+        assert!(header.get("transactions").is_none() && header.get("uncles").is_none());
+
+        send(&mut socket, 2, "eth_unsubscribe", json!([subscription])).await;
+        assert_eq!(receive(&mut socket).await, json!({ "jsonrpc": "2.0", "id": 2, "result": true }));
+        send(&mut socket, 3, "eth_unsubscribe", json!([subscription])).await;
+        assert_eq!(receive(&mut socket).await, json!({ "jsonrpc": "2.0", "id": 3, "result": false }));
+    }
+
+    #[tokio::test]
+    async fn subscriptions_stop_when_the_socket_closes() {
+        let upstream = block_node().await;
+        let proxy = TestProxy::start(&upstream, &["--ws", "--ws-poll-interval", "1"]).await;
+        let mut socket = connect(&proxy).await;
+
+        send(&mut socket, 1, "eth_subscribe", json!(["newHeads"])).await;
+        receive(&mut socket).await;
+        assert_eq!(receive(&mut socket).await["method"], "eth_subscription");
+        socket.close(None).await.unwrap();
+
+        // Give the server a moment to notice, then make sure nothing polls any more
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let polls = upstream.hits().len();
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        assert_eq!(upstream.hits().len(), polls);
+    }
+}